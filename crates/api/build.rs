@@ -0,0 +1,17 @@
+use std::{env, process::Command};
+
+// record the version of the compiler for the `web3_clientVersion`
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .and_then(|s| s.split_whitespace().nth(1).map(|v| v.to_owned()))
+        .unwrap_or_else(|| "unknown".to_owned());
+
+    println!("cargo:rustc-env=RT_EVM_RUSTC_VERSION={}", version);
+    println!("cargo:rerun-if-env-changed=RUSTC");
+}
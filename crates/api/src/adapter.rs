@@ -1,23 +1,38 @@
-use rt_evm_executor::{RTEvmExecutor, RTEvmExecutorAdapter};
+use parking_lot::Mutex;
+use rt_evm_executor::{RTEvmExecutor, RTEvmExecutorAdapter, StateDiffRecorder};
 use rt_evm_mempool::Mempool;
 use rt_evm_model::{
     async_trait,
     codec::ProtocolCodec,
-    traits::{APIAdapter, BlockStorage, Executor, ExecutorAdapter, TxStorage},
+    traits::{APIAdapter, Backend, BlockStorage, Executor, ExecutorAdapter, TxStorage},
     types::{
-        Account, BigEndianHash, Block, BlockNumber, ExecutorContext, Hash, Header,
-        Proposal, Receipt, SignedTransaction, TxResp, H160, MAX_BLOCK_GAS_LIMIT,
-        NIL_HASH, U256, WORLD_STATE_META_KEY,
+        Account, Basic, BigEndianHash, Block, BlockEvent, BlockNumber, CallRequest,
+        CheckPointConfig, ExecutorContext, Hardfork, Hash, Header, Proposal, Receipt,
+        SignedTransaction, StateDiff, StateOverride, TxResp, H160, H256,
+        MAX_BLOCK_GAS_LIMIT, NIL_HASH, U256, WORLD_STATE_META_KEY,
     },
 };
-use rt_evm_storage::{MptStore, Storage};
+use rt_evm_storage::{trie_restore, MptStore, Storage};
 use ruc::*;
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::broadcast::{error::TryRecvError, Receiver};
+
+const HEADER_CACHE_SIZE: usize = 64;
 
 pub struct DefaultAPIAdapter {
     mempool: Arc<Mempool>,
     trie_db: Arc<MptStore>,
     storage: Arc<Storage>,
+
+    // the rules and the activation heights followed by the chain,
+    // the replayed txs must be executed exactly as they were in the block
+    hardfork: Hardfork,
+    checkpoint: CheckPointConfig,
+
+    // the headers of the recently queried heights,
+    // a burst of state queries at the same height fetches the block only once,
+    // the heights replaced by a reorg are dropped on the block events
+    header_cache: Mutex<HeaderCache>,
 }
 
 impl DefaultAPIAdapter {
@@ -25,32 +40,158 @@ impl DefaultAPIAdapter {
         mempool: Arc<Mempool>,
         trie_db: Arc<MptStore>,
         storage: Arc<Storage>,
+        hardfork: Hardfork,
+        checkpoint: CheckPointConfig,
     ) -> Self {
+        let header_cache = HeaderCache::new(storage.subscribe_block_events());
         Self {
             mempool,
             trie_db,
             storage,
+            hardfork,
+            checkpoint,
+            header_cache: Mutex::new(header_cache),
         }
     }
 
+    // the executor following the rules and the activation heights of the chain
+    fn executor(&self) -> RTEvmExecutor {
+        RTEvmExecutor::new(self.hardfork).with_checkpoint(self.checkpoint)
+    }
+
     pub async fn evm_backend(
         &self,
         number: Option<BlockNumber>,
     ) -> Result<RTEvmExecutorAdapter> {
-        let block = self
-            .get_block_by_number(number)
-            .await?
-            .c(d!("Cannot get {:?} block", number))?;
-        let state_root = block.header.state_root;
-        let proposal = Proposal::from(&block);
+        let header = self.state_header(number).c(d!())?;
+        let proposal = Proposal::from(&header);
 
         RTEvmExecutorAdapter::from_root(
-            state_root,
+            header.state_root,
             &self.trie_db,
             &self.storage,
             ExecutorContext::from(&proposal),
         )
     }
+
+    // the state right before the tx at `tx_index` of the block `number`,
+    // in a shadow backend
+    fn replay_to_tx_index(
+        &self,
+        number: BlockNumber,
+        tx_index: usize,
+    ) -> Result<RTEvmExecutorAdapter> {
+        if number == 0 {
+            return Err(eg!("The genesis block has no txs"));
+        }
+
+        let block = self
+            .storage
+            .get_block(number)
+            .c(d!())?
+            .c(d!("Cannot get {:?} block", number))?;
+        if tx_index > block.tx_hashes.len() {
+            return Err(eg!(
+                "The block {} has only {} txs",
+                number,
+                block.tx_hashes.len()
+            ));
+        }
+
+        // also bounded by the batch limit of the storage
+        let txs = self
+            .storage
+            .get_txs(number, &block.tx_hashes[..tx_index])
+            .c(d!())?
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .c(d!("BUG: missing txs"))?;
+
+        let parent = self.state_header(Some(number - 1)).c(d!())?;
+
+        // the replayed changes live in a shadow backend, nothing is persisted as a block
+        let mut backend = RTEvmExecutorAdapter::from_root(
+            parent.state_root,
+            &self.trie_db,
+            &self.storage,
+            ExecutorContext::from(&Proposal::from(&block.header)),
+        )?
+        .simulated();
+
+        self.executor().exec(&mut backend, &txs);
+
+        Ok(backend)
+    }
+
+    // `None` means the latest height, it is resolved on every call,
+    // so a new block makes the queries at the head miss the cache at once;
+    // the latest height is published after its header and state are persisted,
+    // see `BlockMgmt::produce_block`, so the returned root is always restorable
+    fn state_header(&self, number: Option<BlockNumber>) -> Result<Header> {
+        let number = match number {
+            Some(n) => n,
+            None => self.storage.get_latest_block_number().c(d!())?,
+        };
+
+        if let Some(h) = self.header_cache.lock().get(number) {
+            return Ok(h);
+        }
+
+        let header = self
+            .storage
+            .get_block_header(number)
+            .c(d!())?
+            .c(d!("Cannot get {:?} block", number))?;
+        self.header_cache.lock().insert(header.clone());
+
+        Ok(header)
+    }
+}
+
+// a reorg replaces the blocks from its height on, so every block event
+// drops the cached heights at and above the one it carries,
+// the lowest heights are evicted first since the queries focus on the head
+struct HeaderCache {
+    headers: BTreeMap<BlockNumber, Header>,
+    events: Receiver<BlockEvent>,
+}
+
+impl HeaderCache {
+    fn new(events: Receiver<BlockEvent>) -> Self {
+        Self {
+            headers: BTreeMap::new(),
+            events,
+        }
+    }
+
+    // the events are published after the blocks are persisted,
+    // so an entry fetched before a reorg is dropped on the next lookup
+    fn sync(&mut self) {
+        loop {
+            match self.events.try_recv() {
+                Ok(BlockEvent::NewBlock(h))
+                | Ok(BlockEvent::Reorg { header: h, .. }) => {
+                    self.headers.split_off(&h.number);
+                }
+                // some events have been missed, nothing can be trusted
+                Err(TryRecvError::Lagged(_)) => self.headers.clear(),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => return,
+            }
+        }
+    }
+
+    fn get(&mut self, number: BlockNumber) -> Option<Header> {
+        self.sync();
+        self.headers.get(&number).cloned()
+    }
+
+    fn insert(&mut self, header: Header) {
+        self.sync();
+        self.headers.insert(header.number, header);
+        while self.headers.len() > HEADER_CACHE_SIZE {
+            self.headers.pop_first();
+        }
+    }
 }
 
 #[async_trait]
@@ -92,10 +233,31 @@ impl APIAdapter for DefaultAPIAdapter {
         self.storage.get_receipts(block_number, tx_hashes)
     }
 
+    async fn get_receipts_by_block_number(
+        &self,
+        block_number: u64,
+    ) -> Result<Option<Vec<Receipt>>> {
+        self.storage.get_block_receipts(block_number)
+    }
+
     async fn get_tx_by_hash(&self, tx_hash: Hash) -> Result<Option<SignedTransaction>> {
         self.storage.get_tx_by_hash(&tx_hash)
     }
 
+    async fn get_tx_with_block(
+        &self,
+        tx_hash: Hash,
+    ) -> Result<Option<(BlockNumber, SignedTransaction)>> {
+        self.storage.get_tx_with_block(&tx_hash)
+    }
+
+    async fn get_tx_position(
+        &self,
+        tx_hash: Hash,
+    ) -> Result<Option<(BlockNumber, u32)>> {
+        self.storage.get_tx_position(&tx_hash)
+    }
+
     async fn get_txs_by_hashes(
         &self,
         block_number: u64,
@@ -126,7 +288,33 @@ impl APIAdapter for DefaultAPIAdapter {
     }
 
     async fn get_pending_tx_count(&self, address: H160) -> Result<U256> {
-        Ok(self.mempool.tx_pending_cnt(Some(address)).into())
+        let nonce = self.get_account(address, None).await.c(d!())?.nonce;
+        Ok(self
+            .mempool
+            .tx_pending_contiguous_cnt(address, nonce)
+            .into())
+    }
+
+    async fn get_next_nonce(&self, address: H160) -> Result<U256> {
+        let nonce = self.get_account(address, None).await.c(d!())?.nonce;
+        Ok(nonce + self.mempool.tx_pending_contiguous_cnt(address, nonce))
+    }
+
+    async fn is_tx_pooled(&self, tx_hash: Hash) -> Result<bool> {
+        Ok(self.mempool.tx_is_pooled(&tx_hash))
+    }
+
+    async fn is_tx_evicted(&self, tx_hash: Hash) -> Result<bool> {
+        Ok(self.mempool.was_recently_evicted(&tx_hash).is_some())
+    }
+
+    async fn get_txpool_status(&self) -> Result<(U256, U256)> {
+        let (pending, queued) = self.mempool.tx_pool_status();
+        Ok((pending.into(), queued.into()))
+    }
+
+    async fn clear_txpool(&self) -> Result<U256> {
+        Ok(self.mempool.clear().into())
     }
 
     async fn evm_call(
@@ -149,28 +337,102 @@ impl APIAdapter for DefaultAPIAdapter {
             &self.trie_db,
             &self.storage,
             exec_ctx,
-        )?;
+        )?
+        .simulated();
         let gas_limit = gas_limit
             .map(|gas| gas.as_u64())
             .unwrap_or(MAX_BLOCK_GAS_LIMIT);
 
-        Ok(RTEvmExecutor::default().call(&backend, gas_limit, from, to, value, data))
+        Ok(self
+            .executor()
+            .call(&backend, gas_limit, from, to, value, data))
+    }
+
+    async fn evm_call_at_tx_index(
+        &self,
+        from: Option<H160>,
+        to: Option<H160>,
+        gas_price: Option<U256>,
+        gas_limit: Option<U256>,
+        value: U256,
+        data: Vec<u8>,
+        number: BlockNumber,
+        tx_index: usize,
+    ) -> Result<TxResp> {
+        let mut backend = self.replay_to_tx_index(number, tx_index).c(d!())?;
+
+        backend.set_origin(from.unwrap_or_default());
+        backend.set_gas_price(gas_price.unwrap_or_else(U256::one));
+        let gas_limit = gas_limit
+            .map(|gas| gas.as_u64())
+            .unwrap_or(MAX_BLOCK_GAS_LIMIT);
+
+        Ok(self
+            .executor()
+            .call(&backend, gas_limit, from, to, value, data))
+    }
+
+    async fn evm_call_many(
+        &self,
+        calls: Vec<CallRequest>,
+        state_overrides: BTreeMap<H160, StateOverride>,
+        number: Option<BlockNumber>,
+    ) -> Result<Vec<TxResp>> {
+        let mut backend = self.evm_backend(number).await.c(d!())?.simulated();
+
+        for (address, o) in state_overrides.into_iter() {
+            let basic = backend.basic(address);
+            let basic = Basic {
+                balance: o.balance.unwrap_or(basic.balance),
+                nonce: o.nonce.unwrap_or(basic.nonce),
+            };
+            backend.apply(address, basic, o.code, o.state_diff, false);
+        }
+        backend.commit();
+
+        let executor = self.executor();
+        let resps = calls
+            .into_iter()
+            .map(|c| {
+                backend.set_origin(c.from.unwrap_or_default());
+                backend.set_gas_price(c.gas_price.unwrap_or_else(U256::one));
+                let gas_limit = c
+                    .gas_limit
+                    .map(|gas| gas.as_u64())
+                    .unwrap_or(MAX_BLOCK_GAS_LIMIT);
+                executor.call_mut(&mut backend, gas_limit, c.from, c.to, c.value, c.data)
+            })
+            .collect();
+
+        Ok(resps)
     }
 
     async fn get_code_by_hash(&self, hash: &Hash) -> Result<Option<Vec<u8>>> {
         self.storage.get_code_by_hash(hash)
     }
 
+    async fn get_code_size_by_hash(&self, hash: &Hash) -> Result<Option<u64>> {
+        self.storage.get_code_size_by_hash(hash)
+    }
+
     async fn get_storage_at(
         &self,
         address: H160,
         position: U256,
         state_root: Hash,
     ) -> Result<Vec<u8>> {
-        let state_trie_tree = self
-            .trie_db
-            .trie_restore(&WORLD_STATE_META_KEY, state_root.into())
-            .c(d!())?;
+        self.get_storage_at_raw(address, BigEndianHash::from_uint(&position), state_root)
+            .await
+    }
+
+    async fn get_storage_at_raw(
+        &self,
+        address: H160,
+        key: H256,
+        state_root: Hash,
+    ) -> Result<Vec<u8>> {
+        let state_trie_tree =
+            trie_restore(&self.trie_db, &WORLD_STATE_META_KEY, state_root).c(d!())?;
 
         let raw_account = state_trie_tree
             .get(address.as_bytes())
@@ -179,15 +441,180 @@ impl APIAdapter for DefaultAPIAdapter {
 
         let account = Account::decode(raw_account).unwrap();
 
-        let storage_trie_tree = self
-            .trie_db
-            .trie_restore(address.as_bytes(), account.storage_root.into())
-            .c(d!())?;
+        let storage_trie_tree =
+            trie_restore(&self.trie_db, address.as_bytes(), account.storage_root)
+                .c(d!())?;
 
-        let hash: Hash = BigEndianHash::from_uint(&position);
         storage_trie_tree
-            .get(hash.as_bytes())
+            .get(key.as_bytes())
             .c(d!("Can't find this position"))?
             .c(d!("Can't find this position"))
     }
+
+    async fn get_storage_at_batch(
+        &self,
+        address: H160,
+        positions: &[U256],
+        state_root: Hash,
+    ) -> Result<Vec<Option<Vec<u8>>>> {
+        let state_trie_tree =
+            trie_restore(&self.trie_db, &WORLD_STATE_META_KEY, state_root).c(d!())?;
+
+        let raw_account = match state_trie_tree.get(address.as_bytes()).c(d!())? {
+            Some(raw) => raw,
+            None => return Ok(vec![None; positions.len()]),
+        };
+
+        let account = Account::decode(raw_account).c(d!())?;
+
+        let storage_trie_tree =
+            trie_restore(&self.trie_db, address.as_bytes(), account.storage_root)
+                .c(d!())?;
+
+        positions
+            .iter()
+            .map(|position| {
+                let hash: Hash = BigEndianHash::from_uint(position);
+                storage_trie_tree.get(hash.as_bytes()).c(d!())
+            })
+            .collect()
+    }
+
+    async fn get_account_range(
+        &self,
+        number: Option<BlockNumber>,
+        start: Hash,
+        limit: usize,
+    ) -> Result<(Vec<(Hash, H160, Account)>, Option<Hash>)> {
+        let state_root = self.state_header(number).c(d!())?.state_root;
+
+        let state =
+            trie_restore(&self.trie_db, &WORLD_STATE_META_KEY, state_root).c(d!())?;
+
+        self.storage.get_account_range(&state, start, limit).c(d!())
+    }
+
+    async fn get_txs_by_address(
+        &self,
+        address: H160,
+        offset: u64,
+        limit: usize,
+    ) -> Result<(u64, Vec<Hash>)> {
+        self.storage
+            .get_txs_by_address(address, offset, limit)
+            .c(d!())
+    }
+
+    async fn get_tx_state_diff(&self, tx_hash: Hash) -> Result<Option<StateDiff>> {
+        let receipt = match self.storage.get_receipt_by_hash(&tx_hash).c(d!())? {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        let (number, tx_index) = (receipt.block_number, receipt.tx_index as usize);
+
+        let tx = self
+            .storage
+            .get_tx_by_hash(&tx_hash)
+            .c(d!())?
+            .c(d!("BUG: missing txs"))?;
+        let mut backend = self.replay_to_tx_index(number, tx_index).c(d!())?;
+
+        let mut recorder = StateDiffRecorder::new(&mut backend);
+        self.executor().exec(&mut recorder, &[tx]);
+
+        Ok(Some(recorder.into_diff()))
+    }
+
+    fn checkpoint_config(&self) -> CheckPointConfig {
+        self.checkpoint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rt_evm_model::traits::BlockStorage;
+    use tokio::sync::broadcast;
+
+    fn header(number: BlockNumber) -> Header {
+        Header {
+            number,
+            state_root: Hash::from_low_u64_be(number),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_header_cache() {
+        let (_tx, rx) = broadcast::channel(8);
+        let mut cache = HeaderCache::new(rx);
+        cache.insert(header(1));
+
+        // repeated queries at the same height
+        for _ in 0..3 {
+            assert_eq!(Some(header(1)), cache.get(1));
+        }
+
+        // a new head is a miss until it has been fetched once
+        assert!(cache.get(2).is_none());
+        cache.insert(header(2));
+        assert_eq!(Hash::from_low_u64_be(2), cache.get(2).unwrap().state_root);
+
+        // the lowest heights are evicted first
+        (3..=(HEADER_CACHE_SIZE as u64 + 1)).for_each(|n| cache.insert(header(n)));
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(2).is_some());
+        assert_eq!(HEADER_CACHE_SIZE, cache.headers.len());
+    }
+
+    #[test]
+    fn test_header_cache_reorg() {
+        let (tx, rx) = broadcast::channel(8);
+        let mut cache = HeaderCache::new(rx);
+        (1..=3).for_each(|n| cache.insert(header(n)));
+
+        // the block 2 is replaced, the heights from 2 on are dropped
+        let mut h2 = header(2);
+        h2.timestamp = 1;
+        tx.send(BlockEvent::new(Some(header(2).hash()), h2))
+            .unwrap();
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(3).is_none());
+
+        // the missed events drop everything
+        cache.insert(header(2));
+        (0..9).for_each(|n| {
+            tx.send(BlockEvent::NewBlock(header(100 + n))).unwrap();
+        });
+        assert!(cache.get(1).is_none());
+        assert!(cache.headers.is_empty());
+    }
+
+    #[test]
+    fn test_state_header_after_reorg() {
+        let storage = Arc::new(Storage::default());
+        let trie_db = Arc::new(MptStore::new());
+        let mempool =
+            Mempool::new(1000, 60, None, Arc::clone(&trie_db), Arc::clone(&storage));
+        let adapter = DefaultAPIAdapter::new(
+            mempool,
+            trie_db,
+            Arc::clone(&storage),
+            Default::default(),
+            Default::default(),
+        );
+
+        let b1 = Block::mock(9527, 1, Hash::from_low_u64_be(1), 1);
+        storage.set_block(b1.clone()).unwrap();
+        assert_eq!(b1.header, adapter.state_header(Some(1)).unwrap());
+        assert_eq!(b1.header, adapter.state_header(None).unwrap());
+
+        // another block at the same height must not be served from the cache
+        let b1_new = Block::mock(9527, 1, Hash::from_low_u64_be(2), 2);
+        storage.set_block(b1_new.clone()).unwrap();
+        assert_ne!(b1.hash(), b1_new.hash());
+        assert_eq!(b1_new.header, adapter.state_header(Some(1)).unwrap());
+        assert_eq!(b1_new.header, adapter.state_header(None).unwrap());
+    }
 }
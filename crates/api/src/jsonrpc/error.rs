@@ -8,15 +8,78 @@ use rt_evm_executor::decode_revert_msg;
 
 const EXEC_ERROR: i32 = -32015;
 
+/// The error codes of the web3 APIs,
+/// stable across releases, the clients should match on them instead of the messages.
+///
+/// SEE ALSO: EIP-1474
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    InvalidParams,
+    ResourceNotFound,
+    StateUnavailable,
+    ExecutionReverted,
+    LimitExceeded,
+    Internal,
+}
+
+impl ErrorCode {
+    pub const fn code(self) -> i32 {
+        match self {
+            Self::InvalidParams => -32602,
+            Self::ResourceNotFound => -32001,
+            Self::StateUnavailable => -32002,
+            Self::ExecutionReverted => EXEC_ERROR,
+            Self::LimitExceeded => -32005,
+            Self::Internal => -32603,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum RpcError {
     VM(TxResp),
+
+    // malformed or unacceptable arguments, including the rejected transactions
+    InvalidParams(String),
+
+    // the requested block or receipt does not exist
+    NotFound(String),
+
+    // the storage or the world state can not be read
+    StateUnavailable(String),
+
+    // the request is too large to be served
+    LimitExceeded(String),
+
+    // a failure of the node itself, e.g. a stopped background task
+    Internal(String),
+}
+
+impl RpcError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::VM(_) => ErrorCode::ExecutionReverted,
+            Self::InvalidParams(_) => ErrorCode::InvalidParams,
+            Self::NotFound(_) => ErrorCode::ResourceNotFound,
+            Self::StateUnavailable(_) => ErrorCode::StateUnavailable,
+            Self::LimitExceeded(_) => ErrorCode::LimitExceeded,
+            Self::Internal(_) => ErrorCode::Internal,
+        }
+    }
 }
 
 impl From<RpcError> for Error {
     fn from(err: RpcError) -> Self {
+        let code = err.code().code();
         match err {
             RpcError::VM(resp) => vm_err(resp),
+            RpcError::InvalidParams(msg)
+            | RpcError::NotFound(msg)
+            | RpcError::StateUnavailable(msg)
+            | RpcError::LimitExceeded(msg)
+            | RpcError::Internal(msg) => {
+                into_rpc_err(ErrorObject::owned(code, msg, None::<()>))
+            }
         }
     }
 }
@@ -1,9 +1,9 @@
 use crate::jsonrpc::{
+    error::RpcError,
     impls::from_receipt_to_web3_log,
     web3_types::{BlockId, FilterChanges, RawLoggerFilter, Web3Log},
     RpcResult, Web3FilterServer,
 };
-use jsonrpsee::core::Error;
 use rt_evm_model::{
     async_trait,
     traits::APIAdapter,
@@ -66,13 +66,16 @@ pub struct RTEvmWeb3RpcFilter {
 impl Web3FilterServer for RTEvmWeb3RpcFilter {
     async fn new_filter(&self, filter: RawLoggerFilter) -> RpcResult<U256> {
         if let Some(BlockId::Pending) = filter.from_block {
-            return Err(Error::Custom(
+            return Err(RpcError::InvalidParams(
                 "Invalid from_block and to_block union".to_string(),
-            ));
+            )
+            .into());
         }
         match filter.to_block {
             Some(BlockId::Earliest) | Some(BlockId::Num(0)) => {
-                return Err(Error::Custom("Invalid to_block".to_string()));
+                return Err(
+                    RpcError::InvalidParams("Invalid to_block".to_string()).into()
+                );
             }
             _ => (),
         }
@@ -81,7 +84,7 @@ impl Web3FilterServer for RTEvmWeb3RpcFilter {
         self.sender
             .send(Command::NewLogs((filter.into(), tx)))
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::Internal(e.to_string()))?;
 
         Ok(rx.await.unwrap())
     }
@@ -92,7 +95,7 @@ impl Web3FilterServer for RTEvmWeb3RpcFilter {
         self.sender
             .send(Command::NewBlocks(tx))
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::Internal(e.to_string()))?;
 
         Ok(rx.await.unwrap())
     }
@@ -103,7 +106,7 @@ impl Web3FilterServer for RTEvmWeb3RpcFilter {
         self.sender
             .send(Command::FilterRequest((id, tx)))
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::Internal(e.to_string()))?;
 
         rx.await.unwrap()
     }
@@ -114,7 +117,7 @@ impl Web3FilterServer for RTEvmWeb3RpcFilter {
         self.sender
             .send(Command::FilterRequest((id, tx)))
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::Internal(e.to_string()))?;
 
         rx.await.unwrap()
     }
@@ -125,7 +128,7 @@ impl Web3FilterServer for RTEvmWeb3RpcFilter {
         self.sender
             .send(Command::Uninstall((id, tx)))
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::Internal(e.to_string()))?;
 
         Ok(rx.await.unwrap())
     }
@@ -256,10 +259,11 @@ where
             sender.send(res).unwrap()
         } else {
             sender
-                .send(Err(Error::Custom(format!(
+                .send(Err(RpcError::NotFound(format!(
                     "Can't find this filter id: {}",
                     id
-                ))))
+                ))
+                .into()))
                 .unwrap()
         }
     }
@@ -309,7 +313,7 @@ where
             .adapter
             .get_block_by_number(None)
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
             .unwrap();
 
         let latest_number = latest_block.header.number;
@@ -343,13 +347,16 @@ where
             return Ok(Vec::new());
         }
 
-        let extend_logs = |logs: &mut Vec<Web3Log>, receipts: Vec<Option<Receipt>>| {
+        let extend_logs = |logs: &mut Vec<Web3Log>,
+                           receipts: Vec<Option<Receipt>>,
+                           block_timestamp: u64| {
             for (index, receipt) in receipts.into_iter().flatten().enumerate() {
                 from_receipt_to_web3_log(
                     index,
                     topics,
                     filter.address.as_ref().unwrap_or(&Vec::new()),
                     &receipt,
+                    block_timestamp,
                     logs,
                 )
             }
@@ -364,15 +371,15 @@ where
                     .adapter
                     .get_block_by_number(Some(n))
                     .await
-                    .map_err(|e| Error::Custom(e.to_string()))?
+                    .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
                     .unwrap();
                 let receipts = self
                     .adapter
                     .get_receipts_by_hashes(block.header.number, &block.tx_hashes)
                     .await
-                    .map_err(|e| Error::Custom(e.to_string()))?;
+                    .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
 
-                extend_logs(&mut all_logs, receipts);
+                extend_logs(&mut all_logs, receipts, block.header.timestamp);
             }
         }
 
@@ -384,9 +391,9 @@ where
                     &latest_block.tx_hashes,
                 )
                 .await
-                .map_err(|e| Error::Custom(e.to_string()))?;
+                .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
 
-            extend_logs(&mut all_logs, receipts);
+            extend_logs(&mut all_logs, receipts, latest_block.header.timestamp);
         }
 
         if let Some(BlockId::Num(ref mut n)) = filter.from_block {
@@ -396,3 +403,48 @@ where
         Ok(all_logs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::error::ErrorCode;
+    use jsonrpsee::{core::Error, types::error::CallError};
+    use std::fmt::Debug;
+
+    fn err_code<T: Debug>(ret: RpcResult<T>) -> i32 {
+        match ret.unwrap_err() {
+            Error::Call(CallError::Custom(e)) => e.code(),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    fn raw_filter(json: serde_json::Value) -> RawLoggerFilter {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_filter_error_codes() {
+        let invalid_params = ErrorCode::InvalidParams.code();
+        let internal = ErrorCode::Internal.code();
+
+        let (tx, rx) = channel(1);
+        let filter = RTEvmWeb3RpcFilter { sender: tx };
+
+        let pending = raw_filter(serde_json::json!({ "fromBlock": "pending" }));
+        assert_eq!(invalid_params, err_code(filter.new_filter(pending).await));
+        let earliest = raw_filter(serde_json::json!({ "toBlock": "earliest" }));
+        assert_eq!(invalid_params, err_code(filter.new_filter(earliest).await));
+
+        // the filter hub has stopped
+        drop(rx);
+        assert_eq!(internal, err_code(filter.block_filter().await));
+        assert_eq!(
+            internal,
+            err_code(filter.get_filter_changes(U256::one()).await)
+        );
+        assert_eq!(
+            internal,
+            err_code(filter.uninstall_filter(U256::one()).await)
+        );
+    }
+}
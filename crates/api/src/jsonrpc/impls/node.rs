@@ -1,10 +1,15 @@
 use crate::{
-    jsonrpc::{web3_types::Web3SyncStatus, RTEvmNodeRpcServer, RpcResult},
+    jsonrpc::{
+        error::RpcError, web3_types::Web3SyncStatus, RTEvmNodeRpcServer, RpcResult,
+    },
     SYNC_STATUS,
 };
-use jsonrpsee::core::Error;
 use rt_evm_model::lazy::CHAIN_ID;
-use rt_evm_model::types::{Hash, Hasher, Hex, H160, H256, U256};
+use rt_evm_model::types::{Hash, Hasher, Hex, H160, H256, U256, U64};
+use std::env::consts;
+
+// the version of the Ethereum wire protocol, eth/66
+const ETH_PROTOCOL_VERSION: u64 = 0x42;
 
 pub struct NodeRpcImpl {
     version: String,
@@ -13,11 +18,30 @@ pub struct NodeRpcImpl {
 impl NodeRpcImpl {
     pub fn new(version: &str) -> Self {
         NodeRpcImpl {
-            version: version.to_string(),
+            version: format_client_version(version),
         }
     }
 }
 
+// `<name>/v<version>/<os>-<arch>/rustc<version>`,
+// the convention followed by the mainstream Ethereum clients
+fn format_client_version(name: &str) -> String {
+    let name = if name.is_empty() {
+        env!("CARGO_PKG_NAME")
+    } else {
+        name
+    };
+
+    format!(
+        "{}/v{}/{}-{}/rustc{}",
+        name,
+        env!("CARGO_PKG_VERSION"),
+        consts::OS,
+        consts::ARCH,
+        env!("RT_EVM_RUSTC_VERSION")
+    )
+}
+
 impl RTEvmNodeRpcServer for NodeRpcImpl {
     fn chain_id(&self) -> RpcResult<U256> {
         Ok((**CHAIN_ID.load()).into())
@@ -31,6 +55,10 @@ impl RTEvmNodeRpcServer for NodeRpcImpl {
         Ok(self.version.clone())
     }
 
+    fn protocol_version(&self) -> RpcResult<U64> {
+        Ok(ETH_PROTOCOL_VERSION.into())
+    }
+
     fn listening(&self) -> RpcResult<bool> {
         Ok(true)
     }
@@ -67,8 +95,35 @@ impl RTEvmNodeRpcServer for NodeRpcImpl {
     }
 
     fn sha3(&self, data: Hex) -> RpcResult<Hash> {
-        let decode_data =
-            Hex::decode(data.as_string()).map_err(|e| Error::Custom(e.to_string()))?;
+        let decode_data = Hex::decode(data.as_string())
+            .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
         Ok(Hasher::digest(decode_data))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_version_format() {
+        let version = format_client_version("rt-evm-demo");
+        let parts = version.split('/').collect::<Vec<_>>();
+
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], "rt-evm-demo");
+        assert!(parts[1]
+            .strip_prefix('v')
+            .map_or(false, |v| v.split('.').all(|n| n.parse::<u64>().is_ok())));
+        assert_eq!(parts[2], format!("{}-{}", consts::OS, consts::ARCH));
+        assert!(parts[3].starts_with("rustc"));
+
+        assert!(format_client_version("").starts_with("rt-evm-api/v"));
+    }
+
+    #[test]
+    fn test_protocol_version_json() {
+        let v = NodeRpcImpl::new("").protocol_version().unwrap();
+        assert_eq!(serde_json::to_string(&v).unwrap(), r#""0x42""#);
+    }
+}
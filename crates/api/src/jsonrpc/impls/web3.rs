@@ -1,34 +1,41 @@
 use crate::jsonrpc::{
     error::RpcError,
     web3_types::{
-        BlockId, FatTransactionOrHash, Web3Block, Web3CallRequest, Web3FeeHistory,
-        Web3Filter, Web3Log, Web3Receipt, Web3Transaction,
+        BlockId, FatTransactionOrHash, Web3AccountRange, Web3Block, Web3BlockRoots,
+        Web3CallRequest, Web3CallResult, Web3CallWithGas, Web3CheckpointConfig,
+        Web3FeeHistory, Web3Filter, Web3Log, Web3Receipt, Web3StateDiff,
+        Web3StateOverride, Web3Transaction, Web3TxHistory, Web3TxPoolStatus,
+        Web3TxStatus,
     },
-    RTEvmWeb3RpcServer, RpcResult,
+    RTEvmWeb3RpcServer, RpcConfig, RpcResult,
 };
-use jsonrpsee::core::Error;
 use rt_evm_model::{
     async_trait,
     codec::ProtocolCodec,
-    lazy::PROTOCOL_VERSION,
+    lazy::{base_fee_per_gas, is_fee_free, PROTOCOL_VERSION},
     traits::APIAdapter,
     types::{
-        Block, BlockNumber, Bytes, Hash, Header, Hex, Receipt, SignedTransaction,
-        TxResp, UnverifiedTransaction, H160, H256, H64, MAX_BLOCK_GAS_LIMIT, U256,
+        Block, BlockNumber, Bytes, CallRequest, ExitError, ExitReason, Hash, Header,
+        Hex, Receipt, SignedTransaction, TxResp, UnverifiedTransaction, H160, H256, H64,
+        MAX_BLOCK_GAS_LIMIT, U256,
     },
 };
-use ruc::*;
-use std::sync::Arc;
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::Arc};
 
-const MAX_LOG_NUM: usize = 10000;
+const MAX_STORAGE_SLOT_NUM: usize = 10000;
+const MAX_REPLAY_TX_NUM: usize = 1000;
+const MAX_CALL_BUNDLE_SIZE: usize = 100;
+const MAX_FEE_HISTORY_BLOCKS: u64 = 1024;
 
 pub struct Web3RpcImpl<Adapter> {
     adapter: Arc<Adapter>,
+    config: RpcConfig,
 }
 
 impl<Adapter: APIAdapter> Web3RpcImpl<Adapter> {
-    pub fn new(adapter: Arc<Adapter>) -> Self {
-        Self { adapter }
+    pub fn new(adapter: Arc<Adapter>, config: RpcConfig) -> Self {
+        Self { adapter, config }
     }
 
     async fn call_evm(
@@ -36,16 +43,17 @@ impl<Adapter: APIAdapter> Web3RpcImpl<Adapter> {
         req: Web3CallRequest,
         data: Bytes,
         number: Option<u64>,
-    ) -> Result<TxResp> {
-        if req.from.is_none() && req.to.is_none() {
-            return Err(eg!("from and to are both None"));
-        }
+    ) -> RpcResult<TxResp> {
+        check_call_from(&req, self.config.call_from_required)?;
 
         let header = self
             .adapter
             .get_block_header_by_number(number)
-            .await?
-            .c(d!("Cannot get {:?} header", number))?;
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+            .ok_or_else(|| {
+                RpcError::NotFound(format!("Cannot get {:?} header", number))
+            })?;
 
         let mock_header = mock_header_by_call_req(header, &req);
 
@@ -61,6 +69,7 @@ impl<Adapter: APIAdapter> Web3RpcImpl<Adapter> {
                 mock_header.into(),
             )
             .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()).into())
     }
 }
 
@@ -68,16 +77,16 @@ impl<Adapter: APIAdapter> Web3RpcImpl<Adapter> {
 impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter> {
     async fn send_raw_tx(&self, tx: Hex) -> RpcResult<H256> {
         let utx = UnverifiedTransaction::decode(&tx.as_bytes())
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
 
         let stx = SignedTransaction::try_from(utx)
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
         let hash = stx.transaction.hash;
 
         self.adapter
             .insert_signed_tx(stx)
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
 
         Ok(hash)
     }
@@ -85,23 +94,27 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
     async fn get_tx_by_hash(&self, hash: H256) -> RpcResult<Option<Web3Transaction>> {
         let res = self
             .adapter
-            .get_tx_by_hash(hash)
+            .get_tx_with_block(hash)
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
 
-        if let Some(stx) = res {
+        if let Some((number, stx)) = res {
             if let Some(receipt) = self
                 .adapter
                 .get_receipt_by_tx_hash(hash)
                 .await
-                .map_err(|e| Error::Custom(e.to_string()))?
+                .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
             {
-                Ok(Some((stx, receipt).into()))
+                // the index in the block is taken from the receipt
+                Ok(Some(
+                    Web3Transaction::from((stx, receipt)).add_block_number(number),
+                ))
             } else {
-                Err(Error::Custom(format!(
+                Err(RpcError::NotFound(format!(
                     "can not get receipt by hash {:?}",
                     hash
-                )))
+                ))
+                .into())
             }
         } else {
             Ok(None)
@@ -117,7 +130,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .adapter
             .get_block_by_number(number.into())
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
 
         match block {
             Some(b) => {
@@ -132,7 +145,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
                             .adapter
                             .get_tx_by_hash(tx.get_hash())
                             .await
-                            .map_err(|e| Error::Custom(e.to_string()))?
+                            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
                             .unwrap();
 
                         txs.push(FatTransactionOrHash::Fat(
@@ -161,7 +174,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .adapter
             .get_block_by_hash(hash)
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
 
         match block {
             Some(b) => {
@@ -176,7 +189,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
                             .adapter
                             .get_tx_by_hash(tx.get_hash())
                             .await
-                            .map_err(|e| Error::Custom(e.to_string()))?
+                            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
                             .unwrap();
 
                         txs.push(FatTransactionOrHash::Fat(
@@ -207,7 +220,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
                     .adapter
                     .get_pending_tx_count(address)
                     .await
-                    .map_err(|e| Error::Custom(e.to_string()))?;
+                    .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
                 Ok(self
                     .adapter
                     .get_account(address, BlockId::Pending.into())
@@ -228,9 +241,12 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
         self.adapter
             .get_block_header_by_number(None)
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
             .map(|h| U256::from(h.number))
-            .ok_or_else(|| Error::Custom("Cannot get latest block header".to_string()))
+            .ok_or_else(|| {
+                RpcError::StateUnavailable("Cannot get latest block header".to_string())
+                    .into()
+            })
     }
 
     async fn get_balance(
@@ -250,12 +266,13 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
         req: Web3CallRequest,
         number: Option<BlockId>,
     ) -> RpcResult<Hex> {
-        if req.gas_price.unwrap_or_default() > U256::from(u64::MAX) {
-            return Err(Error::Custom("The gas price is too large".to_string()));
-        }
+        check_gas_price(&req, self.config.max_call_gas_price)?;
 
         if req.gas.unwrap_or_default() > U256::from(MAX_BLOCK_GAS_LIMIT) {
-            return Err(Error::Custom("The gas limit is too large".to_string()));
+            return Err(RpcError::InvalidParams(
+                "The gas limit is too large".to_string(),
+            )
+            .into());
         }
 
         let data_bytes = req
@@ -265,8 +282,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .unwrap_or_default();
         let resp = self
             .call_evm(req, data_bytes, number.unwrap_or_default().into())
-            .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .await?;
 
         if resp.exit_reason.is_succeed() {
             let call_hex_result = Hex::encode(resp.ret);
@@ -283,14 +299,23 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
     ) -> RpcResult<U256> {
         if let Some(gas_limit) = req.gas.as_ref() {
             if gas_limit == &U256::zero() {
-                return Err(Error::Custom("Failed: Gas cannot be zero".to_string()));
+                return Err(RpcError::InvalidParams(
+                    "Failed: Gas cannot be zero".to_string(),
+                )
+                .into());
             }
         }
 
-        if let Some(price) = req.gas_price.as_ref() {
-            if price >= &U256::from(u64::MAX) {
-                return Err(Error::Custom("Failed: Gas price too high".to_string()));
-            }
+        check_gas_price(&req, self.config.max_call_gas_price)?;
+
+        let cap = self.config.max_tx_gas_limit;
+        let gas_limit = req.gas.unwrap_or_else(|| cap.into());
+        if gas_limit > U256::from(cap) {
+            return Err(RpcError::InvalidParams(format!(
+                "The gas limit exceeds the per-tx cap {}",
+                cap
+            ))
+            .into());
         }
 
         let num = match number {
@@ -302,16 +327,13 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .as_ref()
             .map(|hex| hex.as_bytes())
             .unwrap_or_default();
-        let resp = self
-            .call_evm(req, data_bytes, num)
-            .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
-
-        if resp.exit_reason.is_succeed() {
-            return Ok(resp.gas_used.into());
-        }
+        let req = Web3CallRequest {
+            gas: Some(gas_limit),
+            ..req
+        };
+        let resp = self.call_evm(req, data_bytes, num).await?;
 
-        Err(RpcError::VM(resp).into())
+        estimated_gas(resp, gas_limit.as_u64(), cap)
     }
 
     async fn get_code(&self, address: H160, number: Option<BlockId>) -> RpcResult<Hex> {
@@ -319,18 +341,17 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .adapter
             .get_account(address, number.unwrap_or_default().into())
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
 
         let code_result = self
             .adapter
             .get_code_by_hash(&account.code_hash)
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
-        if let Some(code_bytes) = code_result {
-            Ok(Hex::encode(code_bytes))
-        } else {
-            Ok(Hex::empty())
-        }
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
+        let code = code_result.map(Hex::encode).unwrap_or_else(Hex::empty);
+        check_response_size(&code, self.config.max_response_size)?;
+
+        Ok(code)
     }
 
     async fn get_block_tx_count_by_number(&self, number: BlockId) -> RpcResult<U256> {
@@ -338,7 +359,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .adapter
             .get_block_by_number(number.into())
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
         let count = match block {
             Some(bc) => bc.tx_hashes.len(),
             _ => 0,
@@ -351,21 +372,22 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .adapter
             .get_tx_by_hash(hash)
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
 
         if let Some(stx) = res {
             if let Some(receipt) = self
                 .adapter
                 .get_receipt_by_tx_hash(hash)
                 .await
-                .map_err(|e| Error::Custom(e.to_string()))?
+                .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
             {
                 Ok(Some(Web3Receipt::new(receipt, stx)))
             } else {
-                Err(Error::Custom(format!(
+                Err(RpcError::NotFound(format!(
                     "can not get receipt by hash {:?}",
                     hash
-                )))
+                ))
+                .into())
             }
         } else {
             Ok(None)
@@ -377,6 +399,9 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
     }
 
     async fn gas_price(&self) -> RpcResult<U256> {
+        if is_fee_free() {
+            return Ok(U256::zero());
+        }
         Ok(U256::from(8u64))
     }
 
@@ -404,32 +429,33 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             topics: &[Option<Vec<Option<H256>>>],
             logs: &mut Vec<Web3Log>,
             address: Option<&Vec<H160>>,
-            early_return: &mut bool,
+            limits: &mut LogLimits,
         ) -> RpcResult<()> {
             let extend_logs = |logs: &mut Vec<Web3Log>,
                                receipts: Vec<Option<Receipt>>,
-                               early_return: &mut bool| {
+                               block_number: BlockNumber,
+                               block_timestamp: u64,
+                               limits: &mut LogLimits|
+             -> RpcResult<()> {
                 for (index, receipt) in receipts.into_iter().flatten().enumerate() {
                     from_receipt_to_web3_log(
                         index,
                         topics,
                         address.as_ref().unwrap_or(&&Vec::new()),
                         &receipt,
+                        block_timestamp,
                         logs,
                     );
-
-                    if logs.len() > MAX_LOG_NUM {
-                        *early_return = true;
-                        return;
-                    }
+                    limits.check(receipt.logs.len(), logs.len(), block_number)?;
                 }
+                Ok(())
             };
 
             match position {
                 BlockPosition::Hash(hash) => match adapter
                     .get_block_by_hash(hash)
                     .await
-                    .map_err(|e| Error::Custom(e.to_string()))?
+                    .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
                 {
                     Some(block) => {
                         let receipts = adapter
@@ -438,45 +464,102 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
                                 &block.tx_hashes,
                             )
                             .await
-                            .map_err(|e| Error::Custom(e.to_string()))?;
-                        extend_logs(logs, receipts, early_return);
-                        Ok(())
+                            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
+                        extend_logs(
+                            logs,
+                            receipts,
+                            block.header.number,
+                            block.header.timestamp,
+                            limits,
+                        )
+                    }
+                    None => {
+                        Err(RpcError::NotFound(format!("Invalid block hash {}", hash))
+                            .into())
                     }
-                    None => Err(Error::Custom(format!(
-                        "Invalid block hash
-                    {}",
-                        hash
-                    ))),
                 },
                 BlockPosition::Num(n) => {
-                    let block = adapter
-                        .get_block_by_number(Some(n))
+                    let header = adapter
+                        .get_block_header_by_number(Some(n))
                         .await
-                        .map_err(|e| Error::Custom(e.to_string()))?
-                        .unwrap();
+                        .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+                        .ok_or_else(|| {
+                            RpcError::NotFound(format!("Invalid block number {}", n))
+                        })?;
                     let receipts = adapter
-                        .get_receipts_by_hashes(block.header.number, &block.tx_hashes)
+                        .get_receipts_by_block_number(n)
                         .await
-                        .map_err(|e| Error::Custom(e.to_string()))?;
+                        .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+                        .ok_or_else(|| {
+                            RpcError::NotFound(format!("Invalid block number {}", n))
+                        })?;
 
-                    extend_logs(logs, receipts, early_return);
-                    Ok(())
+                    extend_logs(
+                        logs,
+                        receipts.into_iter().map(Some).collect(),
+                        n,
+                        header.timestamp,
+                        limits,
+                    )
                 }
                 BlockPosition::Block(block) => {
                     let receipts = adapter
                         .get_receipts_by_hashes(block.header.number, &block.tx_hashes)
                         .await
-                        .map_err(|e| Error::Custom(e.to_string()))?;
+                        .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
 
-                    extend_logs(logs, receipts, early_return);
-                    Ok(())
+                    extend_logs(
+                        logs,
+                        receipts,
+                        block.header.number,
+                        block.header.timestamp,
+                        limits,
+                    )
                 }
             }
         }
 
         let address_filter: Option<Vec<H160>> = filter.address.into();
         let mut all_logs = Vec::new();
-        let mut early_return = false;
+        let mut limits =
+            LogLimits::with(self.config.max_logs_returned, self.config.max_logs_scanned);
+
+        // a tx is in exactly one block, no block needs to be scanned
+        if let Some(hash) = filter.transaction_hash {
+            let receipt = self
+                .adapter
+                .get_receipt_by_tx_hash(hash)
+                .await
+                .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+                .ok_or_else(|| {
+                    RpcError::NotFound(format!("Invalid transaction hash {}", hash))
+                })?;
+            let header = self
+                .adapter
+                .get_block_header_by_number(Some(receipt.block_number))
+                .await
+                .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+                .ok_or_else(|| {
+                    RpcError::NotFound(format!(
+                        "Invalid block number {}",
+                        receipt.block_number
+                    ))
+                })?;
+
+            from_receipt_to_web3_log(
+                receipt.tx_index as usize,
+                &topics,
+                address_filter.as_deref().unwrap_or_default(),
+                &receipt,
+                header.timestamp,
+                &mut all_logs,
+            );
+            limits.check(receipt.logs.len(), all_logs.len(), receipt.block_number)?;
+
+            check_response_size(&all_logs, self.config.max_response_size)?;
+            return Ok(all_logs);
+        }
+
         match filter.block_hash {
             Some(hash) => {
                 get_logs(
@@ -485,7 +568,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
                     &topics,
                     &mut all_logs,
                     address_filter.as_ref(),
-                    &mut early_return,
+                    &mut limits,
                 )
                 .await?;
             }
@@ -494,7 +577,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
                     .adapter
                     .get_block_by_number(None)
                     .await
-                    .map_err(|e| Error::Custom(e.to_string()))?
+                    .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
                     .unwrap();
                 let latest_number = latest_block.header.number;
                 let (start, end) = {
@@ -516,7 +599,11 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
                 };
 
                 if start > latest_number {
-                    return Err(Error::Custom(format!("Invalid from_block {}", start)));
+                    return Err(RpcError::InvalidParams(format!(
+                        "Invalid from_block {}",
+                        start
+                    ))
+                    .into());
                 }
 
                 let mut visiter_last_block = false;
@@ -530,13 +617,9 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
                             &topics,
                             &mut all_logs,
                             address_filter.as_ref(),
-                            &mut early_return,
+                            &mut limits,
                         )
                         .await?;
-
-                        if early_return {
-                            return Ok(all_logs);
-                        }
                     }
                 }
 
@@ -547,26 +630,117 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
                         &topics,
                         &mut all_logs,
                         address_filter.as_ref(),
-                        &mut early_return,
+                        &mut limits,
                     )
                     .await?;
                 }
             }
         }
+
+        check_response_size(&all_logs, self.config.max_response_size)?;
         Ok(all_logs)
     }
 
     async fn fee_history(
         &self,
-        _block_count: U256,
-        _newest_block: BlockId,
-        _reward_percentiles: Option<Vec<f64>>,
+        block_count: U256,
+        newest_block: BlockId,
+        reward_percentiles: Option<Vec<f64>>,
     ) -> RpcResult<Web3FeeHistory> {
+        if let Some(percentiles) = reward_percentiles.as_ref() {
+            check_reward_percentiles(percentiles)?;
+        }
+
+        let block_count = block_count.min(MAX_FEE_HISTORY_BLOCKS.into()).as_u64();
+        if block_count == 0 {
+            return Ok(Web3FeeHistory {
+                oldest_block: U256::zero(),
+                reward: None,
+                base_fee_per_gas: Vec::new(),
+                gas_used_ratio: Vec::new(),
+            });
+        }
+
+        let latest_number = self
+            .adapter
+            .get_block_header_by_number(None)
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+            .ok_or_else(|| RpcError::NotFound("Cannot get the latest block".to_owned()))?
+            .number;
+        let newest_number = match newest_block {
+            BlockId::Num(n) if n > latest_number => {
+                return Err(RpcError::InvalidParams(format!(
+                    "block {} is beyond the chain tip {}",
+                    n, latest_number
+                ))
+                .into());
+            }
+            BlockId::Num(n) => n,
+            BlockId::Earliest => 0,
+            BlockId::Latest | BlockId::Pending => latest_number,
+        };
+        let oldest_number = (newest_number + 1).saturating_sub(block_count);
+
+        let mut base_fees = Vec::with_capacity(block_count as usize + 1);
+        let mut ratios = Vec::with_capacity(block_count as usize);
+        let mut rewards = Vec::with_capacity(block_count as usize);
+
+        for number in oldest_number..=newest_number {
+            let header = self
+                .adapter
+                .get_block_header_by_number(Some(number))
+                .await
+                .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+                .ok_or_else(|| {
+                    RpcError::NotFound(format!("Cannot get {:?} block", number))
+                })?;
+
+            base_fees.push(header.base_fee_per_gas);
+            ratios.push(gas_used_ratio(header.gas_used, header.gas_limit));
+
+            if let Some(percentiles) = reward_percentiles.as_ref() {
+                let tx_hashes = self
+                    .adapter
+                    .get_block_by_number(Some(number))
+                    .await
+                    .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+                    .map(|b| b.tx_hashes)
+                    .unwrap_or_default();
+                let txs = self
+                    .adapter
+                    .get_txs_by_hashes(number, &tx_hashes)
+                    .await
+                    .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
+                let receipts = self
+                    .adapter
+                    .get_receipts_by_hashes(number, &tx_hashes)
+                    .await
+                    .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
+
+                let tx_rewards = txs
+                    .into_iter()
+                    .zip(receipts)
+                    .filter_map(|(tx, receipt)| {
+                        let reward = tx?
+                            .transaction
+                            .unsigned
+                            .effective_priority_fee(header.base_fee_per_gas);
+                        Some((reward, receipt?.used_gas.low_u64()))
+                    })
+                    .collect();
+                rewards.push(fee_rewards(tx_rewards, percentiles));
+            }
+        }
+
+        // the base fee of the next block
+        base_fees.push(base_fee_per_gas());
+
         Ok(Web3FeeHistory {
-            oldest_block: U256::from(0),
-            reward: None,
-            base_fee_per_gas: Vec::new(),
-            gas_used_ratio: Vec::new(),
+            oldest_block: oldest_number.into(),
+            reward: reward_percentiles.map(|_| rewards),
+            base_fee_per_gas: base_fees,
+            gas_used_ratio: ratios,
         })
     }
 
@@ -579,7 +753,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .adapter
             .get_block_by_hash(hash)
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
             .map(|b| U256::from(b.tx_hashes.len()))
             .unwrap_or_default())
     }
@@ -590,7 +764,11 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
         position: U256,
     ) -> RpcResult<Option<Web3Transaction>> {
         if position > U256::from(usize::MAX) {
-            return Err(Error::Custom(format!("invalid position: {}", position)));
+            return Err(RpcError::InvalidParams(format!(
+                "invalid position: {}",
+                position
+            ))
+            .into());
         }
 
         let mut raw = [0u8; 32];
@@ -604,7 +782,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .adapter
             .get_block_by_hash(hash)
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
 
         if let Some(block) = block {
             if let Some(tx_hash) = block.tx_hashes.get(index) {
@@ -620,7 +798,11 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
         position: U256,
     ) -> RpcResult<Option<Web3Transaction>> {
         if position > U256::from(usize::MAX) {
-            return Err(Error::Custom(format!("invalid position: {}", position)));
+            return Err(RpcError::InvalidParams(format!(
+                "invalid position: {}",
+                position
+            ))
+            .into());
         }
 
         let mut raw = [0u8; 32];
@@ -635,7 +817,7 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .adapter
             .get_block_by_number(number.into())
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?;
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
 
         if let Some(block) = block {
             if let Some(tx_hash) = block.tx_hashes.get(index) {
@@ -655,15 +837,18 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
             .adapter
             .get_block_by_number(number.unwrap_or_default().into())
             .await
-            .map_err(|e| Error::Custom(e.to_string()))?
-            .ok_or_else(|| Error::Custom("Can't find this block".to_string()))?;
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+            .ok_or_else(|| RpcError::NotFound("Can't find this block".to_string()))?;
         let value = self
             .adapter
             .get_storage_at(address, position, block.header.state_root)
             .await
             .unwrap_or_else(|_| H256::default().as_bytes().to_vec());
 
-        Ok(Hex::encode(value))
+        let value = Hex::encode(value);
+        check_response_size(&value, self.config.max_response_size)?;
+
+        Ok(value)
     }
 
     async fn model_version(&self) -> RpcResult<Hex> {
@@ -696,6 +881,535 @@ impl<Adapter: APIAdapter + 'static> RTEvmWeb3RpcServer for Web3RpcImpl<Adapter>
     ) -> RpcResult<U256> {
         Ok(U256::zero())
     }
+
+    async fn txpool_status(&self) -> RpcResult<Web3TxPoolStatus> {
+        let (pending, queued) = self
+            .adapter
+            .get_txpool_status()
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
+        Ok(Web3TxPoolStatus { pending, queued })
+    }
+
+    async fn get_tx_status(&self, hash: Hash) -> RpcResult<Web3TxStatus> {
+        if self
+            .adapter
+            .get_tx_by_hash(hash)
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+            .is_some()
+        {
+            return Ok(Web3TxStatus::Mined);
+        }
+
+        if self
+            .adapter
+            .is_tx_pooled(hash)
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+        {
+            return Ok(Web3TxStatus::Pending);
+        }
+
+        if self
+            .adapter
+            .is_tx_evicted(hash)
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+        {
+            return Ok(Web3TxStatus::Dropped);
+        }
+
+        Ok(Web3TxStatus::Unknown)
+    }
+
+    async fn get_state_root(
+        &self,
+        number: BlockId,
+    ) -> RpcResult<Option<Web3BlockRoots>> {
+        Ok(self
+            .adapter
+            .get_block_header_by_number(number.into())
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+            .map(|h| Web3BlockRoots::from(&h)))
+    }
+
+    async fn get_storage_at_batch(
+        &self,
+        address: H160,
+        positions: Vec<U256>,
+        number: Option<BlockId>,
+    ) -> RpcResult<Vec<Hex>> {
+        if positions.len() > MAX_STORAGE_SLOT_NUM {
+            return Err(RpcError::LimitExceeded(format!(
+                "The number of positions exceeds {}",
+                MAX_STORAGE_SLOT_NUM
+            ))
+            .into());
+        }
+
+        let block = self
+            .adapter
+            .get_block_by_number(number.unwrap_or_default().into())
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+            .ok_or_else(|| RpcError::NotFound("Can't find this block".to_string()))?;
+
+        // the same as `eth_getStorageAt`, zero for the positions never set
+        let values = self
+            .adapter
+            .get_storage_at_batch(address, &positions, block.header.state_root)
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+            .into_iter()
+            .map(|v| {
+                Hex::encode(v.unwrap_or_else(|| H256::default().as_bytes().to_vec()))
+            })
+            .collect::<Vec<_>>();
+        check_response_size(&values, self.config.max_response_size)?;
+
+        Ok(values)
+    }
+
+    async fn account_range(
+        &self,
+        number: BlockId,
+        start_key: H256,
+        max_results: u64,
+    ) -> RpcResult<Web3AccountRange> {
+        let max_results = max_results.min(self.config.max_account_range_results);
+        let (accounts, next) = self
+            .adapter
+            .get_account_range(number.into(), start_key, max_results as usize)
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
+
+        Ok(Web3AccountRange {
+            accounts: accounts.into_iter().map(Into::into).collect(),
+            next,
+        })
+    }
+
+    async fn get_next_nonce(&self, address: H160) -> RpcResult<U256> {
+        self.adapter
+            .get_next_nonce(address)
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()).into())
+    }
+
+    async fn call_at_tx_index(
+        &self,
+        req: Web3CallRequest,
+        number: BlockId,
+        tx_index: U256,
+    ) -> RpcResult<Hex> {
+        check_gas_price(&req, self.config.max_call_gas_price)?;
+
+        if req.gas.unwrap_or_default() > U256::from(MAX_BLOCK_GAS_LIMIT) {
+            return Err(RpcError::InvalidParams(
+                "The gas limit is too large".to_string(),
+            )
+            .into());
+        }
+
+        check_call_from(&req, self.config.call_from_required)?;
+
+        if tx_index > U256::from(MAX_REPLAY_TX_NUM) {
+            return Err(RpcError::LimitExceeded(format!(
+                "The tx index exceeds {}",
+                MAX_REPLAY_TX_NUM
+            ))
+            .into());
+        }
+
+        let number = match Option::<u64>::from(number) {
+            Some(n) => n,
+            None => {
+                self.adapter
+                    .get_block_header_by_number(None)
+                    .await
+                    .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+                    .ok_or_else(|| {
+                        RpcError::NotFound("Cannot get the latest header".to_owned())
+                    })?
+                    .number
+            }
+        };
+
+        let data = req
+            .data
+            .as_ref()
+            .map(|hex| hex.as_bytes())
+            .unwrap_or_default();
+        let resp = self
+            .adapter
+            .evm_call_at_tx_index(
+                req.from,
+                req.to,
+                req.gas_price,
+                req.gas,
+                req.value.unwrap_or_default(),
+                data.to_vec(),
+                number,
+                tx_index.as_usize(),
+            )
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
+
+        if resp.exit_reason.is_succeed() {
+            return Ok(Hex::encode(resp.ret));
+        }
+
+        Err(RpcError::VM(resp).into())
+    }
+
+    async fn call_many(
+        &self,
+        bundle: Vec<Web3CallRequest>,
+        number: Option<BlockId>,
+        state_overrides: Option<BTreeMap<H160, Web3StateOverride>>,
+    ) -> RpcResult<Vec<Web3CallResult>> {
+        if bundle.len() > MAX_CALL_BUNDLE_SIZE {
+            return Err(RpcError::LimitExceeded(format!(
+                "The number of calls exceeds {}",
+                MAX_CALL_BUNDLE_SIZE
+            ))
+            .into());
+        }
+
+        let calls = bundle
+            .into_iter()
+            .map(|req| {
+                check_gas_price(&req, self.config.max_call_gas_price)?;
+
+                if req.gas.unwrap_or_default() > U256::from(MAX_BLOCK_GAS_LIMIT) {
+                    return Err(RpcError::InvalidParams(
+                        "The gas limit is too large".to_string(),
+                    )
+                    .into());
+                }
+
+                check_call_from(&req, self.config.call_from_required)?;
+
+                Ok(CallRequest {
+                    from: req.from,
+                    to: req.to,
+                    gas_price: req.gas_price,
+                    gas_limit: req.gas,
+                    value: req.value.unwrap_or_default(),
+                    data: req
+                        .data
+                        .map(|hex| hex.as_bytes().to_vec())
+                        .unwrap_or_default(),
+                })
+            })
+            .collect::<RpcResult<Vec<_>>>()?;
+
+        let state_overrides = state_overrides
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(address, o)| (address, o.into()))
+            .collect();
+
+        Ok(self
+            .adapter
+            .evm_call_many(calls, state_overrides, number.unwrap_or_default().into())
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    async fn get_checkpoint_config(&self) -> RpcResult<Web3CheckpointConfig> {
+        Ok(self.adapter.checkpoint_config().into())
+    }
+
+    async fn call_with_gas(
+        &self,
+        req: Web3CallRequest,
+        number: Option<BlockId>,
+    ) -> RpcResult<Web3CallWithGas> {
+        check_gas_price(&req, self.config.max_call_gas_price)?;
+
+        if req.gas.unwrap_or_default() > U256::from(MAX_BLOCK_GAS_LIMIT) {
+            return Err(RpcError::InvalidParams(
+                "The gas limit is too large".to_string(),
+            )
+            .into());
+        }
+
+        let data_bytes = req
+            .data
+            .as_ref()
+            .map(|hex| hex.as_bytes())
+            .unwrap_or_default();
+        let resp = self
+            .call_evm(req, data_bytes, number.unwrap_or_default().into())
+            .await?;
+
+        // the errors other than a revert are not the results of the code
+        if resp.exit_reason.is_succeed() || resp.exit_reason.is_revert() {
+            return Ok(resp.into());
+        }
+
+        Err(RpcError::VM(resp).into())
+    }
+
+    async fn clear_mempool(&self) -> RpcResult<U256> {
+        if !self.config.admin_rpc_enabled {
+            return Err(RpcError::InvalidParams(
+                "The admin APIs are disabled".to_owned(),
+            )
+            .into());
+        }
+
+        self.adapter
+            .clear_txpool()
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()).into())
+    }
+
+    async fn get_txs_by_address(
+        &self,
+        address: H160,
+        offset: u64,
+        limit: u64,
+    ) -> RpcResult<Web3TxHistory> {
+        let (total, transactions) = self
+            .adapter
+            .get_txs_by_address(address, offset, limit as usize)
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?;
+
+        Ok(Web3TxHistory {
+            total: total.into(),
+            transactions,
+        })
+    }
+
+    async fn get_latest_blocks(&self, count: u64) -> RpcResult<Vec<Web3Block>> {
+        let count = count.min(self.config.max_latest_blocks);
+        if count == 0 {
+            return Ok(vec![]);
+        }
+
+        // the height is resolved only once, so the blocks are continuous
+        let latest = self
+            .adapter
+            .get_block_by_number(None)
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+            .ok_or_else(|| {
+                RpcError::NotFound("Cannot get the latest block".to_owned())
+            })?;
+
+        let latest_number = latest.header.number;
+        let lowest = (latest_number + 1).saturating_sub(count);
+
+        let mut blocks = Vec::with_capacity(count as usize);
+        blocks.push(Web3Block::from(latest));
+        for number in (lowest..latest_number).rev() {
+            let b = self
+                .adapter
+                .get_block_by_number(Some(number))
+                .await
+                .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+                .ok_or_else(|| {
+                    RpcError::NotFound(format!("Cannot get {:?} block", number))
+                })?;
+            blocks.push(Web3Block::from(b));
+        }
+
+        Ok(blocks)
+    }
+
+    async fn get_tx_state_diff(&self, hash: H256) -> RpcResult<Option<Web3StateDiff>> {
+        self.adapter
+            .get_tx_state_diff(hash)
+            .await
+            .map(|d| d.map(Into::into))
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()).into())
+    }
+
+    async fn get_storage_at_raw(
+        &self,
+        address: H160,
+        key: H256,
+        number: Option<BlockId>,
+    ) -> RpcResult<Hex> {
+        let block = self
+            .adapter
+            .get_block_by_number(number.unwrap_or_default().into())
+            .await
+            .map_err(|e| RpcError::StateUnavailable(e.to_string()))?
+            .ok_or_else(|| RpcError::NotFound("Can't find this block".to_string()))?;
+        let value = self
+            .adapter
+            .get_storage_at_raw(address, key, block.header.state_root)
+            .await
+            .unwrap_or_else(|_| H256::default().as_bytes().to_vec());
+
+        let value = Hex::encode(value);
+        check_response_size(&value, self.config.max_response_size)?;
+
+        Ok(value)
+    }
+}
+
+// shared by `eth_call` and `eth_estimateGas`,
+// a request passing one of them must not be rejected by the other for its gas price
+fn check_gas_price(req: &Web3CallRequest, max_gas_price: u64) -> RpcResult<()> {
+    if req.gas_price.unwrap_or_default() > U256::from(max_gas_price) {
+        return Err(
+            RpcError::InvalidParams("The gas price is too large".to_string()).into(),
+        );
+    }
+    Ok(())
+}
+
+// A tx running out of gas at the per-tx cap can never be included,
+// so it is told apart from the ones failing with a lower gas limit.
+fn estimated_gas(resp: TxResp, gas_limit: u64, cap: u64) -> RpcResult<U256> {
+    let out_of_gas = matches!(resp.exit_reason, ExitReason::Error(ExitError::OutOfGas));
+    if resp.gas_used > cap || (out_of_gas && gas_limit >= cap) {
+        return Err(RpcError::LimitExceeded(format!(
+            "The tx needs more gas than the per-tx cap {}",
+            cap
+        ))
+        .into());
+    }
+
+    if resp.exit_reason.is_succeed() {
+        return Ok(resp.gas_used.into());
+    }
+
+    Err(RpcError::VM(resp).into())
+}
+
+// A call without a `from` is executed from the zero address, like geth,
+// unless an explicit one is required.
+fn check_call_from(req: &Web3CallRequest, require_from: bool) -> RpcResult<()> {
+    if req.from.is_none() {
+        if req.to.is_none() {
+            return Err(
+                RpcError::InvalidParams("from and to are both None".to_owned()).into(),
+            );
+        }
+        if require_from {
+            return Err(RpcError::InvalidParams("from is required".to_owned()).into());
+        }
+    }
+    Ok(())
+}
+
+fn check_reward_percentiles(percentiles: &[f64]) -> RpcResult<()> {
+    let mut prev = 0.0;
+    for p in percentiles.iter().copied() {
+        if !(0.0..=100.0).contains(&p) || p < prev {
+            return Err(RpcError::InvalidParams(format!(
+                "invalid reward percentile: {}, must be increasing within [0, 100]",
+                p
+            ))
+            .into());
+        }
+        prev = p;
+    }
+    Ok(())
+}
+
+fn gas_used_ratio(gas_used: U256, gas_limit: U256) -> f64 {
+    if gas_limit.is_zero() {
+        return 0.0;
+    }
+    gas_used.low_u64() as f64 / gas_limit.low_u64() as f64
+}
+
+// `txs`: (<effective priority fee>, <gas used>) of each tx in a block,
+//
+// the reward at a percentile is the lowest one of the txs
+// whose cumulative gas reaches that percentile of the gas used by the block
+fn fee_rewards(mut txs: Vec<(U256, u64)>, percentiles: &[f64]) -> Vec<U256> {
+    if txs.is_empty() {
+        return vec![U256::zero(); percentiles.len()];
+    }
+
+    txs.sort_unstable_by_key(|(reward, _)| *reward);
+
+    let total = txs.iter().map(|(_, gas)| *gas as f64).sum::<f64>();
+    let mut idx = 0;
+    let mut cumulative = txs[0].1 as f64;
+
+    percentiles
+        .iter()
+        .map(|p| {
+            let threshold = total * p / 100.0;
+            while cumulative < threshold && idx < txs.len() - 1 {
+                idx += 1;
+                cumulative += txs[idx].1 as f64;
+            }
+            txs[idx].0
+        })
+        .collect()
+}
+
+// The limits of one `eth_getLogs` query, the results are either complete or rejected,
+// the error tells where to resume from, that is, the block in which a limit is reached.
+struct LogLimits {
+    max_returned: usize,
+    max_scanned: usize,
+    scanned: usize,
+}
+
+impl LogLimits {
+    fn with(max_returned: usize, max_scanned: usize) -> Self {
+        Self {
+            max_returned,
+            max_scanned,
+            scanned: 0,
+        }
+    }
+
+    // called after the logs of each receipt have been filtered,
+    // `scanned` is the number of the logs in the receipt
+    fn check(
+        &mut self,
+        scanned: usize,
+        returned: usize,
+        block_number: BlockNumber,
+    ) -> RpcResult<()> {
+        self.scanned += scanned;
+
+        let reason = if returned > self.max_returned {
+            format!("more than {} logs matched", self.max_returned)
+        } else if self.scanned > self.max_scanned {
+            format!("more than {} logs scanned", self.max_scanned)
+        } else {
+            return Ok(());
+        };
+
+        Err(RpcError::LimitExceeded(format!(
+            "Query exceeds the limit, {} at block {}, \
+             query the blocks before it, then resume from it",
+            reason, block_number
+        ))
+        .into())
+    }
+}
+
+// measured by the JSON encoding, the same as what the clients receive
+fn check_response_size<T: Serialize>(ret: &T, limit: usize) -> RpcResult<()> {
+    // a result failing to encode will be reported by the server anyway
+    let size = serde_json::to_vec(ret).map(|v| v.len()).unwrap_or_default();
+    if size > limit {
+        return Err(RpcError::LimitExceeded(format!(
+            "The response size {} exceeds {} bytes",
+            size, limit
+        ))
+        .into());
+    }
+    Ok(())
 }
 
 fn mock_header_by_call_req(latest_header: Header, call_req: &Web3CallRequest) -> Header {
@@ -736,6 +1450,7 @@ pub fn from_receipt_to_web3_log(
     topics: &[Option<Vec<Option<Hash>>>],
     address: &[H160],
     receipt: &Receipt,
+    block_timestamp: u64,
     logs: &mut Vec<Web3Log>,
 ) {
     macro_rules! contains_topic {
@@ -770,9 +1485,1073 @@ pub fn from_receipt_to_web3_log(
                 transaction_hash: Some(receipt.tx_hash),
                 transaction_index: Some(index.into()),
                 log_index: Some(log_idex.into()),
+                block_timestamp: Some(block_timestamp.into()),
                 removed: false,
             };
             logs.push(web3_log);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::error::ErrorCode;
+    use jsonrpsee::{core::Error, types::error::CallError};
+    use rt_evm_executor::decode_revert_msg;
+    use rt_evm_model::types::{
+        Account, CheckPointConfig, ExitRevert, ExitSucceed, Hasher, LegacyTransaction,
+        Log, LogsLimit, Proposal, StateDiff, StateOverride, TransactionAction,
+        UnsignedTransaction,
+    };
+    use ruc::*;
+    use std::fmt::Debug;
+
+    // an empty chain, `header` is the block header found by any number if set,
+    // otherwise the ones of `blocks`,
+    // and `blocks` are the only blocks, indexed by their numbers
+    struct MockAdapter {
+        header: Option<Header>,
+        blocks: Vec<Block>,
+        checkpoint: CheckPointConfig,
+    }
+
+    #[async_trait]
+    impl APIAdapter for MockAdapter {
+        async fn insert_signed_tx(&self, _: SignedTransaction) -> Result<()> {
+            Err(eg!("rejected"))
+        }
+
+        async fn get_block_by_number(
+            &self,
+            number: Option<u64>,
+        ) -> Result<Option<Block>> {
+            Ok(match number {
+                Some(n) => self.blocks.get(n as usize).cloned(),
+                None => self.blocks.last().cloned(),
+            })
+        }
+
+        async fn get_block_by_hash(&self, _: Hash) -> Result<Option<Block>> {
+            Ok(None)
+        }
+
+        async fn get_block_header_by_number(
+            &self,
+            number: Option<u64>,
+        ) -> Result<Option<Header>> {
+            if self.header.is_some() {
+                return Ok(self.header.clone());
+            }
+            Ok(self.get_block_by_number(number).await?.map(|b| b.header))
+        }
+
+        async fn get_receipt_by_tx_hash(&self, hash: Hash) -> Result<Option<Receipt>> {
+            // the only known tx, the third one of the block 1
+            if hash != Hash::repeat_byte(1) {
+                return Ok(None);
+            }
+            let log = |n: u8| Log {
+                address: H160::repeat_byte(n),
+                topics: vec![H256::repeat_byte(n)],
+                data: vec![n],
+            };
+            Ok(Some(Receipt {
+                tx_hash: hash,
+                block_number: 1,
+                tx_index: 2,
+                logs: vec![log(1), log(2)],
+                ..Default::default()
+            }))
+        }
+
+        async fn get_receipts_by_hashes(
+            &self,
+            _: u64,
+            _: &[Hash],
+        ) -> Result<Vec<Option<Receipt>>> {
+            Ok(vec![])
+        }
+
+        async fn get_receipts_by_block_number(
+            &self,
+            _: u64,
+        ) -> Result<Option<Vec<Receipt>>> {
+            Ok(None)
+        }
+
+        async fn get_tx_by_hash(&self, _: Hash) -> Result<Option<SignedTransaction>> {
+            Ok(None)
+        }
+
+        async fn get_tx_with_block(
+            &self,
+            hash: Hash,
+        ) -> Result<Option<(BlockNumber, SignedTransaction)>> {
+            Ok(alt!(
+                hash == Hash::repeat_byte(1),
+                Some((1, mock_tx(hash))),
+                None
+            ))
+        }
+
+        // the positions of the confirmed txs are read from their receipts
+        async fn get_tx_position(&self, _: Hash) -> Result<Option<(BlockNumber, u32)>> {
+            Err(eg!("unexpected lookup of the tx position"))
+        }
+
+        async fn get_txs_by_hashes(
+            &self,
+            _: u64,
+            _: &[Hash],
+        ) -> Result<Vec<Option<SignedTransaction>>> {
+            Ok(vec![])
+        }
+
+        async fn get_account(&self, _: H160, _: Option<BlockNumber>) -> Result<Account> {
+            Err(eg!("the world state is unavailable"))
+        }
+
+        async fn get_pending_tx_count(&self, _: H160) -> Result<U256> {
+            Ok(U256::zero())
+        }
+
+        async fn get_next_nonce(&self, _: H160) -> Result<U256> {
+            Err(eg!("the world state is unavailable"))
+        }
+
+        async fn is_tx_pooled(&self, _: Hash) -> Result<bool> {
+            Ok(false)
+        }
+
+        async fn is_tx_evicted(&self, _: Hash) -> Result<bool> {
+            Ok(false)
+        }
+
+        async fn get_txpool_status(&self) -> Result<(U256, U256)> {
+            Ok((U256::zero(), U256::zero()))
+        }
+
+        async fn clear_txpool(&self) -> Result<U256> {
+            Ok(U256::from(3))
+        }
+
+        async fn evm_call(
+            &self,
+            _: Option<H160>,
+            _: Option<H160>,
+            _: Option<U256>,
+            _: Option<U256>,
+            _: U256,
+            data: Vec<u8>,
+            _: Hash,
+            _: Proposal,
+        ) -> Result<TxResp> {
+            // echo the calldata if any, at the cost of its bytes
+            if !data.is_empty() {
+                return Ok(TxResp {
+                    exit_reason: ExitReason::Succeed(ExitSucceed::Returned),
+                    gas_used: 21000 + 16 * data.len() as u64,
+                    ret: data,
+                    ..Default::default()
+                });
+            }
+            Ok(TxResp {
+                exit_reason: ExitReason::Revert(ExitRevert::Reverted),
+                ..Default::default()
+            })
+        }
+
+        async fn evm_call_at_tx_index(
+            &self,
+            _: Option<H160>,
+            _: Option<H160>,
+            _: Option<U256>,
+            _: Option<U256>,
+            _: U256,
+            _: Vec<u8>,
+            number: BlockNumber,
+            tx_index: usize,
+        ) -> Result<TxResp> {
+            // the state after the first tx of the block 1 only
+            if (number, tx_index) != (1, 1) {
+                return Err(eg!());
+            }
+            Ok(TxResp {
+                exit_reason: ExitReason::Succeed(ExitSucceed::Returned),
+                ret: vec![1],
+                ..Default::default()
+            })
+        }
+
+        async fn evm_call_many(
+            &self,
+            calls: Vec<CallRequest>,
+            _: BTreeMap<H160, StateOverride>,
+            _: Option<BlockNumber>,
+        ) -> Result<Vec<TxResp>> {
+            // echo the calldata
+            Ok(calls
+                .into_iter()
+                .map(|c| TxResp {
+                    exit_reason: ExitReason::Succeed(ExitSucceed::Returned),
+                    ret: c.data,
+                    ..Default::default()
+                })
+                .collect())
+        }
+
+        async fn get_code_by_hash(&self, _: &Hash) -> Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        async fn get_code_size_by_hash(&self, _: &Hash) -> Result<Option<u64>> {
+            Ok(None)
+        }
+
+        async fn get_storage_at(&self, _: H160, _: U256, _: Hash) -> Result<Vec<u8>> {
+            Ok(vec![])
+        }
+
+        async fn get_storage_at_raw(
+            &self,
+            address: H160,
+            key: H256,
+            _: Hash,
+        ) -> Result<Vec<u8>> {
+            // only the entry of `address` in the mapping at the slot 0 is set
+            if key == mapping_slot(address, 0) {
+                return Ok(H256::from_low_u64_be(42).as_bytes().to_vec());
+            }
+            Err(eg!("Can't find this position"))
+        }
+
+        async fn get_storage_at_batch(
+            &self,
+            _: H160,
+            positions: &[U256],
+            _: Hash,
+        ) -> Result<Vec<Option<Vec<u8>>>> {
+            Ok(vec![None; positions.len()])
+        }
+
+        async fn get_account_range(
+            &self,
+            _: Option<BlockNumber>,
+            _: Hash,
+            limit: usize,
+        ) -> Result<(Vec<(Hash, H160, Account)>, Option<Hash>)> {
+            // as many accounts as requested, with a cursor to more of them
+            let accounts = (0..limit as u64)
+                .map(|n| {
+                    let account = Account {
+                        nonce: U256::zero(),
+                        balance: n.into(),
+                        storage_root: Hash::zero(),
+                        code_hash: Hash::zero(),
+                    };
+                    (Hash::from_low_u64_be(n), H160::from_low_u64_be(n), account)
+                })
+                .collect();
+            Ok((accounts, Some(Hash::from_low_u64_be(limit as u64))))
+        }
+
+        async fn get_txs_by_address(
+            &self,
+            _: H160,
+            offset: u64,
+            limit: usize,
+        ) -> Result<(u64, Vec<Hash>)> {
+            // 5 txs, hashed from their sequences
+            let hashes = (offset..5).take(limit).map(Hash::from_low_u64_be).collect();
+            Ok((5, hashes))
+        }
+
+        async fn get_tx_state_diff(&self, _: Hash) -> Result<Option<StateDiff>> {
+            Ok(None)
+        }
+
+        fn checkpoint_config(&self) -> CheckPointConfig {
+            self.checkpoint
+        }
+    }
+
+    // the slot of the entry of `key` in a `mapping(address => ...)` at the `slot`,
+    // computed the same way as solidity
+    fn mapping_slot(key: H160, slot: u64) -> H256 {
+        let key = H256::from(key);
+        let slot = H256::from_low_u64_be(slot);
+        Hasher::digest([key.as_bytes(), slot.as_bytes()].concat())
+    }
+
+    fn mock_tx(hash: Hash) -> SignedTransaction {
+        SignedTransaction {
+            transaction: UnverifiedTransaction {
+                unsigned: UnsignedTransaction::Legacy(LegacyTransaction {
+                    nonce: U256::zero(),
+                    gas_price: U256::one(),
+                    gas_limit: 21000.into(),
+                    action: TransactionAction::Call(H160::repeat_byte(2)),
+                    value: U256::zero(),
+                    data: vec![],
+                }),
+                signature: None,
+                chain_id: 0,
+                hash,
+            },
+            sender: H160::repeat_byte(1),
+            public: None,
+        }
+    }
+
+    fn rpc(header: Option<Header>) -> Web3RpcImpl<MockAdapter> {
+        rpc_with(header, RpcConfig::default())
+    }
+
+    fn rpc_with(header: Option<Header>, config: RpcConfig) -> Web3RpcImpl<MockAdapter> {
+        Web3RpcImpl::new(
+            Arc::new(MockAdapter {
+                header,
+                blocks: vec![],
+                checkpoint: Default::default(),
+            }),
+            config,
+        )
+    }
+
+    fn call_req(json: serde_json::Value) -> Web3CallRequest {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn err_code<T: Debug>(ret: RpcResult<T>) -> i32 {
+        match ret.unwrap_err() {
+            Error::Call(CallError::Custom(e)) => e.code(),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        assert_eq!(-32602, ErrorCode::InvalidParams.code());
+        assert_eq!(-32001, ErrorCode::ResourceNotFound.code());
+        assert_eq!(-32002, ErrorCode::StateUnavailable.code());
+        assert_eq!(-32015, ErrorCode::ExecutionReverted.code());
+        assert_eq!(-32005, ErrorCode::LimitExceeded.code());
+        assert_eq!(-32603, ErrorCode::Internal.code());
+    }
+
+    #[tokio::test]
+    async fn test_web3_error_codes() {
+        let invalid_params = ErrorCode::InvalidParams.code();
+        let not_found = ErrorCode::ResourceNotFound.code();
+        let unavailable = ErrorCode::StateUnavailable.code();
+        let reverted = ErrorCode::ExecutionReverted.code();
+        let limit_exceeded = ErrorCode::LimitExceeded.code();
+
+        let rpc = rpc(None);
+        let to = H160::repeat_byte(1);
+
+        assert_eq!(
+            invalid_params,
+            err_code(rpc.send_raw_tx(Hex::encode([0xff, 0x00])).await)
+        );
+
+        let req =
+            call_req(serde_json::json!({ "to": to, "gasPrice": "0x10000000000000000" }));
+        assert_eq!(invalid_params, err_code(rpc.call(req, None).await));
+
+        let req = call_req(serde_json::json!({ "to": to, "gas": "0x0" }));
+        assert_eq!(invalid_params, err_code(rpc.estimate_gas(req, None).await));
+
+        let req = call_req(serde_json::json!({}));
+        assert_eq!(invalid_params, err_code(rpc.call(req, None).await));
+
+        assert_eq!(
+            invalid_params,
+            err_code(
+                rpc.get_tx_by_block_hash_and_index(Hash::zero(), U256::MAX)
+                    .await
+            )
+        );
+
+        let req = call_req(serde_json::json!({ "to": to }));
+        assert_eq!(not_found, err_code(rpc.call(req, None).await));
+
+        assert_eq!(
+            not_found,
+            err_code(rpc.get_storage_at(to, U256::zero(), None).await)
+        );
+
+        assert_eq!(unavailable, err_code(rpc.block_number().await));
+
+        assert_eq!(unavailable, err_code(rpc.get_code(to, None).await));
+
+        assert_eq!(unavailable, err_code(rpc.get_next_nonce(to).await));
+
+        let positions = vec![U256::zero(); MAX_STORAGE_SLOT_NUM + 1];
+        assert_eq!(
+            limit_exceeded,
+            err_code(rpc.get_storage_at_batch(to, positions, None).await)
+        );
+
+        let rpc = self::rpc(Some(Header::default()));
+        let req = call_req(serde_json::json!({ "to": to }));
+        assert_eq!(reverted, err_code(rpc.call(req, None).await));
+    }
+
+    #[tokio::test]
+    async fn test_gas_price_ceiling_is_shared() {
+        let invalid_params = ErrorCode::InvalidParams.code();
+        let reverted = ErrorCode::ExecutionReverted.code();
+
+        let rpc = rpc(Some(Header::default()));
+        let to = H160::repeat_byte(1);
+
+        let max = U256::from(u64::MAX);
+        for (price, expected) in [
+            (max - 1, reverted),
+            (max, reverted),
+            (max + 1, invalid_params),
+        ] {
+            let req = call_req(serde_json::json!({ "to": to, "gasPrice": price }));
+            assert_eq!(expected, err_code(rpc.call(req.clone(), None).await));
+            assert_eq!(expected, err_code(rpc.estimate_gas(req, None).await));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_at_tx_index() {
+        let rpc = rpc(Some(Header {
+            number: 1,
+            ..Default::default()
+        }));
+        let req = call_req(serde_json::json!({ "to": H160::repeat_byte(1) }));
+
+        // the value written by tx 0, but not yet by tx 1
+        for number in [BlockId::Num(1), BlockId::Latest] {
+            let ret = rpc
+                .call_at_tx_index(req.clone(), number, U256::one())
+                .await
+                .unwrap();
+            assert_eq!(Hex::encode([1]), ret);
+        }
+
+        assert_eq!(
+            ErrorCode::StateUnavailable.code(),
+            err_code(
+                rpc.call_at_tx_index(req.clone(), BlockId::Num(1), 2.into())
+                    .await
+            )
+        );
+        assert_eq!(
+            ErrorCode::LimitExceeded.code(),
+            err_code(
+                rpc.call_at_tx_index(
+                    req,
+                    BlockId::Num(1),
+                    (MAX_REPLAY_TX_NUM + 1).into()
+                )
+                .await
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_call_many() {
+        let rpc = rpc(Some(Header::default()));
+        let to = H160::repeat_byte(1);
+        let req = |data: u8| {
+            call_req(serde_json::json!({ "to": to, "data": Hex::encode([data]) }))
+        };
+
+        let ret = rpc
+            .call_many(vec![req(1), req(2)], None, Some(BTreeMap::new()))
+            .await
+            .unwrap();
+        assert_eq!(2, ret.len());
+        for (i, r) in ret.into_iter().enumerate() {
+            assert!(r.success);
+            assert_eq!(Hex::encode([i as u8 + 1]), r.return_data);
+        }
+
+        let bundle = vec![req(0); MAX_CALL_BUNDLE_SIZE + 1];
+        assert_eq!(
+            ErrorCode::LimitExceeded.code(),
+            err_code(rpc.call_many(bundle, None, None).await)
+        );
+
+        let price = U256::from(u64::MAX) + 1;
+        let bad = call_req(serde_json::json!({ "to": to, "gasPrice": price }));
+        assert_eq!(
+            ErrorCode::InvalidParams.code(),
+            err_code(rpc.call_many(vec![req(1), bad], None, None).await)
+        );
+    }
+
+    #[test]
+    fn test_response_size_limit() {
+        let log = Web3Log {
+            address: H160::repeat_byte(1),
+            topics: vec![H256::repeat_byte(2); 4],
+            data: Hex::encode([3u8; 1024]),
+            block_hash: None,
+            block_number: None,
+            transaction_hash: None,
+            transaction_index: None,
+            log_index: None,
+            block_timestamp: None,
+            removed: false,
+        };
+
+        // every single log is small, but not all of them together
+        let logs = vec![log; 100];
+        let size = serde_json::to_vec(&logs).unwrap().len();
+        assert!(check_response_size(&logs[..1], size / 2).is_ok());
+        assert!(check_response_size(&logs, size).is_ok());
+        for _ in 0..2 {
+            assert_eq!(
+                ErrorCode::LimitExceeded.code(),
+                err_code(check_response_size(&logs, size - 1))
+            );
+        }
+
+        let code = Hex::encode([0xffu8; 24 * 1024]);
+        assert!(check_response_size(&code, 24 * 1024).is_err());
+        assert!(check_response_size(&code, 64 * 1024).is_ok());
+    }
+
+    #[test]
+    fn test_log_limits() {
+        let exceeded = |ret: RpcResult<()>| match ret.unwrap_err() {
+            Error::Call(CallError::Custom(e)) => {
+                assert_eq!(ErrorCode::LimitExceeded.code(), e.code());
+                e.message().to_owned()
+            }
+            e => panic!("unexpected error: {:?}", e),
+        };
+
+        // returning exactly the cap is fine
+        let mut limits = LogLimits::with(3, 100);
+        assert!(limits.check(2, 2, 7).is_ok());
+        assert!(limits.check(1, 3, 8).is_ok());
+        let msg = exceeded(limits.check(1, 4, 9));
+        assert!(msg.contains("more than 3 logs matched at block 9"));
+
+        // all of the scanned logs count, matched or not
+        let mut limits = LogLimits::with(3, 10);
+        assert!(limits.check(10, 0, 7).is_ok());
+        let msg = exceeded(limits.check(1, 0, 8));
+        assert!(msg.contains("more than 10 logs scanned at block 8"));
+    }
+
+    #[tokio::test]
+    async fn test_get_logs_by_tx_hash() {
+        let header = Header {
+            number: 1,
+            timestamp: 1_680_000_000,
+            ..Default::default()
+        };
+        // no block can be scanned by the mock, only the tx hash is usable
+        let rpc = rpc(Some(header));
+        let tx_hash = Hash::repeat_byte(1);
+
+        let filter = |json: serde_json::Value| -> Web3Filter {
+            serde_json::from_value(json).unwrap()
+        };
+
+        let logs = rpc
+            .get_logs(filter(serde_json::json!({
+                "fromBlock": "0x0",
+                "toBlock": "latest",
+                "transactionHash": tx_hash,
+            })))
+            .await
+            .unwrap();
+        assert_eq!(2, logs.len());
+        assert!(logs.iter().all(|l| l.transaction_hash == Some(tx_hash)));
+        assert!(logs
+            .iter()
+            .all(|l| l.block_timestamp == Some(1_680_000_000u64.into())));
+
+        let logs = rpc
+            .get_logs(filter(serde_json::json!({
+                "address": H160::repeat_byte(2),
+                "transactionHash": tx_hash,
+            })))
+            .await
+            .unwrap();
+        assert_eq!(1, logs.len());
+        assert_eq!(H160::repeat_byte(2), logs[0].address);
+
+        let logs = rpc
+            .get_logs(filter(serde_json::json!({
+                "topics": [H256::repeat_byte(3)],
+                "transactionHash": tx_hash,
+            })))
+            .await
+            .unwrap();
+        assert!(logs.is_empty());
+
+        assert_eq!(
+            ErrorCode::ResourceNotFound.code(),
+            err_code(
+                rpc.get_logs(filter(serde_json::json!({
+                    "transactionHash": Hash::repeat_byte(2),
+                })))
+                .await
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_tx_by_hash_index() {
+        let rpc = rpc(None);
+        let hash = Hash::repeat_byte(1);
+
+        let tx = rpc.get_tx_by_hash(hash).await.unwrap().unwrap();
+        assert_eq!(hash, tx.hash);
+        assert_eq!(Some(U256::one()), tx.block_number);
+        assert_eq!(Some(U256::from(2)), tx.transaction_index);
+
+        let json = serde_json::to_value(&tx).unwrap();
+        assert_eq!(serde_json::json!("0x2"), json["transactionIndex"]);
+        assert_eq!(serde_json::json!("0x1"), json["blockNumber"]);
+
+        // not confirmed
+        assert!(rpc
+            .get_tx_by_hash(Hash::repeat_byte(2))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_checkpoint_config() {
+        // the one of the adapter, no global state is involved
+        let config = |checkpoint| async move {
+            let rpc = Web3RpcImpl::new(
+                Arc::new(MockAdapter {
+                    header: None,
+                    blocks: vec![],
+                    checkpoint,
+                }),
+                Default::default(),
+            );
+            serde_json::to_value(rpc.get_checkpoint_config().await.unwrap()).unwrap()
+        };
+
+        // disabled by default
+        assert_eq!(
+            serde_json::json!({ "emptyRootHeight": null, "maxLogsPerTx": null }),
+            config(CheckPointConfig::default()).await
+        );
+
+        assert_eq!(
+            serde_json::json!({
+                "emptyRootHeight": "0x64",
+                "maxLogsPerTx": { "height": "0xc8", "limit": "0x3e8", "mode": "cap" },
+            }),
+            config(CheckPointConfig {
+                empty_root_height: Some(100),
+                max_logs_per_tx: Some((200, LogsLimit::Cap(1000))),
+            })
+            .await
+        );
+
+        assert_eq!(
+            serde_json::json!({
+                "emptyRootHeight": null,
+                "maxLogsPerTx": { "height": "0x0", "limit": "0x10", "mode": "fail" },
+            }),
+            config(CheckPointConfig {
+                empty_root_height: None,
+                max_logs_per_tx: Some((0, LogsLimit::Fail(16))),
+            })
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_call_with_gas() {
+        let rpc = rpc(Some(Header::default()));
+        let to = H160::repeat_byte(1);
+
+        let req = call_req(serde_json::json!({ "to": to, "data": "0x0102" }));
+        let ret = rpc.call_with_gas(req.clone(), None).await.unwrap();
+        assert_eq!(Hex::encode([1, 2]), ret.output);
+        assert!(!ret.reverted);
+        assert!(ret.revert_reason.is_none());
+        assert_eq!(U256::from(21032), ret.gas_used);
+        assert_eq!(
+            rpc.estimate_gas(req.clone(), None).await.unwrap(),
+            ret.gas_used
+        );
+        assert_eq!(ret.output, rpc.call(req, None).await.unwrap());
+
+        // a result instead of an error
+        let req = call_req(serde_json::json!({ "to": to }));
+        let ret = rpc.call_with_gas(req.clone(), None).await.unwrap();
+        assert!(ret.reverted);
+        assert_eq!(Some(decode_revert_msg(&[])), ret.revert_reason);
+        assert_eq!(
+            ErrorCode::ExecutionReverted.code(),
+            err_code(rpc.estimate_gas(req, None).await)
+        );
+
+        let req =
+            call_req(serde_json::json!({ "to": to, "gas": MAX_BLOCK_GAS_LIMIT + 1 }));
+        assert_eq!(
+            ErrorCode::InvalidParams.code(),
+            err_code(rpc.call_with_gas(req, None).await)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clear_mempool_is_admin_only() {
+        // disabled by default
+        assert_eq!(
+            ErrorCode::InvalidParams.code(),
+            err_code(rpc(None).clear_mempool().await)
+        );
+
+        // per server, no global state is involved
+        let admin = rpc_with(
+            None,
+            RpcConfig {
+                admin_rpc_enabled: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(U256::from(3), admin.clear_mempool().await.unwrap());
+        assert_eq!(
+            ErrorCode::InvalidParams.code(),
+            err_code(rpc(None).clear_mempool().await)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_txs_by_address() {
+        let rpc = &rpc(None);
+        let address = H160::repeat_byte(1);
+
+        let page = |offset, limit| async move {
+            serde_json::to_value(
+                rpc.get_txs_by_address(address, offset, limit)
+                    .await
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        let hash = |n| serde_json::to_value(Hash::from_low_u64_be(n)).unwrap();
+
+        assert_eq!(
+            serde_json::json!({ "total": "0x5", "transactions": [hash(0), hash(1)] }),
+            page(0, 2).await
+        );
+        assert_eq!(
+            serde_json::json!({ "total": "0x5", "transactions": [hash(4)] }),
+            page(4, 2).await
+        );
+        assert_eq!(
+            serde_json::json!({ "total": "0x5", "transactions": [] }),
+            page(5, 2).await
+        );
+    }
+
+    #[test]
+    fn test_check_call_from() {
+        let to = H160::repeat_byte(1);
+        let only_to = call_req(serde_json::json!({ "to": to }));
+        let with_from = call_req(serde_json::json!({ "from": to, "to": to }));
+        let neither = call_req(serde_json::json!({}));
+
+        // from the zero address by default
+        assert!(check_call_from(&only_to, false).is_ok());
+        assert!(check_call_from(&with_from, false).is_ok());
+        assert!(check_call_from(&neither, false).is_err());
+
+        assert!(check_call_from(&only_to, true).is_err());
+        assert!(check_call_from(&with_from, true).is_ok());
+        assert!(check_call_from(&neither, true).is_err());
+    }
+
+    #[test]
+    fn test_estimated_gas_at_cap() {
+        let cap = 100_000;
+        let resp = |exit_reason, gas_used| TxResp {
+            exit_reason,
+            gas_used,
+            ..Default::default()
+        };
+        let succeed = || ExitReason::Succeed(ExitSucceed::Stopped);
+        let out_of_gas = || ExitReason::Error(ExitError::OutOfGas);
+
+        assert_eq!(
+            U256::from(cap - 1),
+            estimated_gas(resp(succeed(), cap - 1), cap, cap).unwrap()
+        );
+        assert_eq!(
+            U256::from(cap),
+            estimated_gas(resp(succeed(), cap), cap, cap).unwrap()
+        );
+        assert_eq!(
+            ErrorCode::LimitExceeded.code(),
+            err_code(estimated_gas(resp(succeed(), cap + 1), cap, cap))
+        );
+
+        // can not fit the cap
+        assert_eq!(
+            ErrorCode::LimitExceeded.code(),
+            err_code(estimated_gas(resp(out_of_gas(), cap), cap, cap))
+        );
+
+        // only the gas limit of the request is too low
+        assert_eq!(
+            ErrorCode::ExecutionReverted.code(),
+            err_code(estimated_gas(resp(out_of_gas(), cap - 1), cap - 1, cap))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_above_cap() {
+        let rpc = rpc(Some(Header::default()));
+        let data = Hex::encode([7u8; 4]);
+
+        let req =
+            call_req(serde_json::json!({ "to": H160::repeat_byte(1), "data": data }));
+        assert_eq!(
+            U256::from(21000 + 16 * 4),
+            rpc.estimate_gas(req.clone(), None).await.unwrap()
+        );
+
+        let req = Web3CallRequest {
+            gas: Some(U256::from(MAX_BLOCK_GAS_LIMIT + 1)),
+            ..req
+        };
+        assert_eq!(
+            ErrorCode::InvalidParams.code(),
+            err_code(rpc.estimate_gas(req, None).await)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_account_range_is_capped() {
+        let range = rpc(None)
+            .account_range(BlockId::Latest, H256::zero(), 10)
+            .await
+            .unwrap();
+        assert_eq!(10, range.accounts.len());
+
+        let range = rpc(None)
+            .account_range(BlockId::Latest, H256::zero(), u64::MAX)
+            .await
+            .unwrap();
+        assert_eq!(256, range.accounts.len());
+
+        let capped = rpc_with(
+            None,
+            RpcConfig {
+                max_account_range_results: 3,
+                ..Default::default()
+            },
+        );
+        let range = capped
+            .account_range(BlockId::Latest, H256::zero(), u64::MAX)
+            .await
+            .unwrap();
+        assert_eq!(3, range.accounts.len());
+        assert_eq!(Some(H256::from_low_u64_be(3)), range.next);
+    }
+
+    #[tokio::test]
+    async fn test_get_latest_blocks() {
+        let rpc = rpc(None);
+        assert_eq!(
+            ErrorCode::ResourceNotFound.code(),
+            err_code(rpc.get_latest_blocks(5).await)
+        );
+
+        // the blocks 0..=9, the block `n` has `n` txs
+        let blocks = (0..10u64)
+            .map(|n| Block {
+                header: Header {
+                    number: n,
+                    ..Default::default()
+                },
+                tx_hashes: (0..n).map(Hash::from_low_u64_be).collect(),
+            })
+            .collect();
+        let adapter = Arc::new(MockAdapter {
+            header: None,
+            blocks,
+            checkpoint: Default::default(),
+        });
+        let rpc = Web3RpcImpl::new(Arc::clone(&adapter), Default::default());
+
+        let latest = rpc.get_latest_blocks(5).await.unwrap();
+        assert_eq!(5, latest.len());
+        for (b, n) in latest.iter().zip((5..10u64).rev()) {
+            assert_eq!(U256::from(n), b.number);
+            assert_eq!(n as usize, b.transactions.len());
+            assert!(matches!(
+                b.transactions.first(),
+                None | Some(FatTransactionOrHash::Hash(_))
+            ));
+        }
+
+        // no more than the chain
+        assert_eq!(10, rpc.get_latest_blocks(20).await.unwrap().len());
+        assert!(rpc.get_latest_blocks(0).await.unwrap().is_empty());
+
+        // no more than the cap of the server
+        let capped = Web3RpcImpl::new(
+            adapter,
+            RpcConfig {
+                max_latest_blocks: 3,
+                ..Default::default()
+            },
+        );
+        assert_eq!(3, capped.get_latest_blocks(u64::MAX).await.unwrap().len());
+        assert_eq!(10, rpc.get_latest_blocks(u64::MAX).await.unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn test_get_storage_at_raw() {
+        let holder = H160::repeat_byte(1);
+        let token = H160::repeat_byte(0xcc);
+
+        assert_eq!(
+            ErrorCode::ResourceNotFound.code(),
+            err_code(
+                rpc(None)
+                    .get_storage_at_raw(token, H256::zero(), None)
+                    .await
+            )
+        );
+
+        let rpc = Web3RpcImpl::new(
+            Arc::new(MockAdapter {
+                header: None,
+                blocks: vec![Block::default()],
+                checkpoint: Default::default(),
+            }),
+            Default::default(),
+        );
+
+        // `balanceOf[holder]` at the slot 0
+        let value = rpc
+            .get_storage_at_raw(token, mapping_slot(holder, 0), None)
+            .await
+            .unwrap();
+        assert_eq!(Hex::encode(H256::from_low_u64_be(42)), value);
+
+        // the unset ones are zero
+        for key in [mapping_slot(holder, 1), mapping_slot(token, 0)] {
+            let value = rpc.get_storage_at_raw(token, key, None).await.unwrap();
+            assert_eq!(Hex::encode(H256::zero()), value);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fee_history() {
+        assert_eq!(
+            ErrorCode::ResourceNotFound.code(),
+            err_code(rpc(None).fee_history(4.into(), BlockId::Latest, None).await)
+        );
+
+        // the blocks 0..=9, the block `n` uses `n` tenths of the gas limit
+        let blocks = (0..10u64)
+            .map(|n| Block {
+                header: Header {
+                    number: n,
+                    gas_limit: 1000.into(),
+                    gas_used: (n * 100).into(),
+                    base_fee_per_gas: (n + 7).into(),
+                    ..Default::default()
+                },
+                tx_hashes: vec![],
+            })
+            .collect();
+        let rpc = Web3RpcImpl::new(
+            Arc::new(MockAdapter {
+                header: None,
+                blocks,
+                checkpoint: Default::default(),
+            }),
+            Default::default(),
+        );
+
+        let history = rpc
+            .fee_history(4.into(), BlockId::Num(8), Some(vec![10.0, 90.0]))
+            .await
+            .unwrap();
+        assert_eq!(U256::from(5), history.oldest_block);
+        assert_eq!(5, history.base_fee_per_gas.len());
+        assert_eq!(
+            (12..16).map(U256::from).collect::<Vec<_>>(),
+            history.base_fee_per_gas[..4]
+        );
+        assert_eq!(vec![0.5, 0.6, 0.7, 0.8], history.gas_used_ratio);
+        assert_eq!(Some(vec![vec![U256::zero(); 2]; 4]), history.reward);
+
+        // no more than the chain, and no rewards unless requested
+        let history = rpc
+            .fee_history(u64::MAX.into(), BlockId::Latest, None)
+            .await
+            .unwrap();
+        assert_eq!(U256::zero(), history.oldest_block);
+        assert_eq!(11, history.base_fee_per_gas.len());
+        assert_eq!(10, history.gas_used_ratio.len());
+        assert!(history.reward.is_none());
+        assert!(!serde_json::to_string(&history).unwrap().contains("reward"));
+
+        assert!(rpc
+            .fee_history(0.into(), BlockId::Latest, None)
+            .await
+            .unwrap()
+            .base_fee_per_gas
+            .is_empty());
+
+        for (count, newest, percentiles) in [
+            (4, BlockId::Num(10), None),
+            (4, BlockId::Latest, Some(vec![50.0, 10.0])),
+            (4, BlockId::Latest, Some(vec![101.0])),
+        ] {
+            assert_eq!(
+                ErrorCode::InvalidParams.code(),
+                err_code(rpc.fee_history(count.into(), newest, percentiles).await)
+            );
+        }
+    }
+
+    #[test]
+    fn test_fee_rewards() {
+        assert_eq!(
+            vec![U256::zero(); 3],
+            fee_rewards(vec![], &[0.0, 50.0, 100.0])
+        );
+
+        // (<reward>, <gas used>), the total gas is 100
+        let txs = vec![
+            (U256::from(30), 50),
+            (U256::from(10), 10),
+            (U256::from(20), 40),
+        ];
+        assert_eq!(
+            vec![10, 10, 20, 20, 30, 30]
+                .into_iter()
+                .map(U256::from)
+                .collect::<Vec<_>>(),
+            fee_rewards(txs, &[0.0, 10.0, 11.0, 50.0, 51.0, 100.0])
+        );
+
+        assert!(check_reward_percentiles(&[]).is_ok());
+        assert!(check_reward_percentiles(&[0.0, 25.0, 25.0, 100.0]).is_ok());
+        assert!(check_reward_percentiles(&[-1.0]).is_err());
+        assert!(check_reward_percentiles(&[100.5]).is_err());
+        assert!(check_reward_percentiles(&[50.0, 10.0]).is_err());
+
+        assert_eq!(0.0, gas_used_ratio(1.into(), U256::zero()));
+        assert_eq!(0.25, gas_used_ratio(250.into(), 1000.into()));
+    }
+}
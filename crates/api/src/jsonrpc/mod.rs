@@ -1,20 +1,29 @@
 mod error;
 mod impls;
+mod tls;
 pub mod web3_types;
 mod ws_subscription;
 
+pub use tls::TlsConfig;
+
 use crate::jsonrpc::web3_types::{
-    BlockId, FilterChanges, RawLoggerFilter, Web3Block, Web3CallRequest, Web3FeeHistory,
-    Web3Filter, Web3Log, Web3Receipt, Web3SyncStatus, Web3Transaction,
+    BlockId, FilterChanges, RawLoggerFilter, Web3AccountRange, Web3Block,
+    Web3BlockRoots, Web3CallRequest, Web3CallResult, Web3CallWithGas,
+    Web3CheckpointConfig, Web3FeeHistory, Web3Filter, Web3Log, Web3Receipt,
+    Web3StateDiff, Web3StateOverride, Web3SyncStatus, Web3Transaction, Web3TxHistory,
+    Web3TxPoolStatus, Web3TxStatus,
 };
 use crate::jsonrpc::ws_subscription::{ws_subscription_module, HexIdProvider};
 use jsonrpsee::http_server::{HttpServerBuilder, HttpServerHandle};
 use jsonrpsee::ws_server::{WsServerBuilder, WsServerHandle};
 use jsonrpsee::{core::Error, proc_macros::rpc};
 use rt_evm_model::traits::APIAdapter;
-use rt_evm_model::types::{Hash, Hex, H160, H256, U256};
+use rt_evm_model::types::{Hash, Hex, H160, H256, MAX_BLOCK_GAS_LIMIT, U256, U64};
 use ruc::*;
-use std::{result::Result as StdResult, sync::Arc};
+use std::{
+    collections::BTreeMap, net::SocketAddr, result::Result as StdResult, sync::Arc,
+};
+use tokio::task::JoinHandle;
 
 type RpcResult<T> = StdResult<T, Error>;
 
@@ -149,6 +158,114 @@ pub trait RTEvmWeb3Rpc {
     async fn get_uncle_count_by_block_hash(&self, hash: Hash) -> RpcResult<U256>;
     #[method(name = "eth_getUncleCountByBlockNumber")]
     async fn get_uncle_count_by_block_number(&self, number: BlockId) -> RpcResult<U256>;
+
+    #[method(name = "txpool_status")]
+    async fn txpool_status(&self) -> RpcResult<Web3TxPoolStatus>;
+
+    #[method(name = "rt_getTransactionStatus")]
+    async fn get_tx_status(&self, hash: Hash) -> RpcResult<Web3TxStatus>;
+
+    #[method(name = "rt_getStateRoot")]
+    async fn get_state_root(&self, number: BlockId)
+        -> RpcResult<Option<Web3BlockRoots>>;
+
+    #[method(name = "rt_getStorageAtBatch")]
+    async fn get_storage_at_batch(
+        &self,
+        address: H160,
+        positions: Vec<U256>,
+        number: Option<BlockId>,
+    ) -> RpcResult<Vec<Hex>>;
+
+    /// A page of the accounts existing at the block `number`,
+    /// at most `RpcConfig::max_account_range_results` of them.
+    #[method(name = "debug_accountRange")]
+    async fn account_range(
+        &self,
+        number: BlockId,
+        start_key: H256,
+        max_results: u64,
+    ) -> RpcResult<Web3AccountRange>;
+
+    /// The nonce that the next transaction of `address` should use,
+    /// the queued transactions after a nonce gap are not counted.
+    #[method(name = "rt_getNextNonce")]
+    async fn get_next_nonce(&self, address: H160) -> RpcResult<U256>;
+
+    /// The same as `eth_call`, but against the state right after the first `tx_index`
+    /// transactions of the block, e.g. `0` for the state before all of them.
+    ///
+    /// NOTE: the transactions are replayed on every request, at most 1000 of them.
+    #[method(name = "rt_callAtTxIndex")]
+    async fn call_at_tx_index(
+        &self,
+        req: Web3CallRequest,
+        number: BlockId,
+        tx_index: U256,
+    ) -> RpcResult<Hex>;
+
+    /// Execute the calls in order against one evolving state of the block,
+    /// each call observes the changes of the previous ones, nothing is persisted.
+    ///
+    /// The `state_overrides` are applied before the first call.
+    #[method(name = "rt_callMany")]
+    async fn call_many(
+        &self,
+        bundle: Vec<Web3CallRequest>,
+        number: Option<BlockId>,
+        state_overrides: Option<BTreeMap<H160, Web3StateOverride>>,
+    ) -> RpcResult<Vec<Web3CallResult>>;
+
+    /// The heights at which the protocol changes are activated on this node.
+    #[method(name = "rt_getCheckpointConfig")]
+    async fn get_checkpoint_config(&self) -> RpcResult<Web3CheckpointConfig>;
+
+    /// The same as `eth_call`, but the gas used is also returned,
+    /// and a reverted call is a result with its revert reason, not an error.
+    #[method(name = "rt_callWithGas")]
+    async fn call_with_gas(
+        &self,
+        req: Web3CallRequest,
+        number: Option<BlockId>,
+    ) -> RpcResult<Web3CallWithGas>;
+
+    /// Drop all the transactions in the mempool, return the number of the dropped ones.
+    ///
+    /// An admin API, rejected unless enabled by `RpcConfig::admin_rpc_enabled`.
+    #[method(name = "rt_clearMempool")]
+    async fn clear_mempool(&self) -> RpcResult<U256>;
+
+    /// The hashes of the transactions sent or received by `address`, from the oldest one,
+    /// skipping the first `offset` ones, at most `limit` of them.
+    ///
+    /// NOTE: only the transactions persisted since the index was introduced are listed.
+    #[method(name = "rt_getTransactionsByAddress")]
+    async fn get_txs_by_address(
+        &self,
+        address: H160,
+        offset: u64,
+        limit: u64,
+    ) -> RpcResult<Web3TxHistory>;
+
+    /// The latest `count` blocks, from the latest one, with the tx hashes only,
+    /// at most `RpcConfig::max_latest_blocks` of them, e.g. for the home page of an explorer.
+    #[method(name = "rt_getLatestBlocks")]
+    async fn get_latest_blocks(&self, count: u64) -> RpcResult<Vec<Web3Block>>;
+
+    /// The accounts and the slots changed by a transaction, before and after it,
+    /// recomputed by replaying the block up to it, `null` if the transaction is not found.
+    #[method(name = "rt_getTransactionStateDiff")]
+    async fn get_tx_state_diff(&self, hash: H256) -> RpcResult<Option<Web3StateDiff>>;
+
+    /// The same as `eth_getStorageAt`, but by the exact 32-byte key in the storage trie,
+    /// e.g. the slot of a mapping entry, `keccak256(<padded key> ++ <padded slot>)`.
+    #[method(name = "rt_getStorageAtRaw")]
+    async fn get_storage_at_raw(
+        &self,
+        address: H160,
+        key: H256,
+        number: Option<BlockId>,
+    ) -> RpcResult<Hex>;
 }
 
 #[rpc(server)]
@@ -180,6 +297,9 @@ pub trait RTEvmNodeRpc {
     #[method(name = "web3_clientVersion")]
     fn client_version(&self) -> RpcResult<String>;
 
+    #[method(name = "eth_protocolVersion")]
+    fn protocol_version(&self) -> RpcResult<U64>;
+
     #[method(name = "net_listening")]
     fn listening(&self) -> RpcResult<bool>;
 
@@ -205,17 +325,111 @@ pub trait RTEvmNodeRpc {
     fn sha3(&self, data: Hex) -> RpcResult<Hash>;
 }
 
-pub type ServerHandlers = (Option<HttpServerHandle>, Option<WsServerHandle>);
+/// The limits and the switches of the servers started by one `run_jsonrpc_server`.
+#[derive(Clone, Debug)]
+pub struct RpcConfig {
+    /// The max number of the active `eth_subscribe` subscriptions of one websocket
+    /// connection, the ones past the limit are rejected with an error, `1024` by default.
+    pub max_subscriptions_per_connection: u32,
+
+    /// The highest `gasPrice` accepted by `eth_call` and `eth_estimateGas`, inclusive,
+    /// `u64::MAX` by default.
+    pub max_call_gas_price: u64,
+
+    /// The max size in bytes of the JSON encoded result of
+    /// `eth_getCode`, `eth_getStorageAt`, `rt_getStorageAtBatch` and `eth_getLogs`,
+    /// 10 MiB by default, the larger ones are rejected with an error.
+    pub max_response_size: usize,
+
+    /// The max number of the logs returned by one `eth_getLogs` query, `10000` by default,
+    /// a query matching more is rejected with the block to resume from.
+    pub max_logs_returned: usize,
+
+    /// The max number of the logs, matched or not, scanned by one `eth_getLogs` query,
+    /// `100000` by default, a query scanning more is rejected with the block to resume from.
+    pub max_logs_scanned: usize,
+
+    /// Serve the admin APIs, e.g. `rt_clearMempool`, disabled by default,
+    /// only enable it on the servers that can not be reached by the public.
+    pub admin_rpc_enabled: bool,
+
+    /// Reject the `eth_call`-like requests without a `from`, disabled by default.
+    ///
+    /// Like geth, such a call is executed from the zero address,
+    /// which is misleading for the contracts checking the `msg.sender`.
+    pub call_from_required: bool,
+
+    /// The max gas limit of one tx, the upper bound of `eth_estimateGas`,
+    /// `MAX_BLOCK_GAS_LIMIT` by default, for the chains with a smaller per-tx cap.
+    pub max_tx_gas_limit: u64,
+
+    /// The max number of the blocks returned by one `rt_getLatestBlocks`,
+    /// `100` by default, a larger `count` is capped to it.
+    pub max_latest_blocks: u64,
+
+    /// The max number of the accounts returned by one `debug_accountRange`,
+    /// `256` by default, a larger `max_results` is capped to it.
+    pub max_account_range_results: u64,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            max_subscriptions_per_connection: 1024,
+            max_call_gas_price: u64::MAX,
+            max_response_size: 10 * 1024 * 1024,
+            max_logs_returned: 10000,
+            max_logs_scanned: 100000,
+            admin_rpc_enabled: false,
+            call_from_required: false,
+            max_tx_gas_limit: MAX_BLOCK_GAS_LIMIT,
+            max_latest_blocks: 100,
+            max_account_range_results: 256,
+        }
+    }
+}
+
+/// The handlers of the servers started by `run_jsonrpc_server`,
+/// pass them to `stop_jsonrpc_server` to shut the servers down gracefully.
+pub struct ServerHandlers {
+    pub http: Option<HttpServerHandle>,
+    pub ws: Option<WsServerHandle>,
+
+    /// The address the http clients connect to, the TLS one if enabled
+    pub http_addr: Option<SocketAddr>,
+
+    /// The address the ws clients connect to, the TLS one if enabled
+    pub ws_addr: Option<SocketAddr>,
 
+    // the TLS listeners in front of the servers
+    tls_proxies: Vec<JoinHandle<()>>,
+}
+
+// the plaintext servers behind the TLS listeners
+const TLS_INNER_ADDRESS: &str = "127.0.0.1:0";
+
+/// Serve plaintext `http://` and `ws://` if `tls` is `None`,
+/// or `https://` and `wss://` at the same listening addresses otherwise.
+///
+/// Both servers follow the `config`, the ones started by other calls are not affected.
 pub async fn run_jsonrpc_server<Adapter: APIAdapter + 'static>(
     adapter: Arc<Adapter>,
     client_version: &str,
     http_listening_address: Option<&str>,
     ws_listening_address: Option<&str>,
+    tls: Option<&TlsConfig>,
+    config: &RpcConfig,
 ) -> Result<ServerHandlers> {
-    let mut ret = (None, None);
-
-    let mut rpc = impls::Web3RpcImpl::new(Arc::clone(&adapter)).into_rpc();
+    let mut ret = ServerHandlers {
+        http: None,
+        ws: None,
+        http_addr: None,
+        ws_addr: None,
+        tls_proxies: vec![],
+    };
+
+    let mut rpc =
+        impls::Web3RpcImpl::new(Arc::clone(&adapter), config.clone()).into_rpc();
     let node_rpc = impls::NodeRpcImpl::new(client_version).into_rpc();
     let filter = impls::filter_module(Arc::clone(&adapter)).into_rpc();
 
@@ -223,22 +437,132 @@ pub async fn run_jsonrpc_server<Adapter: APIAdapter + 'static>(
     rpc.merge(filter).unwrap();
 
     if let Some(addr) = http_listening_address {
-        let server = HttpServerBuilder::new().build(addr).await.c(d!())?;
+        let server = HttpServerBuilder::new()
+            .build(alt!(tls.is_some(), TLS_INNER_ADDRESS, addr))
+            .await
+            .c(d!())?;
+
+        let mut local_addr = server.local_addr().c(d!())?;
+        if let Some(tls) = tls {
+            let (addr, proxy) =
+                tls::spawn_tls_proxy(addr, local_addr, tls).await.c(d!())?;
+            local_addr = addr;
+            ret.tls_proxies.push(proxy);
+        }
 
-        ret.0 = Some(server.start(rpc.clone()).c(d!())?);
+        ret.http = Some(server.start(rpc.clone()).c(d!())?);
+        ret.http_addr = Some(local_addr);
     }
 
     if let Some(addr) = ws_listening_address {
-        let server = WsServerBuilder::new()
-            .set_id_provider(HexIdProvider::default())
-            .build(addr)
+        let server = ws_server_builder(config.max_subscriptions_per_connection)
+            .build(alt!(tls.is_some(), TLS_INNER_ADDRESS, addr))
             .await
             .c(d!())?;
 
+        let mut local_addr = server.local_addr().c(d!())?;
+        if let Some(tls) = tls {
+            let (addr, proxy) =
+                tls::spawn_tls_proxy(addr, local_addr, tls).await.c(d!())?;
+            local_addr = addr;
+            ret.tls_proxies.push(proxy);
+        }
+
         rpc.merge(ws_subscription_module(adapter).await).unwrap();
 
-        ret.1 = Some(server.start(rpc).c(d!())?)
+        ret.ws = Some(server.start(rpc).c(d!())?);
+        ret.ws_addr = Some(local_addr);
     }
 
     Ok(ret)
 }
+
+/// Stop the servers started by `run_jsonrpc_server`,
+/// and wait until their in-flight requests are drained.
+///
+/// The TLS listeners are closed at first, so no new connection is accepted,
+/// and their addresses can be bound again once this returns.
+pub async fn stop_jsonrpc_server(handlers: ServerHandlers) -> Result<()> {
+    for proxy in handlers.tls_proxies {
+        proxy.abort();
+        if let Err(e) = proxy.await {
+            if !e.is_cancelled() {
+                return Err(eg!("the TLS listener failed: {}", e));
+            }
+        }
+    }
+
+    if let Some(hdr) = handlers.http {
+        hdr.stop().c(d!())?.await.c(d!())?;
+    }
+    if let Some(hdr) = handlers.ws {
+        hdr.stop().c(d!())?.await.c(d!())?;
+    }
+    Ok(())
+}
+
+// The subscriptions are tracked per connection by the server,
+// they are released on `eth_unsubscribe` or when the connection is closed.
+fn ws_server_builder(max_subscriptions_per_connection: u32) -> WsServerBuilder {
+    WsServerBuilder::new()
+        .set_id_provider(HexIdProvider::default())
+        .max_subscriptions_per_connection(max_subscriptions_per_connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpsee::{
+        core::client::{Subscription, SubscriptionClientT},
+        rpc_params,
+        ws_client::WsClientBuilder,
+        ws_server::{RpcModule, SubscriptionSink},
+    };
+    use parking_lot::Mutex;
+
+    #[tokio::test]
+    async fn test_max_subscriptions_per_connection() {
+        // keep the sinks alive, or the subscriptions will be closed at once
+        let mut module = RpcModule::new(Mutex::new(Vec::<SubscriptionSink>::new()));
+        module
+            .register_subscription("sub", "notif", "unsub", |_, mut sink, ctx| {
+                sink.accept()?;
+                ctx.lock().push(sink);
+                Ok(())
+            })
+            .unwrap();
+
+        let server = ws_server_builder(2).build("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let hdr = server.start(module).unwrap();
+
+        let client = WsClientBuilder::default()
+            .build(format!("ws://{}", addr))
+            .await
+            .unwrap();
+
+        let mut subs = vec![];
+        for _ in 0..2 {
+            let sub: Subscription<serde_json::Value> = client
+                .subscribe("sub", rpc_params![], "unsub")
+                .await
+                .unwrap();
+            subs.push(sub);
+        }
+
+        let sub: StdResult<Subscription<serde_json::Value>, _> =
+            client.subscribe("sub", rpc_params![], "unsub").await;
+        assert!(sub.is_err());
+
+        // a new connection has its own quota
+        let client = WsClientBuilder::default()
+            .build(format!("ws://{}", addr))
+            .await
+            .unwrap();
+        let sub: StdResult<Subscription<serde_json::Value>, _> =
+            client.subscribe("sub", rpc_params![], "unsub").await;
+        assert!(sub.is_ok());
+
+        hdr.stop().unwrap();
+    }
+}
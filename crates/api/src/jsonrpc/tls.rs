@@ -0,0 +1,135 @@
+use ruc::*;
+use std::{fs::File, io::BufReader, net::SocketAddr, sync::Arc};
+use tokio::{
+    io::copy_bidirectional,
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+
+/// Serve `https://` and `wss://` instead of the plaintext protocols,
+/// both of the files must be in the PEM format.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// The certificate chain, the end-entity certificate first
+    pub cert_path: String,
+
+    /// A PKCS#8, RSA(PKCS#1) or SEC1 private key
+    pub key_path: String,
+}
+
+impl TlsConfig {
+    pub fn new(cert_path: impl Into<String>, key_path: impl Into<String>) -> Self {
+        Self {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+        }
+    }
+
+    fn acceptor(&self) -> Result<TlsAcceptor> {
+        let certs = load_certs(&self.cert_path).c(d!())?;
+        let key = load_key(&self.key_path).c(d!())?;
+
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .c(d!())?;
+
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>> {
+    let mut rd = BufReader::new(File::open(path).c(d!(path))?);
+    let certs = rustls_pemfile::certs(&mut rd).c(d!(path))?;
+    if certs.is_empty() {
+        return Err(eg!("No certificates found in {}", path));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_key(path: &str) -> Result<PrivateKey> {
+    let mut rd = BufReader::new(File::open(path).c(d!(path))?);
+    while let Some(item) = rustls_pemfile::read_one(&mut rd).c(d!(path))? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(k)
+            | rustls_pemfile::Item::RSAKey(k)
+            | rustls_pemfile::Item::ECKey(k) => return Ok(PrivateKey(k)),
+            _ => {}
+        }
+    }
+    Err(eg!("No private keys found in {}", path))
+}
+
+// The jsonrpc servers of jsonrpsee can only serve the plaintext protocols,
+// so they are bound to a loopback address, and the TLS connections accepted
+// at `addr` are decrypted here and forwarded to them.
+//
+// Abort the returned task to close the listener at `addr`,
+// the connections being forwarded are not affected.
+pub(crate) async fn spawn_tls_proxy(
+    addr: &str,
+    inner: SocketAddr,
+    tls: &TlsConfig,
+) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let acceptor = tls.acceptor().c(d!())?;
+    let listener = TcpListener::bind(addr).await.c(d!())?;
+    let local_addr = listener.local_addr().c(d!())?;
+
+    let proxy = tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((s, _)) => s,
+                Err(_) => continue,
+            };
+
+            // a failed handshake or upstream only affects its own connection,
+            // and nothing is dialed upstream before the handshake succeeds
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let mut stream = match acceptor.accept(stream).await {
+                    Ok(s) => s,
+                    Err(_) => return,
+                };
+                let mut upstream = match TcpStream::connect(inner).await {
+                    Ok(s) => s,
+                    Err(_) => return,
+                };
+                let _ = copy_bidirectional(&mut stream, &mut upstream).await;
+            });
+        }
+    });
+
+    Ok((local_addr, proxy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tls_config() {
+        let cert =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("rt-evm-tls-{}", fastrand::u64(..)));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("cert.pem").to_string_lossy().into_owned();
+        let key_path = dir.join("key.pem").to_string_lossy().into_owned();
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+        assert!(TlsConfig::new(&cert_path, &key_path).acceptor().is_ok());
+        assert!(TlsConfig::new(&cert_path, "/no/such/key.pem")
+            .acceptor()
+            .is_err());
+        assert!(TlsConfig::new(&key_path, &key_path).acceptor().is_err());
+        assert!(TlsConfig::new(&cert_path, &cert_path).acceptor().is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,12 +1,15 @@
-use std::fmt;
+use std::{collections::BTreeMap, fmt};
 
 use serde::de::{Error, MapAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use rt_evm_executor::decode_revert_msg;
 use rt_evm_model::codec::ProtocolCodec;
 use rt_evm_model::types::{
-    AccessList, Block, Bloom, Bytes, Hash, Header, Hex, Public, Receipt,
-    SignedTransaction, H160, H256, H64, MAX_PRIORITY_FEE_PER_GAS, U256, U64,
+    AccessList, Account, AccountState, Block, Bloom, Bytes, CheckPointConfig, ExitError,
+    ExitReason, Hash, Header, Hex, Log, LogsLimit, Public, Receipt, SignedTransaction,
+    StateDiff, StateOverride, TxResp, UnsignedTransaction, H160, H256, H64, RLP_NULL,
+    U256, U64,
 };
 
 pub const EMPTY_UNCLE_HASH: H256 = H256([
@@ -70,6 +73,9 @@ pub struct Web3Transaction {
     pub chain_id: Option<U256>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub standard_v: Option<U256>,
+    // only for the typed transactions, equal to the `v`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y_parity: Option<U64>,
     pub v: U256,
     pub r: U256,
     pub s: U256,
@@ -78,7 +84,34 @@ pub struct Web3Transaction {
 impl From<SignedTransaction> for Web3Transaction {
     fn from(stx: SignedTransaction) -> Web3Transaction {
         let signature = stx.transaction.signature.clone().unwrap_or_default();
-        let is_eip1559 = stx.transaction.unsigned.is_eip1559();
+        let unsigned = &stx.transaction.unsigned;
+
+        // the fee market fields only exist in the EIP-1559 transactions,
+        // NOTE: the `gas_price` of an EIP-1559 transaction is its `max_fee_per_gas`
+        let (max_fee_per_gas, max_priority_fee_per_gas) = match unsigned {
+            UnsignedTransaction::Eip1559(tx) => {
+                (Some(tx.gas_price), Some(tx.max_priority_fee_per_gas))
+            }
+            _ => (None, None),
+        };
+
+        // the legacy transactions have no access list
+        let access_list = if unsigned.is_legacy() {
+            None
+        } else {
+            Some(unsigned.access_list())
+        };
+
+        // the legacy transactions carry the EIP-155 `v`,
+        // the typed ones carry the bare recovery id as both `v` and `yParity`
+        let (v, y_parity) = if unsigned.is_legacy() {
+            let chain_id = Some(stx.transaction.chain_id);
+            (signature.add_chain_replay_protection(chain_id), None)
+        } else {
+            let parity = signature.standard_v as u64;
+            (parity, Some(parity.into()))
+        };
+
         Web3Transaction {
             type_: Some(stx.type_().into()),
             block_number: None,
@@ -86,28 +119,21 @@ impl From<SignedTransaction> for Web3Transaction {
             raw: Hex::encode(stx.transaction.encode().unwrap()),
             public_key: stx.public,
             gas: U256::zero(),
-            gas_price: stx.transaction.unsigned.gas_price(),
-            max_fee_per_gas: if is_eip1559 {
-                Some(U256::from(MAX_PRIORITY_FEE_PER_GAS))
-            } else {
-                None
-            },
-            max_priority_fee_per_gas: if is_eip1559 {
-                Some(*stx.transaction.unsigned.max_priority_fee_per_gas())
-            } else {
-                None
-            },
+            gas_price: unsigned.gas_price(),
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
             hash: stx.transaction.hash,
             from: stx.sender,
             to: stx.get_to(),
-            input: Hex::encode(stx.transaction.unsigned.data()),
-            nonce: *stx.transaction.unsigned.nonce(),
+            input: Hex::encode(unsigned.data()),
+            nonce: *unsigned.nonce(),
             transaction_index: None,
-            value: *stx.transaction.unsigned.value(),
-            access_list: Some(stx.transaction.unsigned.access_list()),
+            value: *unsigned.value(),
+            access_list,
             chain_id: Some(stx.transaction.chain_id.into()),
             standard_v: None,
-            v: signature.standard_v.into(),
+            y_parity,
+            v: v.into(),
             r: signature.r.as_slice().into(),
             s: signature.s.as_slice().into(),
         }
@@ -117,39 +143,12 @@ impl From<SignedTransaction> for Web3Transaction {
 impl From<(SignedTransaction, Receipt)> for Web3Transaction {
     fn from(stx_receipt: (SignedTransaction, Receipt)) -> Self {
         let (stx, receipt) = stx_receipt;
-        let signature = stx.transaction.signature.clone().unwrap_or_default();
-        let is_eip1559 = stx.transaction.unsigned.is_eip1559();
         Web3Transaction {
-            type_: Some(stx.type_().into()),
             block_number: Some(receipt.block_number.into()),
             block_hash: Some(receipt.block_hash),
-            raw: Hex::encode(stx.transaction.encode().unwrap()),
-            public_key: stx.public,
             gas: receipt.used_gas,
-            gas_price: stx.transaction.unsigned.gas_price(),
-            max_fee_per_gas: if is_eip1559 {
-                Some(U256::from(MAX_PRIORITY_FEE_PER_GAS))
-            } else {
-                None
-            },
-            max_priority_fee_per_gas: if is_eip1559 {
-                Some(*stx.transaction.unsigned.max_priority_fee_per_gas())
-            } else {
-                None
-            },
-            hash: receipt.tx_hash,
-            from: stx.sender,
-            to: stx.get_to(),
-            input: Hex::encode(stx.transaction.unsigned.data()),
-            nonce: *stx.transaction.unsigned.nonce(),
             transaction_index: Some(receipt.tx_index.into()),
-            value: *stx.transaction.unsigned.value(),
-            access_list: Some(stx.transaction.unsigned.access_list()),
-            chain_id: Some(stx.transaction.chain_id.into()),
-            standard_v: None,
-            v: signature.standard_v.into(),
-            r: signature.r.as_slice().into(),
-            s: signature.s.as_slice().into(),
+            ..Web3Transaction::from(stx)
         }
     }
 }
@@ -271,11 +270,22 @@ pub struct Web3Block {
     pub uncles: Vec<H256>,
     pub transactions: Vec<FatTransactionOrHash>,
     pub size: Option<U256>,
+    pub mix_hash: H256,
     pub nonce: H64,
+    // EIP-4895, always empty,
+    // only for the post-Shanghai clients expecting these fields
+    pub withdrawals: Vec<Web3Withdrawal>,
+    pub withdrawals_root: H256,
 }
 
 impl From<Block> for Web3Block {
     fn from(b: Block) -> Self {
+        // the difficulty is fixed along the chain from block 1 on,
+        // enforced by `BlockMgmt`, and the genesis is not counted
+        let total_difficulty =
+            b.header.difficulty.saturating_mul(b.header.number.into());
+        let size = b.size().into();
+
         Web3Block {
             hash: b.hash(),
             number: b.header.number.into(),
@@ -288,11 +298,11 @@ impl From<Block> for Web3Block {
             receipts_root: b.header.receipts_root,
             miner: b.header.proposer,
             difficulty: b.header.difficulty,
-            total_difficulty: Some(U256::zero()),
+            total_difficulty: Some(total_difficulty),
             seal_fields: vec![],
             base_fee_per_gas: b.header.base_fee_per_gas,
             extra_data: Hex::encode(&b.header.extra_data),
-            size: Some(b.header.size().into()),
+            size: Some(size),
             gas_limit: b.header.gas_limit,
             gas_used: b.header.gas_used,
             timestamp: b.header.timestamp.into(),
@@ -302,11 +312,24 @@ impl From<Block> for Web3Block {
                 .map(FatTransactionOrHash::Hash)
                 .collect(),
             uncles: vec![],
+            mix_hash: b.header.mixed_hash.unwrap_or_default(),
             nonce: H64::default(),
+            withdrawals: vec![],
+            // no withdrawals, so always the root of an empty trie
+            withdrawals_root: RLP_NULL,
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3Withdrawal {
+    pub index: U64,
+    pub validator_index: U64,
+    pub address: H160,
+    pub amount: U64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TransactionCondition {
     #[serde(rename = "block")]
@@ -334,6 +357,253 @@ pub struct Web3CallRequest {
     pub max_priority_fee_per_gas: Option<U256>,
 }
 
+/// The account fields replaced before a `rt_callMany` bundle, the absent ones are kept.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct Web3StateOverride {
+    pub balance: Option<U256>,
+    pub nonce: Option<U256>,
+    pub code: Option<Hex>,
+    #[serde(default)]
+    pub state_diff: BTreeMap<H256, H256>,
+}
+
+/// The changed fields and slots of an account, like the `diffMode` of the `prestateTracer`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3AccountState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<Hex>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<H256, H256>,
+}
+
+impl From<AccountState> for Web3AccountState {
+    fn from(s: AccountState) -> Self {
+        Web3AccountState {
+            balance: s.balance,
+            nonce: s.nonce,
+            code: s.code.map(Hex::encode),
+            storage: s.storage,
+        }
+    }
+}
+
+/// The changes made by a transaction, the values before and after it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Web3StateDiff {
+    pub pre: BTreeMap<H160, Web3AccountState>,
+    pub post: BTreeMap<H160, Web3AccountState>,
+}
+
+impl From<StateDiff> for Web3StateDiff {
+    fn from(d: StateDiff) -> Self {
+        Web3StateDiff {
+            pre: d.pre.into_iter().map(|(a, s)| (a, s.into())).collect(),
+            post: d.post.into_iter().map(|(a, s)| (a, s.into())).collect(),
+        }
+    }
+}
+
+impl From<Web3StateOverride> for StateOverride {
+    fn from(o: Web3StateOverride) -> Self {
+        StateOverride {
+            balance: o.balance,
+            nonce: o.nonce,
+            code: o.code.map(|c| c.as_bytes().to_vec()),
+            state_diff: o.state_diff,
+        }
+    }
+}
+
+/// The activation heights of the protocol changes, `None` for the inactive ones.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3CheckpointConfig {
+    /// From which the empty blocks use `RLP_NULL` as their transactions and receipts roots
+    pub empty_root_height: Option<U64>,
+
+    /// From which the logs of one transaction are limited
+    pub max_logs_per_tx: Option<Web3LogsLimit>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3LogsLimit {
+    pub height: U64,
+    pub limit: U64,
+    // "fail" or "cap"
+    pub mode: String,
+}
+
+impl From<(u64, LogsLimit)> for Web3LogsLimit {
+    fn from((height, limit): (u64, LogsLimit)) -> Self {
+        let (limit, mode) = match limit {
+            LogsLimit::Fail(n) => (n, "fail"),
+            LogsLimit::Cap(n) => (n, "cap"),
+        };
+        Web3LogsLimit {
+            height: height.into(),
+            limit: (limit as u64).into(),
+            mode: mode.to_owned(),
+        }
+    }
+}
+
+impl From<CheckPointConfig> for Web3CheckpointConfig {
+    fn from(cp: CheckPointConfig) -> Self {
+        Web3CheckpointConfig {
+            empty_root_height: cp.empty_root_height.map(Into::into),
+            max_logs_per_tx: cp.max_logs_per_tx.map(Into::into),
+        }
+    }
+}
+
+/// The result of one call of a `rt_callMany` bundle.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3CallResult {
+    pub success: bool,
+    pub return_data: Hex,
+    pub gas_used: U256,
+    pub logs: Vec<Web3Log>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<TxResp> for Web3CallResult {
+    fn from(resp: TxResp) -> Self {
+        let error = match &resp.exit_reason {
+            ExitReason::Succeed(_) => None,
+            ExitReason::Revert(_) => Some(decode_revert_msg(&resp.ret)),
+            ExitReason::Error(e) => Some(format!("{:?}", e)),
+            ExitReason::Fatal(e) => Some(format!("{:?}", e)),
+        };
+
+        Web3CallResult {
+            success: error.is_none(),
+            return_data: Hex::encode(&resp.ret),
+            gas_used: resp.gas_used.into(),
+            logs: resp.logs.into_iter().map(unmined_log).collect(),
+            error,
+        }
+    }
+}
+
+// a log of a call or a tx not in any block
+fn unmined_log(log: Log) -> Web3Log {
+    Web3Log {
+        address: log.address,
+        topics: log.topics,
+        data: Hex::encode(log.data),
+        block_hash: None,
+        block_number: None,
+        transaction_hash: None,
+        transaction_index: None,
+        log_index: None,
+        block_timestamp: None,
+        removed: false,
+    }
+}
+
+/// The JSON form of an execution result, for the debug and trace APIs,
+/// the `error` is one of the messages of geth if there is an equivalent.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3TxResp {
+    pub failed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    pub output: Hex,
+    pub gas_used: U256,
+    // only for a successful contract creation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_address: Option<H160>,
+    pub logs: Vec<Web3Log>,
+}
+
+impl From<TxResp> for Web3TxResp {
+    fn from(resp: TxResp) -> Self {
+        let error = exit_error(&resp.exit_reason);
+        let revert_reason = match &resp.exit_reason {
+            ExitReason::Revert(_) => Some(decode_revert_msg(&resp.ret)),
+            _ => None,
+        };
+        let contract_address = match error {
+            None => resp.code_address.map(Into::into),
+            Some(_) => None,
+        };
+
+        Web3TxResp {
+            failed: error.is_some(),
+            error,
+            revert_reason,
+            output: Hex::encode(&resp.ret),
+            gas_used: resp.gas_used.into(),
+            contract_address,
+            logs: resp.logs.into_iter().map(unmined_log).collect(),
+        }
+    }
+}
+
+// the same messages as geth for the equivalent errors, `None` for a success
+fn exit_error(reason: &ExitReason) -> Option<String> {
+    let msg = match reason {
+        ExitReason::Succeed(_) => return None,
+        ExitReason::Revert(_) => "execution reverted",
+        ExitReason::Error(e) => match e {
+            ExitError::OutOfGas => "out of gas",
+            ExitError::OutOfFund => "insufficient balance for transfer",
+            ExitError::OutOfOffset => "return data out of bounds",
+            ExitError::StackUnderflow => "stack underflow",
+            ExitError::StackOverflow => "stack limit reached 1024",
+            ExitError::InvalidJump => "invalid jump destination",
+            ExitError::DesignatedInvalid => "invalid opcode: INVALID",
+            ExitError::CallTooDeep => "max call depth exceeded",
+            ExitError::CreateCollision => "contract address collision",
+            ExitError::CreateContractLimit => "max code size exceeded",
+            ExitError::Other(msg) => return Some(msg.to_string()),
+            e => return Some(format!("{:?}", e)),
+        },
+        ExitReason::Fatal(e) => return Some(format!("fatal: {:?}", e)),
+    };
+    Some(msg.to_owned())
+}
+
+/// The result of `rt_callWithGas`, a reverted call is also a result.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3CallWithGas {
+    pub output: Hex,
+    pub gas_used: U256,
+    pub reverted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+}
+
+impl From<TxResp> for Web3CallWithGas {
+    fn from(resp: TxResp) -> Self {
+        Web3TxResp::from(resp).into()
+    }
+}
+
+impl From<Web3TxResp> for Web3CallWithGas {
+    fn from(resp: Web3TxResp) -> Self {
+        Web3CallWithGas {
+            output: resp.output,
+            gas_used: resp.gas_used,
+            reverted: resp.revert_reason.is_some(),
+            revert_reason: resp.revert_reason,
+        }
+    }
+}
+
 #[derive(Clone, Default, Debug, PartialEq, Eq, Hash)]
 pub enum BlockId {
     Num(u64),
@@ -510,6 +780,9 @@ pub struct Web3Filter {
     #[serde(default)]
     pub address: MultiType<H160>,
     pub topics: Option<Vec<MultiNestType<Hash>>>,
+    /// Only the logs of this transaction, the block range is ignored if set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_hash: Option<H256>,
 }
 
 #[derive(Default, PartialEq, Eq, Debug, Clone)]
@@ -633,6 +906,7 @@ pub struct Web3Log {
     pub transaction_hash: Option<H256>,
     pub transaction_index: Option<U256>,
     pub log_index: Option<U256>,
+    pub block_timestamp: Option<U256>,
     #[serde(default)]
     pub removed: bool,
 }
@@ -663,13 +937,96 @@ pub struct SyncStatus {
     pub highest_block: U256,
 }
 
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Web3TxPoolStatus {
+    pub pending: U256,
+    pub queued: U256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Web3TxStatus {
+    // included in a block
+    Mined,
+    // waiting in the mempool
+    Pending,
+    // dropped from the mempool without being mined
+    Dropped,
+    // never seen, or dropped too long ago
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3BlockRoots {
+    pub state_root: H256,
+    pub receipts_root: H256,
+    pub transactions_root: H256,
+}
+
+impl From<&Header> for Web3BlockRoots {
+    fn from(h: &Header) -> Self {
+        Web3BlockRoots {
+            state_root: h.state_root,
+            receipts_root: h.receipts_root,
+            transactions_root: h.transactions_root,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3AccountRange {
+    pub accounts: Vec<Web3RangeAccount>,
+    // the start key of the next page, `None` if the end has been reached
+    pub next: Option<H256>,
+}
+
+/// A page of the txs sent or received by an address, from the oldest one.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3TxHistory {
+    // the number of all the indexed txs of the address
+    pub total: U64,
+    pub transactions: Vec<H256>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3RangeAccount {
+    // keccak256(address)
+    pub key: H256,
+    pub address: H160,
+    pub balance: U256,
+    pub nonce: U256,
+    // the storage root
+    pub root: H256,
+    pub code_hash: H256,
+}
+
+impl From<(H256, H160, Account)> for Web3RangeAccount {
+    fn from((key, address, account): (H256, H160, Account)) -> Self {
+        Web3RangeAccount {
+            key,
+            address,
+            balance: account.balance,
+            nonce: account.nonce,
+            root: account.storage_root,
+            code_hash: account.code_hash,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Web3FeeHistory {
     pub oldest_block: U256,
-    pub reward: Option<Vec<U256>>,
+    // one list of the rewards at the requested percentiles for each block
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reward: Option<Vec<Vec<U256>>>,
+    // one more than the blocks, the last one is for the next block
     pub base_fee_per_gas: Vec<U256>,
-    pub gas_used_ratio: Vec<U256>,
+    pub gas_used_ratio: Vec<f64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -734,6 +1091,68 @@ pub struct RawLoggerFilter {
 mod tests {
     use super::*;
 
+    use rt_evm_model::types::{
+        AccessListItem, Eip1559Transaction, Eip2930Transaction, Hasher,
+        LegacyTransaction, Log, SignatureComponents, TransactionAction,
+        UnverifiedTransaction,
+    };
+
+    #[test]
+    fn test_tx_resp_json() {
+        use rt_evm_model::types::{ExitRevert, ExitSucceed};
+
+        let log = Log {
+            address: H160::repeat_byte(1),
+            topics: vec![H256::repeat_byte(2)],
+            data: vec![3],
+        };
+        let created = H160::repeat_byte(0xcc);
+        let resp = |exit_reason, ret: Vec<u8>| TxResp {
+            exit_reason,
+            ret,
+            gas_used: 21000,
+            logs: vec![log.clone()],
+            code_address: Some(created.into()),
+            ..Default::default()
+        };
+        let json = |r: TxResp| serde_json::to_value(Web3TxResp::from(r)).unwrap();
+
+        let ok = json(resp(ExitReason::Succeed(ExitSucceed::Returned), vec![1, 2]));
+        assert_eq!(Some(false), ok["failed"].as_bool());
+        assert!(ok.get("error").is_none());
+        assert!(ok.get("revertReason").is_none());
+        assert_eq!("0x0102", ok["output"]);
+        assert_eq!("0x5208", ok["gasUsed"]);
+        assert_eq!(
+            serde_json::to_value(created).unwrap(),
+            ok["contractAddress"]
+        );
+        assert_eq!("0x03", ok["logs"][0]["data"]);
+
+        let reverted = json(resp(ExitReason::Revert(ExitRevert::Reverted), vec![]));
+        assert_eq!(Some(true), reverted["failed"].as_bool());
+        assert_eq!("execution reverted", reverted["error"]);
+        assert_eq!(decode_revert_msg(&[]), reverted["revertReason"]);
+        assert!(reverted.get("contractAddress").is_none());
+
+        let oog = json(resp(ExitReason::Error(ExitError::OutOfGas), vec![]));
+        assert_eq!(Some(true), oog["failed"].as_bool());
+        assert_eq!("out of gas", oog["error"]);
+        assert!(oog.get("revertReason").is_none());
+        assert!(oog.get("contractAddress").is_none());
+
+        let other = json(TxResp::invalid_nonce(21000, U256::zero()));
+        assert_eq!("invalid nonce", other["error"]);
+
+        // the same view for `rt_callWithGas`
+        let r = Web3CallWithGas::from(resp(
+            ExitReason::Revert(ExitRevert::Reverted),
+            vec![],
+        ));
+        assert!(r.reverted);
+        assert_eq!(Some(decode_revert_msg(&[])), r.revert_reason);
+    }
+
     #[test]
     fn test_sync_status_json() {
         let status = Web3SyncStatus::False;
@@ -748,4 +1167,307 @@ mod tests {
         let json = json::parse(&serde_json::to_string(&status).unwrap()).unwrap();
         assert!(json.is_object());
     }
+
+    #[test]
+    fn test_state_override_json() {
+        let json = serde_json::json!({
+            "balance": "0x10",
+            "code": "0x6000",
+            "stateDiff": { format!("{:?}", H256::zero()): H256::repeat_byte(7) },
+        });
+        let o: StateOverride = serde_json::from_value::<Web3StateOverride>(json)
+            .unwrap()
+            .into();
+
+        assert_eq!(Some(U256::from(16)), o.balance);
+        assert_eq!(None, o.nonce);
+        assert_eq!(Some(vec![0x60, 0]), o.code);
+        assert_eq!(Some(&H256::repeat_byte(7)), o.state_diff.get(&H256::zero()));
+
+        assert!(serde_json::from_value::<Web3StateOverride>(
+            serde_json::json!({ "storage": {} })
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_block_difficulty_and_size() {
+        let block = Block::mock(9527, 100, H256::random(), 0);
+        let size = block.size();
+
+        let web3_block = Web3Block::from(block);
+        assert_eq!(web3_block.total_difficulty, Some(web3_block.number));
+        assert_eq!(web3_block.size, Some(size.into()));
+    }
+
+    #[test]
+    fn test_block_uncles_json() {
+        // keccak256(rlp([]))
+        assert_eq!(EMPTY_UNCLE_HASH, Hasher::digest([0xc0u8]));
+
+        let block = Web3Block::from(Block::mock(9527, 1, H256::random(), 0));
+        let json = json::parse(&serde_json::to_string(&block).unwrap()).unwrap();
+
+        assert!(json["uncles"].is_array());
+        assert!(json["uncles"].is_empty());
+        assert_eq!(
+            json["sha3Uncles"].as_str(),
+            Some("0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347")
+        );
+    }
+
+    #[test]
+    fn test_block_withdrawals_json() {
+        let block = Web3Block::from(Block::mock(9527, 1, H256::random(), 0));
+        let json = json::parse(&serde_json::to_string(&block).unwrap()).unwrap();
+
+        assert!(json["withdrawals"].is_array());
+        assert!(json["withdrawals"].is_empty());
+        assert_eq!(
+            json["withdrawalsRoot"].as_str(),
+            Some("0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+        );
+    }
+
+    #[test]
+    fn test_tx_status_json() {
+        assert_eq!(
+            serde_json::to_string(&Web3TxStatus::Mined).unwrap(),
+            r#""mined""#
+        );
+        assert_eq!(
+            serde_json::to_string(&Web3TxStatus::Dropped).unwrap(),
+            r#""dropped""#
+        );
+    }
+
+    #[test]
+    fn test_block_roots() {
+        let mut block = Block::mock(9527, 1, H256::random(), 0);
+        block.header.receipts_root = H256::random();
+        block.header.transactions_root = H256::random();
+
+        let roots = Web3BlockRoots::from(&block.header);
+        let web3_block = Web3Block::from(block);
+        assert_eq!(roots.state_root, web3_block.state_root);
+        assert_eq!(roots.receipts_root, web3_block.receipts_root);
+        assert_eq!(roots.transactions_root, web3_block.transactions_root);
+
+        let json = json::parse(&serde_json::to_string(&roots).unwrap()).unwrap();
+        assert!(json["stateRoot"].is_string());
+        assert!(json["receiptsRoot"].is_string());
+        assert!(json["transactionsRoot"].is_string());
+    }
+
+    #[test]
+    fn test_log_block_timestamp() {
+        let block = Block::mock(9527, 1, H256::random(), 1_680_000_000);
+        let receipt = Receipt {
+            block_number: block.header.number,
+            block_hash: block.hash(),
+            logs: vec![Log {
+                address: H160::random(),
+                topics: vec![H256::random()],
+                data: vec![1, 2, 3],
+            }],
+            ..Default::default()
+        };
+
+        let mut logs = vec![];
+        crate::jsonrpc::impls::from_receipt_to_web3_log(
+            0,
+            &[],
+            &[],
+            &receipt,
+            block.header.timestamp,
+            &mut logs,
+        );
+
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].block_timestamp, Some(block.header.timestamp.into()));
+
+        let json = json::parse(&serde_json::to_string(&logs[0]).unwrap()).unwrap();
+        assert_eq!(json["blockTimestamp"].as_str(), Some("0x6422c400"));
+    }
+
+    #[test]
+    fn test_omitted_block_id_is_latest() {
+        assert_eq!(BlockId::default(), BlockId::Latest);
+
+        // an omitted block tag arrives as `None`
+        let id = serde_json::from_str::<Option<BlockId>>("null").unwrap();
+        assert_eq!(id, None);
+        assert_eq!(Option::<u64>::from(id.unwrap_or_default()), None);
+
+        let id = serde_json::from_str::<Option<BlockId>>(r#""latest""#).unwrap();
+        assert_eq!(Option::<u64>::from(id.unwrap_or_default()), None);
+
+        // NOT the same as the genesis
+        let id = serde_json::from_str::<Option<BlockId>>(r#""earliest""#).unwrap();
+        assert_eq!(Option::<u64>::from(id.unwrap_or_default()), Some(0));
+    }
+
+    #[test]
+    fn test_account_range_json() {
+        let range = Web3AccountRange {
+            accounts: vec![Web3RangeAccount::from((
+                H256::repeat_byte(1),
+                H160::repeat_byte(2),
+                Account {
+                    nonce: 3.into(),
+                    balance: 4.into(),
+                    storage_root: H256::repeat_byte(5),
+                    code_hash: H256::repeat_byte(6),
+                },
+            ))],
+            next: None,
+        };
+        let json = json::parse(&serde_json::to_string(&range).unwrap()).unwrap();
+
+        assert_eq!(json["accounts"].len(), 1);
+        assert_eq!(json["accounts"][0]["nonce"].as_str(), Some("0x3"));
+        assert_eq!(json["accounts"][0]["balance"].as_str(), Some("0x4"));
+        assert!(json["accounts"][0].has_key("codeHash"));
+        // the end of the state
+        assert!(json["next"].is_null());
+    }
+
+    fn mock_signed_tx(unsigned: UnsignedTransaction) -> SignedTransaction {
+        SignedTransaction {
+            transaction: UnverifiedTransaction {
+                unsigned,
+                signature: Some(SignatureComponents {
+                    r: vec![1; 32],
+                    s: vec![2; 32],
+                    standard_v: 1,
+                }),
+                chain_id: 9527,
+                hash: H256::random(),
+            },
+            sender: H160::random(),
+            public: None,
+        }
+    }
+
+    fn mock_access_list() -> AccessList {
+        vec![AccessListItem {
+            address: H160::random(),
+            storage_keys: vec![H256::random()],
+        }]
+    }
+
+    #[test]
+    fn test_legacy_tx_json() {
+        let stx = mock_signed_tx(UnsignedTransaction::Legacy(LegacyTransaction {
+            nonce: U256::zero(),
+            gas_price: 10.into(),
+            gas_limit: 21000.into(),
+            action: TransactionAction::Call(H160::random()),
+            value: U256::zero(),
+            data: vec![],
+        }));
+        let tx = Web3Transaction::from(stx);
+        let json = json::parse(&serde_json::to_string(&tx).unwrap()).unwrap();
+
+        assert_eq!(json["type"].as_str(), Some("0x0"));
+        assert_eq!(json["chainId"].as_str(), Some("0x2537"));
+        assert_eq!(json["gasPrice"].as_str(), Some("0xa"));
+        assert!(!json.has_key("yParity"));
+        // 1 + 35 + 9527 * 2
+        assert_eq!(json["v"].as_str(), Some("0x4a92"));
+        assert!(!json.has_key("accessList"));
+        assert!(!json.has_key("maxFeePerGas"));
+        assert!(!json.has_key("maxPriorityFeePerGas"));
+    }
+
+    #[test]
+    fn test_eip2930_tx_json() {
+        let stx = mock_signed_tx(UnsignedTransaction::Eip2930(Eip2930Transaction {
+            nonce: U256::zero(),
+            gas_price: 10.into(),
+            gas_limit: 21000.into(),
+            action: TransactionAction::Call(H160::random()),
+            value: U256::zero(),
+            data: vec![],
+            access_list: mock_access_list(),
+        }));
+        let tx = Web3Transaction::from(stx);
+        let json = json::parse(&serde_json::to_string(&tx).unwrap()).unwrap();
+
+        assert_eq!(json["type"].as_str(), Some("0x1"));
+        assert_eq!(json["chainId"].as_str(), Some("0x2537"));
+        assert_eq!(json["yParity"].as_str(), Some("0x1"));
+        assert_eq!(json["v"].as_str(), Some("0x1"));
+        assert_eq!(json["accessList"].len(), 1);
+        assert!(!json.has_key("maxFeePerGas"));
+        assert!(!json.has_key("maxPriorityFeePerGas"));
+    }
+
+    #[test]
+    fn test_eip1559_tx_json() {
+        let stx = mock_signed_tx(UnsignedTransaction::Eip1559(Eip1559Transaction {
+            nonce: U256::zero(),
+            max_priority_fee_per_gas: 2.into(),
+            gas_price: 10.into(),
+            gas_limit: 21000.into(),
+            action: TransactionAction::Create,
+            value: U256::zero(),
+            data: vec![],
+            access_list: mock_access_list(),
+        }));
+        let tx = Web3Transaction::from(stx);
+        let json = json::parse(&serde_json::to_string(&tx).unwrap()).unwrap();
+
+        assert_eq!(json["type"].as_str(), Some("0x2"));
+        assert_eq!(json["chainId"].as_str(), Some("0x2537"));
+        assert_eq!(json["yParity"].as_str(), Some("0x1"));
+        assert_eq!(json["v"].as_str(), Some("0x1"));
+        assert_eq!(json["accessList"].len(), 1);
+        assert_eq!(json["maxFeePerGas"].as_str(), Some("0xa"));
+        assert_eq!(json["maxPriorityFeePerGas"].as_str(), Some("0x2"));
+        assert!(json["to"].is_null());
+    }
+
+    #[test]
+    fn test_state_diff_json() {
+        let (sender, contract) = (H160::repeat_byte(1), H160::repeat_byte(0xcc));
+        let mut diff = StateDiff::default();
+        diff.pre.insert(
+            sender,
+            AccountState {
+                balance: Some(U256::from(100)),
+                ..Default::default()
+            },
+        );
+        diff.post.insert(
+            sender,
+            AccountState {
+                balance: Some(U256::from(79)),
+                ..Default::default()
+            },
+        );
+        diff.post.insert(
+            contract,
+            AccountState {
+                storage: [(H256::zero(), H256::repeat_byte(7))].into_iter().collect(),
+                ..Default::default()
+            },
+        );
+
+        let json = serde_json::to_value(Web3StateDiff::from(diff)).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "pre": {
+                    format!("{:?}", sender): { "balance": "0x64" }
+                },
+                "post": {
+                    format!("{:?}", sender): { "balance": "0x4f" },
+                    format!("{:?}", contract): {
+                        "storage": { format!("{:?}", H256::zero()): H256::repeat_byte(7) }
+                    }
+                }
+            }),
+            json
+        );
+    }
 }
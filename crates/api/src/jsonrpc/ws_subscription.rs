@@ -118,6 +118,7 @@ where
 
         let mut log_vec = Vec::new();
         let latest_header_number = latest_block.header.number;
+        let latest_header_timestamp = latest_block.header.timestamp;
 
         // Send all header
         if !self.header_hubs.is_empty() {
@@ -129,7 +130,11 @@ where
                     .unwrap()
                     .unwrap();
 
-                log_vec.push((block.header.number, block.tx_hashes));
+                log_vec.push((
+                    block.header.number,
+                    block.header.timestamp,
+                    block.tx_hashes,
+                ));
 
                 let web3_header = Web3Header::from(block.header);
                 for hub in self.header_hubs.iter_mut() {
@@ -164,13 +169,21 @@ where
                         .unwrap()
                         .unwrap();
 
-                    log_vec.push((block.header.number, block.tx_hashes));
+                    log_vec.push((
+                        block.header.number,
+                        block.header.timestamp,
+                        block.tx_hashes,
+                    ));
                 }
             }
 
-            log_vec.push((latest_header_number, latest_block.tx_hashes));
+            log_vec.push((
+                latest_header_number,
+                latest_header_timestamp,
+                latest_block.tx_hashes,
+            ));
 
-            for (number, tx_hashes) in log_vec {
+            for (number, timestamp, tx_hashes) in log_vec {
                 let receipts = self
                     .adapter
                     .get_receipts_by_hashes(number, &tx_hashes)
@@ -187,6 +200,7 @@ where
                             hub.filter.topics.as_slice(),
                             hub.filter.address.as_ref().unwrap_or(&Vec::new()),
                             &receipt,
+                            timestamp,
                             &mut logs,
                         );
 
@@ -5,10 +5,14 @@ pub mod adapter;
 pub mod jsonrpc;
 
 pub use adapter::DefaultAPIAdapter;
-pub use jsonrpc::{run_jsonrpc_server, web3_types::SyncStatus, ServerHandlers};
+pub use jsonrpc::{
+    run_jsonrpc_server, stop_jsonrpc_server, web3_types::SyncStatus, RpcConfig,
+    ServerHandlers, TlsConfig,
+};
 
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
+
 static SYNC_STATUS: Lazy<RwLock<SyncStatus>> =
     Lazy::new(|| RwLock::new(Default::default()));
 
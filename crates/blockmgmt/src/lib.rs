@@ -3,21 +3,23 @@
 
 use rayon::prelude::*;
 use rt_evm_executor::{
-    logs_bloom, trie_root_indexed, trie_root_txs, RTEvmExecutor as Executor,
+    logs_bloom, trie_root_indexed_at, PrecompileCache, RTEvmExecutor as Executor,
     RTEvmExecutorAdapter as EvmExecBackend,
 };
 use rt_evm_mempool::Mempool;
 use rt_evm_model::{
-    traits::{BlockStorage as _, Executor as _, TxStorage as _},
+    lazy::{base_fee_per_gas, rayon_install},
+    traits::{BlockStorage as _, Executor as _},
     types::{
-        Block, ExecResp, ExecutorContext, FatBlock, FatBlockRef, Hash, Header,
-        MerkleRoot, Proposal, Receipt, SignedTransaction, BASE_FEE_PER_GAS, H160,
-        MAX_BLOCK_GAS_LIMIT, U256,
+        verify_signatures_batch, Block, Bytes, CheckPointConfig, ExecResp,
+        ExecutorContext, FatBlock, FatBlockRef, Hardfork, Hash, Header, MerkleRoot,
+        Proposal, Receipt, SignedTransaction, H160, MAX_BLOCK_GAS_LIMIT,
+        MAX_EXTRA_DATA_SIZE, U256, WORLD_STATE_META_KEY,
     },
 };
 use rt_evm_storage::{MptStore, Storage};
 use ruc::*;
-use std::sync::Arc;
+use std::{collections::BTreeSet, sync::Arc, time::Instant};
 
 pub struct BlockMgmt {
     pub proposer: H160,
@@ -36,6 +38,43 @@ pub struct BlockMgmt {
 
     pub chain_id: u64,
 
+    // the `difficulty` of the produced blocks, default to the one of the previous block,
+    // it means nothing to the consensus, only for the clients expecting PoW-era fields;
+    // it is fixed along the chain from block 1 on (the genesis always uses `1`),
+    // so the total difficulty of block `n` is `difficulty * n`,
+    // checked on both producing and verifying
+    pub difficulty: U256,
+
+    // if `true`, fill the `mixHash` of the produced blocks with the previous block hash,
+    // only for the clients rejecting an empty `mixHash`
+    pub fill_mixed_hash: bool,
+
+    // the rules of executing the transactions, default to `London`,
+    // all nodes of a network must use the same one at the same height
+    pub hardfork: Hardfork,
+
+    // the activation heights of the protocol changes, none of them by default,
+    // set by the runtime to its own one, all nodes of a network must use the same one
+    pub checkpoint: CheckPointConfig,
+
+    // the `extraData` of the produced blocks, empty by default,
+    // e.g. a client identifier, at most `MAX_EXTRA_DATA_SIZE` bytes
+    pub extra_data: Bytes,
+
+    // the proposers allowed to produce blocks, e.g. the validators of a PoA/PoS chain,
+    // `None` by default, it means that any proposer is allowed,
+    // checked on both producing and verifying
+    pub authorized_proposers: Option<BTreeSet<H160>>,
+
+    // if `true`, fail the block instead of executing it with a zero hash
+    // when `BLOCKHASH` can not find a block within the recent 256 ones,
+    // e.g. on a pruned node, `false` by default
+    pub strict_block_hash: bool,
+
+    // the results of the pure precompiles shared across the blocks,
+    // `None` by default, it does not change the results or the gas
+    pub precompile_cache: Option<Arc<PrecompileCache>>,
+
     pub mempool: Arc<Mempool>,
     pub trie: Arc<MptStore>,
     pub storage: Arc<Storage>,
@@ -57,6 +96,14 @@ impl BlockMgmt {
             block_number: 1 + latest_block_header.number,
             block_timestamp: timestamp,
             chain_id: latest_block_header.chain_id,
+            difficulty: latest_block_header.difficulty,
+            fill_mixed_hash: false,
+            hardfork: Hardfork::default(),
+            checkpoint: CheckPointConfig::default(),
+            extra_data: Bytes::new(),
+            authorized_proposers: None,
+            strict_block_hash: false,
+            precompile_cache: None,
             mempool,
             trie,
             storage,
@@ -64,38 +111,139 @@ impl BlockMgmt {
     }
 
     /// generate a new block and persist it
-    pub fn produce_block(&self, txs: Vec<SignedTransaction>) -> Result<Header> {
-        let (block, receipts) = self.generate_block(&txs).c(d!())?;
+    ///
+    /// The readers never see a block whose state is not fully persisted:
+    /// - the world state is committed to the trie during the execution
+    /// - `check_state_root` verifies that the new root can be restored
+    /// - the header is written at last as the commit marker of the block
+    /// - the block becomes the latest one only after its header is written
+    ///
+    /// So any (header, state root) pair a reader can find is complete,
+    /// and a reader at the latest height keeps using the root it resolved,
+    /// even if a newer block is committed in the middle of the read.
+    ///
+    /// Stop including txs once the `deadline` is exceeded,
+    /// e.g. the end of the slot of the consensus,
+    /// the txs not included are left in the mempool.
+    pub fn produce_block(
+        &self,
+        mut txs: Vec<SignedTransaction>,
+        deadline: Option<Instant>,
+    ) -> Result<Header> {
+        check_proposer(self.authorized_proposers.as_ref(), self.proposer).c(d!())?;
+
+        let (block, receipts, destructed) =
+            self.generate_block(&txs, deadline).c(d!())?;
+        txs.truncate(block.tx_hashes.len());
         let header = block.header.clone();
 
-        self.storage.insert_txs(header.number, txs).c(d!())?;
-        self.storage
-            .insert_receipts(header.number, receipts)
-            .c(d!())?;
-        self.storage.set_block(block).c(d!())?;
+        self.check_state_root(header.state_root).c(d!())?;
+
+        self.storage.persist_block(block, txs, receipts).c(d!())?;
+
+        // the new block no longer refers to them,
+        // a failure only leaves the nodes unreleased
+        for (address, root) in destructed {
+            info_omit!(self
+                .storage
+                .release_storage_trie(&self.trie, address, root)
+                .c(d!()));
+        }
 
         Ok(header)
     }
 
+    // Make sure that the world state can be restored at the new root,
+    // a block whose state is not fully persisted must not be persisted.
+    fn check_state_root(&self, state_root: MerkleRoot) -> Result<()> {
+        let state = self
+            .trie
+            .trie_restore(&WORLD_STATE_META_KEY, state_root.into())
+            .c(d!("the world state can not be restored"))?;
+
+        // reading any key will load the root node from the backend
+        state
+            .get(self.proposer.as_bytes())
+            .c(d!("the world state is incomplete"))
+            .map(|_| ())
+    }
+
     fn generate_block(
         &self,
         txs: &[SignedTransaction],
+        deadline: Option<Instant>,
+    ) -> Result<(Block, Vec<Receipt>, Vec<(H160, MerkleRoot)>)> {
+        let ret = self.execute_block(txs, deadline, false).c(d!())?;
+        self.mempool.tx_cleanup(&txs[..ret.0.tx_hashes.len()]);
+        Ok(ret)
+    }
+
+    /// execute the transactions and generate a new block,
+    /// but do NOT clean up the mempool or persist anything into the storage,
+    /// useful for 'what-if' block building and verification
+    ///
+    /// The txs are executed in a simulated backend,
+    /// see `RTEvmExecutorAdapter::simulated`, so the persisted state,
+    /// e.g. the storage of a destructed contract, is left untouched.
+    ///
+    /// NOTE: only the txs executed before the `deadline` are included
+    pub fn generate_block_speculative(
+        &self,
+        txs: &[SignedTransaction],
+        deadline: Option<Instant>,
     ) -> Result<(Block, Vec<Receipt>)> {
+        self.execute_block(txs, deadline, true)
+            .c(d!())
+            .map(|(block, receipts, _)| (block, receipts))
+    }
+
+    fn execute_block(
+        &self,
+        txs: &[SignedTransaction],
+        deadline: Option<Instant>,
+        simulated: bool,
+    ) -> Result<(Block, Vec<Receipt>, Vec<(H160, MerkleRoot)>)> {
         let proposal = self.generate_proposal(txs).c(d!())?;
 
         let executor_ctx = ExecutorContext::from(&proposal);
-        let mut evm_exec_backend = EvmExecBackend::from_root(
+        let evm_exec_backend = EvmExecBackend::from_root(
             self.prev_state_root,
             &self.trie,
             &self.storage,
             executor_ctx,
         )
         .c(d!())?;
-        let exec_resp = Executor.exec(&mut evm_exec_backend, txs);
+        let mut evm_exec_backend =
+            alt!(simulated, evm_exec_backend.simulated(), evm_exec_backend);
+        let exec_resp = Executor::new(self.hardfork)
+            .with_checkpoint(self.checkpoint)
+            .with_precompile_cache(self.precompile_cache.clone())
+            .with_deadline(deadline)
+            .exec(&mut evm_exec_backend, txs);
+
+        let missing = evm_exec_backend.take_missing_block_hashes();
+        if self.strict_block_hash && !missing.is_empty() {
+            return Err(eg!("the blocks {:?} are unavailable to BLOCKHASH", missing));
+        }
 
-        self.mempool.tx_cleanup(txs);
+        // the deadline has been exceeded
+        let txs = &txs[..exec_resp.txs_resp.len()];
+        let proposal = if txs.len() < proposal.tx_hashes.len() {
+            self.generate_proposal(txs).c(d!())?
+        } else {
+            proposal
+        };
+
+        let prev_header = self
+            .storage
+            .get_block_header(self.block_number - 1)
+            .c(d!())?
+            .c(d!())?;
+        check_difficulty(&prev_header, self.difficulty).c(d!())?;
+
+        let mut block = Block::new(proposal, &exec_resp);
+        block.header.difficulty = self.difficulty;
 
-        let block = Block::new(proposal, &exec_resp);
         let receipts = generate_receipts(
             self.block_number,
             block.hash(),
@@ -104,22 +252,32 @@ impl BlockMgmt {
             &exec_resp,
         );
 
-        Ok((block, receipts))
+        let destructed = evm_exec_backend.take_destructed_storages();
+
+        Ok((block, receipts, destructed))
     }
 
     pub fn generate_proposal(&self, txs: &[SignedTransaction]) -> Result<Proposal> {
+        check_extra_data(&self.extra_data).c(d!())?;
+
+        let tx_hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
         let p = Proposal {
             prev_hash: self.prev_block_hash,
             proposer: self.proposer,
-            transactions_root: trie_root_txs(txs),
+            transactions_root: trie_root_indexed_at(
+                &self.checkpoint,
+                self.block_number,
+                &tx_hashes,
+            ),
             timestamp: self.block_timestamp,
             number: self.block_number,
             gas_limit: MAX_BLOCK_GAS_LIMIT.into(),
-            extra_data: Default::default(),
-            mixed_hash: None,
-            base_fee_per_gas: BASE_FEE_PER_GAS.into(),
+            extra_data: self.extra_data.clone(),
+            mixed_hash: alt!(self.fill_mixed_hash, Some(self.prev_block_hash), None),
+            base_fee_per_gas: base_fee_per_gas(),
             chain_id: self.chain_id,
-            tx_hashes: txs.iter().map(|tx| tx.transaction.hash).collect(),
+            tx_hashes,
         };
 
         Ok(p)
@@ -130,7 +288,15 @@ impl BlockMgmt {
     }
 
     pub fn verify_refblock(&self, fb: FatBlockRef) -> Result<()> {
-        self.verify_proposal(&fb.block.into(), fb.txs).c(d!())
+        self.verify_proposal(&Proposal::from_block_full(fb.block), fb.txs)
+            .c(d!())?;
+
+        let prev_header = self
+            .storage
+            .get_block_header(fb.block.header.number - 1)
+            .c(d!())?
+            .c(d!())?;
+        check_difficulty(&prev_header, fb.block.header.difficulty).c(d!())
     }
 
     pub fn verify_proposal(
@@ -150,6 +316,9 @@ impl BlockMgmt {
             return Err(eg!());
         }
 
+        check_proposer(self.authorized_proposers.as_ref(), p.proposer).c(d!())?;
+        check_extra_data(&p.extra_data).c(d!())?;
+
         let prev_header = self
             .storage
             .get_block_header(p.number - 1)
@@ -160,8 +329,9 @@ impl BlockMgmt {
             return Err(eg!());
         }
 
-        let txs_root = trie_root_indexed(&p.tx_hashes.iter().collect::<Vec<_>>());
-        if txs_root != p.transactions_root {
+        if trie_root_indexed_at(&self.checkpoint, p.number, &p.tx_hashes)
+            != p.transactions_root
+        {
             return Err(eg!());
         }
 
@@ -169,15 +339,55 @@ impl BlockMgmt {
             return Err(eg!());
         }
 
-        (0..txs.len())
-            .into_par_iter()
-            .map(|i| (&txs[i], &p.tx_hashes[i]))
-            .try_for_each(|(tx, hash_in_block)| {
-                if &tx.transaction.hash != hash_in_block {
-                    return Err(eg!("Hash mismatch"));
-                }
-                self.mempool.tx_pre_check(tx, false).c(d!())
-            })
+        verify_signatures_batch(txs).c(d!())?;
+
+        rayon_install(|| {
+            (0..txs.len())
+                .into_par_iter()
+                .map(|i| (&txs[i], &p.tx_hashes[i]))
+                .try_for_each(|(tx, hash_in_block)| {
+                    if &tx.transaction.hash != hash_in_block {
+                        return Err(eg!("Hash mismatch"));
+                    }
+                    self.mempool.tx_pre_check(tx, true).c(d!())
+                })
+        })
+    }
+}
+
+pub fn check_extra_data(extra_data: &[u8]) -> Result<()> {
+    if extra_data.len() > MAX_EXTRA_DATA_SIZE {
+        return Err(eg!(
+            "The extra data exceeds {} bytes: {}",
+            MAX_EXTRA_DATA_SIZE,
+            extra_data.len()
+        ));
+    }
+    Ok(())
+}
+
+// The genesis always uses `1`, so the difficulty of block 1 is free,
+// any later block must use the one of its parent.
+pub fn check_difficulty(prev_header: &Header, difficulty: U256) -> Result<()> {
+    if 0 < prev_header.number && prev_header.difficulty != difficulty {
+        return Err(eg!(
+            "The difficulty is fixed along the chain: {}, got {}",
+            prev_header.difficulty,
+            difficulty
+        ));
+    }
+    Ok(())
+}
+
+pub fn check_proposer(
+    authorized_proposers: Option<&BTreeSet<H160>>,
+    proposer: H160,
+) -> Result<()> {
+    match authorized_proposers {
+        Some(set) if !set.contains(&proposer) => {
+            Err(eg!("The proposer is not authorized: {:?}", proposer))
+        }
+        _ => Ok(()),
     }
 }
 
@@ -213,3 +423,129 @@ fn generate_receipts(
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rt_evm_model::types::{Hasher, NIL_HASH, RLP_NULL};
+
+    #[test]
+    fn test_extra_data() {
+        assert!(check_extra_data(&[]).is_ok());
+        assert!(check_extra_data(&[7; MAX_EXTRA_DATA_SIZE]).is_ok());
+        assert!(check_extra_data(&[7; MAX_EXTRA_DATA_SIZE + 1]).is_err());
+
+        let extra_data = b"rt-evm/v0.1.0".to_vec();
+        let proposal = Proposal {
+            extra_data: extra_data.clone(),
+            ..Default::default()
+        };
+        let exec_resp = ExecResp {
+            state_root: Default::default(),
+            transaction_root: Default::default(),
+            receipt_root: Default::default(),
+            gas_used: 0,
+            fee_used: U256::zero(),
+            txs_resp: vec![],
+        };
+
+        let block = Block::new(proposal, &exec_resp);
+        assert_eq!(extra_data, block.header.extra_data);
+    }
+
+    #[test]
+    fn test_check_proposer() {
+        let proposer = H160::repeat_byte(1);
+
+        // no validation
+        assert!(check_proposer(None, proposer).is_ok());
+        assert!(check_proposer(None, H160::zero()).is_ok());
+
+        let authorized = [proposer].into_iter().collect::<BTreeSet<_>>();
+        assert!(check_proposer(Some(&authorized), proposer).is_ok());
+        assert!(check_proposer(Some(&authorized), H160::repeat_byte(2)).is_err());
+        assert!(check_proposer(Some(&authorized), H160::zero()).is_err());
+
+        // nobody can produce blocks
+        assert!(check_proposer(Some(&BTreeSet::new()), proposer).is_err());
+    }
+
+    #[test]
+    fn test_check_difficulty() {
+        let mut prev_header =
+            Block::genesis(9527, Default::default(), 0, &Default::default()).header;
+
+        // block 1 may use any difficulty
+        assert!(check_difficulty(&prev_header, U256::one()).is_ok());
+        assert!(check_difficulty(&prev_header, 7.into()).is_ok());
+
+        prev_header.number = 1;
+        prev_header.difficulty = 7.into();
+        assert!(check_difficulty(&prev_header, 7.into()).is_ok());
+        assert!(check_difficulty(&prev_header, U256::one()).is_err());
+        assert!(check_difficulty(&prev_header, 8.into()).is_err());
+    }
+
+    #[test]
+    fn test_empty_transactions_root() {
+        assert_eq!(RLP_NULL, Hasher::digest([0x80u8]));
+
+        // the config is passed explicitly, no global state is involved
+        let legacy = CheckPointConfig::default();
+        let cp = CheckPointConfig {
+            empty_root_height: Some(100),
+            ..Default::default()
+        };
+        let root_at = |height, input: &[Hash]| trie_root_indexed_at(&cp, height, input);
+
+        let empty: &[Hash] = &[];
+        let tx_hashes = [Hasher::digest(b"tx")];
+        let root = root_at(100, &tx_hashes);
+        assert_ne!(RLP_NULL, root);
+        assert_eq!(root, trie_root_indexed_at(&legacy, 100, &tx_hashes));
+
+        // the legacy one
+        assert_eq!(NIL_HASH, trie_root_indexed_at(&legacy, 100, empty));
+
+        assert_eq!(NIL_HASH, root_at(99, empty));
+        assert_eq!(RLP_NULL, root_at(100, empty));
+        assert_eq!(root, root_at(100, &tx_hashes));
+
+        // an empty block passes the checks of its txs
+        let proposal = Proposal {
+            number: 100,
+            transactions_root: root_at(100, empty),
+            ..Default::default()
+        };
+        let block = Block::new(
+            proposal.clone(),
+            &ExecResp {
+                state_root: Default::default(),
+                transaction_root: Default::default(),
+                receipt_root: root_at(100, empty),
+                gas_used: 0,
+                fee_used: U256::zero(),
+                txs_resp: vec![],
+            },
+        );
+        assert_eq!(RLP_NULL, block.header.transactions_root);
+        assert_eq!(RLP_NULL, block.header.receipts_root);
+        assert_eq!(
+            proposal.transactions_root,
+            root_at(proposal.number, &proposal.tx_hashes)
+        );
+
+        // so is the genesis
+        let cp = CheckPointConfig {
+            empty_root_height: Some(0),
+            ..Default::default()
+        };
+        let genesis = Block::genesis(9527, Default::default(), 0, &cp);
+        assert_eq!(RLP_NULL, genesis.header.transactions_root);
+        assert_eq!(RLP_NULL, genesis.header.receipts_root);
+
+        let genesis = Block::genesis(9527, Default::default(), 0, &legacy);
+        assert_eq!(NIL_HASH, genesis.header.transactions_root);
+        assert_eq!(NIL_HASH, genesis.header.receipts_root);
+    }
+}
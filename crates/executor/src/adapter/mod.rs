@@ -1,30 +1,73 @@
 use evm::backend::{Apply, Basic};
+use parking_lot::Mutex;
 use rt_evm_model::{
     codec::ProtocolCodec,
     traits::{ApplyBackend, Backend, BlockStorage, ExecutorAdapter, TxStorage},
     types::{
-        Account, ExecutorContext, Hasher, Log, MerkleRoot, Proposal, H160, H256,
-        NIL_HASH, U256, WORLD_STATE_META_KEY,
+        Account, ExecutorContext, Hash, Hasher, Log, MerkleRoot, H160, H256, NIL_HASH,
+        U256, WORLD_STATE_META_KEY,
     },
 };
 use rt_evm_storage::{
-    get_account_by_state, save_account_by_state,
+    get_account_by_state,
     trie_db::{MptOnce, MptStore},
-    Storage,
+    trie_restore, Storage,
 };
 use ruc::*;
-use std::mem;
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 const GET_BLOCK_HASH_NUMBER_RANGE: u64 = 256;
 
+static MISSING_BLOCK_HASHES: AtomicU64 = AtomicU64::new(0);
+
+/// How many times a block within the range of `BLOCKHASH` has not been found
+/// since the process started, it should always be zero on an unpruned node.
+pub fn missing_block_hash_cnt() -> u64 {
+    MISSING_BLOCK_HASHES.load(Ordering::Relaxed)
+}
+
+// the height whose hash is visible to `BLOCKHASH` at the `current` height
+fn block_hash_height(current: U256, number: U256) -> Option<u64> {
+    if number >= current || current - number > U256::from(GET_BLOCK_HASH_NUMBER_RANGE) {
+        return None;
+    }
+    Some(number.as_u64())
+}
+
 type WorldStateMpt = MptOnce;
 type GlobalState = WorldStateMpt;
 
 pub struct RTEvmExecutorAdapter<'a> {
     state: GlobalState,
+    // the root of the last commit, `None` if a new trie has never been committed
+    committed_root: Option<MerkleRoot>,
+    // the writes to the `state` since the last commit, `None` means a removal
+    pending: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
     trie_db: &'a MptStore,
     storage: &'a Storage,
     exec_ctx: ExecutorContext,
+
+    // the known results of `exists` in the current `state`,
+    // kept in sync with every write to the `state`
+    exists_cache: Mutex<HashMap<H160, bool>>,
+
+    // never persisted as a block, see `simulated`
+    simulated: bool,
+
+    // the codes written in a simulation, kept out of the storage
+    sim_codes: HashMap<Hash, Vec<u8>>,
+
+    // the storage tries dropped by the deleted or reset accounts,
+    // released only after the block has been persisted
+    destructed_storages: Vec<(H160, MerkleRoot)>,
+
+    // the heights within the range of `BLOCKHASH` that could not be found,
+    // a zero hash has been returned for each of them
+    missing_block_hashes: Mutex<Vec<u64>>,
 }
 
 impl<'a> ExecutorAdapter for RTEvmExecutorAdapter<'a> {
@@ -45,7 +88,11 @@ impl<'a> ExecutorAdapter for RTEvmExecutorAdapter<'a> {
     }
 
     fn commit(&mut self) -> MerkleRoot {
-        self.state.commit().into()
+        RTEvmExecutorAdapter::commit(self)
+    }
+
+    fn current_root(&self) -> MerkleRoot {
+        RTEvmExecutorAdapter::current_root(self)
     }
 
     fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
@@ -57,7 +104,9 @@ impl<'a> ExecutorAdapter for RTEvmExecutorAdapter<'a> {
     }
 
     fn save_account(&mut self, address: H160, account: &Account) {
-        pnk!(save_account_by_state(&mut self.state, address, account))
+        self.index_account(address);
+        self.state_insert(address.as_bytes(), pnk!(account.encode()).to_vec());
+        self.exists_cache.lock().insert(address, true);
     }
 }
 
@@ -75,19 +124,27 @@ impl<'a> Backend for RTEvmExecutorAdapter<'a> {
     }
 
     fn block_hash(&self, number: U256) -> H256 {
-        let current_number = self.block_number();
-        if number >= current_number {
-            return H256::default();
-        }
+        let number = match block_hash_height(self.block_number(), number) {
+            Some(n) => n,
+            None => return H256::default(),
+        };
 
-        if (current_number - number) > U256::from(GET_BLOCK_HASH_NUMBER_RANGE) {
-            return H256::default();
+        // the `Backend` can not return an error,
+        // the miss is recorded for the caller to decide, see `take_missing_block_hashes`
+        match self
+            .storage
+            .get_block_hash(number)
+            .c(d!())
+            .and_then(|h| h.c(d!("the block {} is unavailable to BLOCKHASH", number)))
+        {
+            Ok(hash) => hash,
+            Err(e) => {
+                info_omit!(Err::<(), _>(e));
+                MISSING_BLOCK_HASHES.fetch_add(1, Ordering::Relaxed);
+                self.missing_block_hashes.lock().push(number);
+                H256::default()
+            }
         }
-
-        let number = number.as_u64();
-        let res = pnk!(self.storage.get_block(number));
-
-        res.map(|b| Proposal::from(&b).hash()).unwrap_or_default()
     }
 
     fn block_coinbase(&self) -> H160 {
@@ -115,7 +172,18 @@ impl<'a> Backend for RTEvmExecutorAdapter<'a> {
     }
 
     fn exists(&self, address: H160) -> bool {
-        self.state.contains(address.as_bytes()).unwrap_or_default()
+        if let Some(exists) = self.exists_cache.lock().get(&address) {
+            return *exists;
+        }
+
+        // errors are not cached, they will be retried on the next query
+        match self.state.contains(address.as_bytes()) {
+            Ok(exists) => {
+                self.exists_cache.lock().insert(address, exists);
+                exists
+            }
+            Err(_) => false,
+        }
     }
 
     fn basic(&self, address: H160) -> Basic {
@@ -147,6 +215,10 @@ impl<'a> Backend for RTEvmExecutorAdapter<'a> {
             return Vec::new();
         }
 
+        if let Some(c) = self.sim_codes.get(&code_hash) {
+            return c.clone();
+        }
+
         let res = pnk!(self.storage.get_code_by_hash(&code_hash));
 
         res.unwrap_or_default()
@@ -164,12 +236,12 @@ impl<'a> Backend for RTEvmExecutorAdapter<'a> {
                     if storage_root == NIL_HASH {
                         Ok(H256::default())
                     } else {
-                        self.trie_db
-                            .trie_restore(address.as_bytes(), storage_root.into())
-                            .map(|trie| match trie.get(index.as_bytes()) {
+                        trie_restore(self.trie_db, address.as_bytes(), storage_root).map(
+                            |trie| match trie.get(index.as_bytes()) {
                                 Ok(Some(res)) => H256::from_slice(res.as_ref()),
                                 _ => H256::default(),
-                            })
+                            },
+                        )
                     }
                 })
                 .unwrap_or_default()
@@ -203,13 +275,11 @@ impl<'a> ApplyBackend for RTEvmExecutorAdapter<'a> {
                     let is_empty =
                         self.apply(address, basic, code, storage, reset_storage);
                     if is_empty && delete_empty {
-                        self.trie_db.trie_remove(address.as_bytes());
-                        pnk!(self.state.remove(address.as_bytes()));
+                        self.delete_account(address);
                     }
                 }
                 Apply::Delete { address } => {
-                    self.trie_db.trie_remove(address.as_bytes());
-                    pnk!(self.state.remove(address.as_bytes()));
+                    self.delete_account(address);
                 }
             }
         }
@@ -219,6 +289,60 @@ impl<'a> ApplyBackend for RTEvmExecutorAdapter<'a> {
 }
 
 impl<'a> RTEvmExecutorAdapter<'a> {
+    // the simulations must not grow the persisted index,
+    // or anyone calling `eth_call` could fill it with arbitrary addresses
+    fn index_account(&self, address: H160) {
+        if !self.simulated {
+            pnk!(self.storage.index_account(address));
+        }
+    }
+
+    // The storage trie is still referenced by the previous states,
+    // so its backend is kept as is, and the trie is released later,
+    // see `take_destructed_storages`.
+    fn delete_account(&mut self, address: H160) {
+        let account = pnk!(get_account_by_state(&self.state, address));
+        self.destruct_storage(address, account.storage_root);
+        self.state_remove(address.as_bytes());
+        self.exists_cache.lock().insert(address, false);
+    }
+
+    // every write to the `state` goes through these two,
+    // so `current_root` can replay the uncommitted ones
+    fn state_insert(&mut self, key: &[u8], value: Vec<u8>) {
+        pnk!(self.state.insert(key, &value));
+        self.pending.insert(key.to_vec(), Some(value));
+    }
+
+    fn state_remove(&mut self, key: &[u8]) {
+        pnk!(self.state.remove(key));
+        self.pending.insert(key.to_vec(), None);
+    }
+
+    fn destruct_storage(&mut self, address: H160, storage_root: MerkleRoot) {
+        if !self.simulated && NIL_HASH != storage_root {
+            self.destructed_storages.push((address, storage_root));
+        }
+    }
+
+    /// The storage tries dropped by the deleted or reset accounts since the last call,
+    /// release them by `Storage::release_storage_trie` after the block is persisted,
+    /// not before, or a block failing afterwards would leave them released.
+    ///
+    /// Always empty in the simulations.
+    pub fn take_destructed_storages(&mut self) -> Vec<(H160, MerkleRoot)> {
+        mem::take(&mut self.destructed_storages)
+    }
+
+    /// The heights within the range of `BLOCKHASH` not found since the last call,
+    /// a zero hash has been returned for each of them.
+    ///
+    /// Always empty on an unpruned node, a block executed with any of them
+    /// may diverge from the other nodes, see `BlockMgmt::strict_block_hash`.
+    pub fn take_missing_block_hashes(&mut self) -> Vec<u64> {
+        mem::take(self.missing_block_hashes.get_mut())
+    }
+
     pub fn new(
         trie_db: &'a MptStore,
         storage: &'a Storage,
@@ -227,9 +351,16 @@ impl<'a> RTEvmExecutorAdapter<'a> {
         let state = trie_db.trie_create(&WORLD_STATE_META_KEY, false).c(d!())?;
         Ok(RTEvmExecutorAdapter {
             state,
+            committed_root: None,
+            pending: BTreeMap::new(),
             trie_db,
             storage,
             exec_ctx,
+            exists_cache: Mutex::new(HashMap::new()),
+            simulated: false,
+            sim_codes: HashMap::new(),
+            destructed_storages: vec![],
+            missing_block_hashes: Mutex::new(vec![]),
         })
     }
 
@@ -239,18 +370,37 @@ impl<'a> RTEvmExecutorAdapter<'a> {
         storage: &'a Storage,
         exec_ctx: ExecutorContext,
     ) -> Result<Self> {
-        let state = trie_db
-            .trie_restore(&WORLD_STATE_META_KEY, state_root.into())
-            .c(d!())?;
+        let state = trie_restore(trie_db, &WORLD_STATE_META_KEY, state_root).c(d!())?;
 
         Ok(RTEvmExecutorAdapter {
             state,
+            committed_root: Some(state_root),
+            pending: BTreeMap::new(),
             trie_db,
             storage,
             exec_ctx,
+            exists_cache: Mutex::new(HashMap::new()),
+            simulated: false,
+            sim_codes: HashMap::new(),
+            destructed_storages: vec![],
+            missing_block_hashes: Mutex::new(vec![]),
         })
     }
 
+    /// For the simulations, e.g. `eth_call` bundles or replaying a historical block.
+    ///
+    /// The storage tries of the deleted or recreated accounts are not recorded
+    /// for the release, see `take_destructed_storages`,
+    /// the written codes are kept in memory instead of the storage,
+    /// and the written accounts are not recorded for `get_account_range`.
+    ///
+    /// So nothing reachable from the persisted data is changed,
+    /// only the new trie nodes are written, and no persisted root refers to them.
+    pub fn simulated(mut self) -> Self {
+        self.simulated = true;
+        self
+    }
+
     pub fn apply<I: IntoIterator<Item = (H256, H256)>>(
         &mut self,
         address: H160,
@@ -272,16 +422,21 @@ impl<'a> RTEvmExecutorAdapter<'a> {
             ),
         };
 
+        // The backends of the storage tries are never reset,
+        // the previous states may still refer to the nodes in them,
+        // a new empty trie is created in the existing backend instead.
         let storage_trie = if reset_storage {
-            self.trie_db.trie_create(address.as_bytes(), true).c(d!())
+            if existing {
+                self.destruct_storage(address, old_account.storage_root);
+            }
+            self.trie_db.trie_create(address.as_bytes(), false).c(d!())
         } else if existing {
             self.trie_db
                 .trie_restore(address.as_bytes(), old_account.storage_root.into())
                 .c(d!())
         } else {
-            // this address does not exist in the world state,
-            // so we reset it in the trie backend db also.
-            self.trie_db.trie_create(address.as_bytes(), true).c(d!(
+            // this address does not exist in the world state
+            self.trie_db.trie_create(address.as_bytes(), false).c(d!(
                 "{}, {:?}",
                 address,
                 address.as_bytes()
@@ -291,6 +446,9 @@ impl<'a> RTEvmExecutorAdapter<'a> {
         let mut storage_trie = pnk!(storage_trie);
 
         storage.into_iter().for_each(|(k, v)| {
+            if !self.simulated {
+                pnk!(self.storage.index_storage_slot(address, k));
+            }
             let _ = storage_trie.insert(k.as_bytes(), v.as_bytes());
         });
 
@@ -304,21 +462,225 @@ impl<'a> RTEvmExecutorAdapter<'a> {
         if let Some(c) = code {
             let new_code_hash = Hasher::digest(&c);
             if new_code_hash != old_account.code_hash {
-                pnk!(self.storage.insert_code(address.into(), new_code_hash, c));
+                if self.simulated {
+                    self.sim_codes.insert(new_code_hash, c);
+                } else {
+                    pnk!(self.storage.insert_code(address.into(), new_code_hash, c));
+                }
                 new_account.code_hash = new_code_hash;
             }
         }
 
         let bytes = pnk!(new_account.encode());
 
-        pnk!(self.state.insert(address.as_bytes(), bytes.as_ref()));
+        self.index_account(address);
+        self.state_insert(address.as_bytes(), bytes.to_vec());
+        self.exists_cache.lock().insert(address, true);
 
         new_account.balance == U256::zero()
             && new_account.nonce == U256::zero()
             && new_account.code_hash.is_zero()
     }
 
+    /// Finalize all the changes and return the new root.
     pub fn commit(&mut self) -> MerkleRoot {
-        self.state.commit().into()
+        let root = self.state.commit().into();
+        self.committed_root = Some(root);
+        self.pending.clear();
+        root
+    }
+
+    /// The root including the changes that have not been committed,
+    /// the same one as the next `commit` returns, the `state` is not touched.
+    ///
+    /// Read from the last commit if nothing has been written since then,
+    /// e.g. at the end of `exec`, which commits after each tx.
+    /// Otherwise the uncommitted writes are replayed onto a scratch trie,
+    /// its nodes are written to the backend like the ones of a simulation,
+    /// and no persisted root refers to them.
+    pub fn current_root(&self) -> MerkleRoot {
+        match self.committed_root {
+            Some(root) if self.pending.is_empty() => root,
+            _ => pnk!(self.working_root()),
+        }
+    }
+
+    fn working_root(&self) -> Result<MerkleRoot> {
+        let mut scratch = match self.committed_root {
+            Some(root) => {
+                trie_restore(self.trie_db, &WORLD_STATE_META_KEY, root).c(d!())?
+            }
+            None => self
+                .trie_db
+                .trie_create(&WORLD_STATE_META_KEY, false)
+                .c(d!())?,
+        };
+
+        for (k, v) in self.pending.iter() {
+            match v {
+                Some(v) => scratch.insert(k, v).c(d!())?,
+                None => scratch.remove(k).c(d!())?,
+            }
+        }
+
+        Ok(scratch.commit().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_hash_height() {
+        let at =
+            |current: u64, number: u64| block_hash_height(current.into(), number.into());
+
+        // the current and the future ones
+        assert_eq!(None, at(1000, 1000));
+        assert_eq!(None, at(1000, 1001));
+
+        // the recent 256 ones
+        assert_eq!(Some(999), at(1000, 999));
+        assert_eq!(Some(744), at(1000, 744));
+        assert_eq!(None, at(1000, 743));
+
+        assert_eq!(Some(0), at(1, 0));
+        assert_eq!(None, at(0, 0));
+    }
+
+    #[test]
+    fn test_missing_block_hash() {
+        let trie_db = MptStore::new();
+        let storage = Storage::default();
+        let exec_ctx = ExecutorContext {
+            block_number: 5.into(),
+            ..Default::default()
+        };
+        let mut backend =
+            RTEvmExecutorAdapter::new(&trie_db, &storage, exec_ctx).unwrap();
+
+        // out of range, zero without a miss
+        assert_eq!(H256::default(), backend.block_hash(5.into()));
+        assert!(backend.take_missing_block_hashes().is_empty());
+
+        // no block has been stored
+        let cnt = missing_block_hash_cnt();
+        assert_eq!(H256::default(), backend.block_hash(3.into()));
+        assert_eq!(H256::default(), backend.block_hash(4.into()));
+        assert_eq!(vec![3, 4], backend.take_missing_block_hashes());
+        assert!(backend.take_missing_block_hashes().is_empty());
+        assert!(missing_block_hash_cnt() >= cnt + 2);
+    }
+
+    #[test]
+    fn test_current_root() {
+        let trie_db = MptStore::new();
+        let storage = Storage::default();
+        let mut backend =
+            RTEvmExecutorAdapter::new(&trie_db, &storage, Default::default()).unwrap();
+
+        let (alice, bob) = (H160::repeat_byte(1), H160::repeat_byte(2));
+        let basic = |balance: u64| Basic {
+            balance: balance.into(),
+            nonce: U256::zero(),
+        };
+
+        // a new trie that has never been committed
+        backend.apply(alice, basic(1), None, vec![], false);
+        let working = backend.current_root();
+        assert_eq!(working, backend.current_root());
+        assert_eq!(working, backend.commit());
+        assert_eq!(working, backend.current_root());
+
+        // the final apply, with a storage write, is included without a commit
+        backend.apply(
+            bob,
+            basic(2),
+            Some(vec![0x00]),
+            vec![(H256::zero(), H256::from_low_u64_be(1))],
+            false,
+        );
+        let working = backend.current_root();
+        assert_ne!(working, backend.committed_root.unwrap());
+        assert_eq!(working, backend.commit());
+
+        // so is a removal
+        backend.delete_account(alice);
+        let working = backend.current_root();
+        assert_eq!(working, backend.commit());
+
+        let restored = RTEvmExecutorAdapter::from_root(
+            working,
+            &trie_db,
+            &storage,
+            Default::default(),
+        )
+        .unwrap();
+        assert!(restored.get(alice.as_bytes()).is_none());
+        assert_eq!(U256::from(2), restored.basic(bob).balance);
+    }
+
+    #[test]
+    fn test_exists_cache() {
+        let trie_db = MptStore::new();
+        let storage = Storage::default();
+        let mut backend =
+            RTEvmExecutorAdapter::new(&trie_db, &storage, Default::default()).unwrap();
+
+        let accounts = (1..=3u64).map(H160::from_low_u64_be).collect::<Vec<_>>();
+        let basic = |balance: u64| Basic {
+            balance: balance.into(),
+            nonce: U256::zero(),
+        };
+        fn check(backend: &RTEvmExecutorAdapter, accounts: &[H160]) {
+            for a in accounts.iter() {
+                assert_eq!(
+                    backend.state.contains(a.as_bytes()).unwrap(),
+                    backend.exists(*a)
+                );
+            }
+        }
+
+        // the misses are cached too
+        check(&backend, &accounts);
+        assert!(accounts.iter().all(|a| !backend.exists(*a)));
+
+        for a in accounts.iter() {
+            backend.apply(*a, basic(1), None, vec![], false);
+        }
+        check(&backend, &accounts);
+
+        // the ways an account is removed
+        backend.delete_account(accounts[0]);
+        ApplyBackend::apply(
+            &mut backend,
+            vec![Apply::Delete::<Vec<(H256, H256)>> {
+                address: accounts[1],
+            }],
+            vec![],
+            false,
+        );
+        check(&backend, &accounts);
+        assert!(!backend.exists(accounts[1]));
+        assert!(backend.exists(accounts[2]));
+
+        // inserted again after the removal
+        backend.apply(accounts[0], basic(2), None, vec![], false);
+        check(&backend, &accounts);
+
+        // a cold cache agrees with the warm one
+        let root = backend.commit();
+        let restored = RTEvmExecutorAdapter::from_root(
+            root,
+            &trie_db,
+            &storage,
+            Default::default(),
+        )
+        .unwrap();
+        check(&restored, &accounts);
+        for a in accounts.iter() {
+            assert_eq!(backend.exists(*a), restored.exists(*a));
+        }
     }
 }
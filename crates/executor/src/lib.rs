@@ -4,34 +4,57 @@
 
 pub mod adapter;
 mod precompiles;
+mod state_diff;
 mod utils;
 
-use crate::precompiles::build_precompile_set;
+pub use crate::precompiles::PrecompileCache;
 pub use crate::{
-    adapter::RTEvmExecutorAdapter,
+    adapter::{missing_block_hash_cnt, RTEvmExecutorAdapter},
+    state_diff::StateDiffRecorder,
     utils::{
-        code_address, decode_revert_msg, logs_bloom, trie_root_indexed, trie_root_txs,
+        code_address, decode_revert_msg, trie_root_indexed, trie_root_indexed_at,
+        trie_root_txs,
     },
 };
+use crate::{
+    precompiles::{build_precompile_set, Precompiles},
+    utils::eip6780_apply,
+};
 use evm::{
-    executor::stack::{
-        MemoryStackState, PrecompileFn, StackExecutor, StackSubstateMetadata,
-    },
+    executor::stack::{MemoryStackState, StackExecutor, StackSubstateMetadata},
     CreateScheme,
 };
+pub use rt_evm_model::types::logs_bloom;
 use rt_evm_model::{
     codec::ProtocolCodec,
     traits::{ApplyBackend, Backend, Executor, ExecutorAdapter as Adapter},
     types::{
-        data_gas_cost, Account, Config, ExecResp, Hasher, SignedTransaction,
-        TransactionAction, TxResp, GAS_CALL_TRANSACTION, GAS_CREATE_TRANSACTION, H160,
-        MIN_TRANSACTION_GAS_LIMIT, NIL_HASH, U256,
+        intrinsic_gas, Account, Apply, CheckPointConfig, Config, ExecResp, ExitError,
+        ExitReason, Hardfork, Hasher, Log, LogsLimit, SignedTransaction,
+        TransactionAction, TxResp, H160, H256, MIN_TRANSACTION_GAS_LIMIT, NIL_HASH,
+        U256,
     },
 };
-use std::collections::BTreeMap;
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+    time::Instant,
+};
 
-#[derive(Default)]
-pub struct RTEvmExecutor;
+#[derive(Clone)]
+pub struct RTEvmExecutor {
+    hardfork: Hardfork,
+    checkpoint: CheckPointConfig,
+    deadline: Option<Instant>,
+    clock: fn() -> Instant,
+    precompile_cache: Option<Arc<PrecompileCache>>,
+}
+
+impl Default for RTEvmExecutor {
+    fn default() -> Self {
+        Self::new(Hardfork::default())
+    }
+}
 
 impl Executor for RTEvmExecutor {
     // Used for query data API, this function will not modify the world state.
@@ -44,18 +67,14 @@ impl Executor for RTEvmExecutor {
         value: U256,
         data: Vec<u8>,
     ) -> TxResp {
-        let config = Config::london();
+        let config = self.hardfork.evm_config();
         let metadata = StackSubstateMetadata::new(gas_limit, &config);
         let state = MemoryStackState::new(metadata, backend);
-        let precompiles = build_precompile_set();
+        let precompiles = build_precompile_set(self.precompile_cache.clone());
         let mut executor =
             StackExecutor::new_with_precompiles(state, &config, &precompiles);
 
-        let base_gas = if to.is_some() {
-            GAS_CALL_TRANSACTION + data_gas_cost(&data)
-        } else {
-            GAS_CREATE_TRANSACTION + GAS_CALL_TRANSACTION + data_gas_cost(&data)
-        };
+        let base_gas = intrinsic_gas(to.is_none(), &data, &[]);
 
         let (exit, res) = if let Some(addr) = &to {
             executor.transact_call(
@@ -116,14 +135,21 @@ impl Executor for RTEvmExecutor {
         let mut receipt_hashes = Vec::with_capacity(txs_len);
 
         let (mut gas, mut fee) = (0u64, U256::zero());
-        let precompiles = build_precompile_set();
-        let config = Config::london();
+        let precompiles = build_precompile_set(self.precompile_cache.clone());
+        let config = self.hardfork.evm_config();
 
         for tx in txs.iter() {
-            backend.set_gas_price(tx.transaction.unsigned.gas_price());
-            backend.set_origin(tx.sender);
+            if matches!(self.deadline, Some(d) if d <= (self.clock)()) {
+                break;
+            }
 
-            let mut r = Self::evm_exec(backend, &config, &precompiles, tx);
+            let mut backend = CtxGuard::new(
+                &mut *backend,
+                tx.sender,
+                tx.transaction.unsigned.gas_price(),
+            );
+
+            let mut r = self.evm_exec(&mut *backend, &config, &precompiles, tx);
 
             backend.commit();
 
@@ -137,11 +163,15 @@ impl Executor for RTEvmExecutor {
             res.push(r);
         }
 
-        // Get the new root, the look-like `commit` is a noop here
-        let new_state_root = backend.commit();
+        // All changes have been committed after each tx,
+        // so just read the root without committing again
+        let new_state_root = backend.current_root();
 
-        let transaction_root = trie_root_indexed(&tx_hashes);
-        let receipt_root = trie_root_indexed(&receipt_hashes);
+        let height = backend.get_ctx().block_number.low_u64();
+        let transaction_root =
+            trie_root_indexed_at(&self.checkpoint, height, &tx_hashes);
+        let receipt_root =
+            trie_root_indexed_at(&self.checkpoint, height, &receipt_hashes);
 
         ExecResp {
             state_root: new_state_root,
@@ -167,10 +197,49 @@ impl Executor for RTEvmExecutor {
 }
 
 impl RTEvmExecutor {
+    /// None of the protocol changes are activated, see `with_checkpoint`.
+    pub fn new(hardfork: Hardfork) -> Self {
+        Self {
+            hardfork,
+            checkpoint: CheckPointConfig::default(),
+            deadline: None,
+            clock: Instant::now,
+            precompile_cache: None,
+        }
+    }
+
+    /// Follow the `checkpoint`, e.g. the one of the runtime.
+    pub fn with_checkpoint(mut self, checkpoint: CheckPointConfig) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    /// Stop executing the remaining txs once the `deadline` is exceeded,
+    /// only the executed ones are included in the `ExecResp`.
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Look up the results of the pure precompiles in the `cache` first,
+    /// the gas is charged exactly the same, see `PrecompileCache`, not used by default.
+    pub fn with_precompile_cache(mut self, cache: Option<Arc<PrecompileCache>>) -> Self {
+        self.precompile_cache = cache;
+        self
+    }
+
+    /// Read the time from the `clock` to check the deadline,
+    /// it is read once before each tx, `Instant::now` by default.
+    pub fn with_clock(mut self, clock: fn() -> Instant) -> Self {
+        self.clock = clock;
+        self
+    }
+
     pub fn evm_exec<B: Backend + ApplyBackend + Adapter>(
+        &self,
         backend: &mut B,
         config: &Config,
-        precompiles: &BTreeMap<H160, PrecompileFn>,
+        precompiles: &Precompiles,
         tx: &SignedTransaction,
     ) -> TxResp {
         // Deduct pre-pay gas
@@ -231,6 +300,19 @@ impl RTEvmExecutor {
         let remained_gas = executor.gas();
         let used_gas = executor.used_gas();
 
+        let mut exit = exit;
+        if exit.is_succeed() {
+            let (values, logs) = executor.into_state().deconstruct();
+            let mut logs = logs.into_iter().collect::<Vec<_>>();
+
+            let limit = self.checkpoint.logs_limit(backend.block_number().low_u64());
+            if limit_logs(limit, &mut logs) {
+                self.apply_changes(backend, values, logs);
+            } else {
+                exit = ExitReason::Error(ExitError::Other("too many logs".into()));
+            }
+        }
+
         let code_addr = if tx.transaction.unsigned.action() == &TransactionAction::Create
             && exit.is_succeed()
         {
@@ -239,11 +321,6 @@ impl RTEvmExecutor {
             None
         };
 
-        if exit.is_succeed() {
-            let (values, logs) = executor.into_state().deconstruct();
-            backend.apply(values, logs, true);
-        }
-
         let mut account = backend.get_account(tx.sender);
         account.nonce = current_nonce + U256::one();
 
@@ -271,4 +348,776 @@ impl RTEvmExecutor {
             removed: false,
         }
     }
+
+    /// The same as `call`, but the changes of a successful call are applied
+    /// and committed into the `backend`, so the following calls can observe them,
+    /// e.g. simulating a bundle of calls.
+    ///
+    /// NOTE: use a shadow backend, it must never be persisted as a block.
+    pub fn call_mut<B: Backend + ApplyBackend + Adapter>(
+        &self,
+        backend: &mut B,
+        gas_limit: u64,
+        from: Option<H160>,
+        to: Option<H160>,
+        value: U256,
+        data: Vec<u8>,
+    ) -> TxResp {
+        let config = self.hardfork.evm_config();
+        let metadata = StackSubstateMetadata::new(gas_limit, &config);
+        let precompiles = build_precompile_set(self.precompile_cache.clone());
+        let mut executor = StackExecutor::new_with_precompiles(
+            MemoryStackState::new(metadata, backend),
+            &config,
+            &precompiles,
+        );
+
+        let caller = from.unwrap_or_default();
+        let base_gas = intrinsic_gas(to.is_none(), &data, &[]);
+
+        // before the nonce of the caller is increased
+        let code_address: Option<H256> = to.is_none().then(|| {
+            executor
+                .create_address(CreateScheme::Legacy { caller })
+                .into()
+        });
+
+        let (exit, res) = if let Some(addr) = to {
+            executor.transact_call(caller, addr, value, data, gas_limit, Vec::new())
+        } else {
+            executor.transact_create(caller, value, data, gas_limit, Vec::new())
+        };
+
+        let remain_gas = executor.gas();
+        let used_gas = executor.used_gas() + base_gas;
+
+        let succeed = exit.is_succeed();
+        if succeed {
+            let (values, logs) = executor.into_state().deconstruct();
+            self.apply_changes(backend, values, logs);
+            backend.commit();
+        }
+
+        TxResp {
+            exit_reason: exit,
+            ret: res,
+            remain_gas,
+            gas_used: used_gas,
+            fee_cost: backend
+                .gas_price()
+                .checked_mul(used_gas.into())
+                .unwrap_or(U256::max_value()),
+            logs: backend.get_logs(),
+            code_address: code_address.filter(|_| succeed),
+            removed: false,
+        }
+    }
+
+    fn apply_changes<B, A, I, L>(&self, backend: &mut B, values: A, logs: L)
+    where
+        B: Backend + ApplyBackend,
+        A: IntoIterator<Item = Apply<I>>,
+        I: IntoIterator<Item = (H256, H256)>,
+        L: IntoIterator<Item = Log>,
+    {
+        if self.hardfork.is_eip6780_enabled() {
+            // the backend has not been changed by these changes yet
+            let values = values
+                .into_iter()
+                .map(|v| {
+                    eip6780_apply(v, |addr| {
+                        (backend.basic(addr).nonce, backend.code(addr).len())
+                    })
+                })
+                .collect::<Vec<_>>();
+            backend.apply(values, logs, true);
+        } else {
+            backend.apply(values, logs, true);
+        }
+    }
+}
+
+// Apply the `limit` to the logs of a successful tx,
+// return `false` if the tx must fail for them.
+fn limit_logs(limit: Option<LogsLimit>, logs: &mut Vec<Log>) -> bool {
+    match limit {
+        Some(LogsLimit::Fail(n)) => logs.len() <= n,
+        Some(LogsLimit::Cap(n)) => {
+            logs.truncate(n);
+            true
+        }
+        None => true,
+    }
+}
+
+// Set the `origin` and `gas_price` of a tx into the backend,
+// and restore the previous ones when dropped,
+// so the context of a tx can not leak into the following operations,
+// even if the execution exits early.
+struct CtxGuard<'a, B: Backend + Adapter> {
+    backend: &'a mut B,
+    prev_origin: H160,
+    prev_gas_price: U256,
+}
+
+impl<'a, B: Backend + Adapter> CtxGuard<'a, B> {
+    fn new(backend: &'a mut B, origin: H160, gas_price: U256) -> Self {
+        let prev_origin = backend.origin();
+        let prev_gas_price = backend.gas_price();
+
+        backend.set_origin(origin);
+        backend.set_gas_price(gas_price);
+
+        Self {
+            backend,
+            prev_origin,
+            prev_gas_price,
+        }
+    }
+}
+
+impl<B: Backend + Adapter> Deref for CtxGuard<'_, B> {
+    type Target = B;
+
+    fn deref(&self) -> &Self::Target {
+        self.backend
+    }
+}
+
+impl<B: Backend + Adapter> DerefMut for CtxGuard<'_, B> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.backend
+    }
+}
+
+impl<B: Backend + Adapter> Drop for CtxGuard<'_, B> {
+    fn drop(&mut self) {
+        self.backend.set_origin(self.prev_origin);
+        self.backend.set_gas_price(self.prev_gas_price);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rt_evm_model::types::{Basic, ExecutorContext, MerkleRoot};
+    use std::collections::BTreeMap;
+
+    // stores the calldata into the slot 0 if any, or returns the slot 0
+    const STORE_OR_LOAD: [u8; 24] = [
+        0x36, 0x15, 0x60, 0x0c, 0x57, 0x60, 0x00, 0x35, 0x60, 0x00, 0x55, 0x00, 0x5b,
+        0x60, 0x00, 0x54, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3,
+    ];
+
+    #[derive(Default)]
+    struct MemBackend {
+        ctx: ExecutorContext,
+        basics: BTreeMap<H160, Basic>,
+        codes: BTreeMap<H160, Vec<u8>>,
+        storages: BTreeMap<(H160, H256), H256>,
+        // simulate a crash in the middle of a tx
+        commit_panics: bool,
+    }
+
+    impl Backend for MemBackend {
+        fn gas_price(&self) -> U256 {
+            self.ctx.gas_price
+        }
+
+        fn origin(&self) -> H160 {
+            self.ctx.origin
+        }
+
+        fn block_number(&self) -> U256 {
+            self.ctx.block_number
+        }
+
+        fn block_hash(&self, _: U256) -> H256 {
+            H256::default()
+        }
+
+        fn block_coinbase(&self) -> H160 {
+            self.ctx.block_coinbase
+        }
+
+        fn block_timestamp(&self) -> U256 {
+            self.ctx.block_timestamp
+        }
+
+        fn block_difficulty(&self) -> U256 {
+            self.ctx.difficulty
+        }
+
+        fn block_gas_limit(&self) -> U256 {
+            self.ctx.block_gas_limit
+        }
+
+        fn block_base_fee_per_gas(&self) -> U256 {
+            self.ctx.block_base_fee_per_gas
+        }
+
+        fn chain_id(&self) -> U256 {
+            self.ctx.chain_id
+        }
+
+        fn exists(&self, address: H160) -> bool {
+            self.basics.contains_key(&address) || self.codes.contains_key(&address)
+        }
+
+        fn basic(&self, address: H160) -> Basic {
+            self.basics.get(&address).cloned().unwrap_or_default()
+        }
+
+        fn code(&self, address: H160) -> Vec<u8> {
+            self.codes.get(&address).cloned().unwrap_or_default()
+        }
+
+        fn storage(&self, address: H160, index: H256) -> H256 {
+            self.storages
+                .get(&(address, index))
+                .copied()
+                .unwrap_or_default()
+        }
+
+        fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
+            Some(self.storage(address, index))
+        }
+    }
+
+    impl ApplyBackend for MemBackend {
+        fn apply<A, I, L>(&mut self, values: A, logs: L, _delete_empty: bool)
+        where
+            A: IntoIterator<Item = Apply<I>>,
+            I: IntoIterator<Item = (H256, H256)>,
+            L: IntoIterator<Item = Log>,
+        {
+            for apply in values {
+                match apply {
+                    Apply::Modify {
+                        address,
+                        basic,
+                        code,
+                        storage,
+                        reset_storage,
+                    } => {
+                        self.basics.insert(address, basic);
+                        if let Some(code) = code {
+                            self.codes.insert(address, code);
+                        }
+                        if reset_storage {
+                            self.storages.retain(|(addr, _), _| *addr != address);
+                        }
+                        for (index, value) in storage {
+                            self.storages.insert((address, index), value);
+                        }
+                    }
+                    Apply::Delete { address } => {
+                        self.basics.remove(&address);
+                        self.codes.remove(&address);
+                        self.storages.retain(|(addr, _), _| *addr != address);
+                    }
+                }
+            }
+            self.ctx.logs = logs.into_iter().collect();
+        }
+    }
+
+    impl Adapter for MemBackend {
+        fn set_origin(&mut self, origin: H160) {
+            self.ctx.origin = origin;
+        }
+
+        fn set_gas_price(&mut self, gas_price: U256) {
+            self.ctx.gas_price = gas_price;
+        }
+
+        fn get_logs(&mut self) -> Vec<Log> {
+            std::mem::take(&mut self.ctx.logs)
+        }
+
+        fn commit(&mut self) -> MerkleRoot {
+            assert!(!self.commit_panics, "commit failed");
+            MerkleRoot::default()
+        }
+
+        fn current_root(&self) -> MerkleRoot {
+            MerkleRoot::default()
+        }
+
+        fn get(&self, _: &[u8]) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn get_ctx(&self) -> ExecutorContext {
+            self.ctx.clone()
+        }
+
+        fn get_account(&self, address: H160) -> Account {
+            let basic = self.basic(address);
+            Account {
+                nonce: basic.nonce,
+                balance: basic.balance,
+                storage_root: NIL_HASH,
+                code_hash: NIL_HASH,
+            }
+        }
+
+        fn save_account(&mut self, address: H160, account: &Account) {
+            let basic = Basic {
+                balance: account.balance,
+                nonce: account.nonce,
+            };
+            self.basics.insert(address, basic);
+        }
+    }
+
+    #[test]
+    fn test_call_mut_carries_state() {
+        let contract = H160::repeat_byte(0xcc);
+        let mut backend = MemBackend::default();
+        backend.codes.insert(contract, STORE_OR_LOAD.to_vec());
+        backend.basics.insert(
+            contract,
+            Basic {
+                balance: U256::zero(),
+                nonce: U256::one(),
+            },
+        );
+
+        let executor = RTEvmExecutor::default();
+        let from = Some(H160::repeat_byte(1));
+        let value = H256::repeat_byte(7).as_bytes().to_vec();
+        let call = |backend: &mut MemBackend, data: Vec<u8>, persist: bool| {
+            let to = Some(contract);
+            let resp = if persist {
+                executor.call_mut(backend, 1_000_000, from, to, U256::zero(), data)
+            } else {
+                executor.call(&*backend, 1_000_000, from, to, U256::zero(), data)
+            };
+            assert!(resp.exit_reason.is_succeed());
+            resp.ret
+        };
+
+        // the changes of `call` are discarded
+        call(&mut backend, value.clone(), false);
+        assert_eq!(vec![0; 32], call(&mut backend, vec![], false));
+
+        // the second call observes the slot written by the first one
+        call(&mut backend, value.clone(), true);
+        assert_eq!(value, call(&mut backend, vec![], true));
+        assert_eq!(value, call(&mut backend, vec![], false));
+    }
+
+    #[test]
+    fn test_exec_with_checkpoint() {
+        use rt_evm_model::types::{NIL_HASH, RLP_NULL};
+
+        let mut backend = MemBackend::default();
+        backend.ctx.block_number = 100.into();
+
+        // no global state involved
+        let exec = |empty_root_height: Option<u64>, backend: &mut MemBackend| {
+            RTEvmExecutor::new(Hardfork::default())
+                .with_checkpoint(CheckPointConfig {
+                    empty_root_height,
+                    ..Default::default()
+                })
+                .exec(backend, &[])
+        };
+
+        let resp = exec(None, &mut backend);
+        assert_eq!(NIL_HASH, resp.transaction_root);
+        assert_eq!(NIL_HASH, resp.receipt_root);
+
+        let resp = exec(Some(101), &mut backend);
+        assert_eq!(NIL_HASH, resp.receipt_root);
+
+        let resp = exec(Some(100), &mut backend);
+        assert_eq!(RLP_NULL, resp.transaction_root);
+        assert_eq!(RLP_NULL, resp.receipt_root);
+    }
+
+    // a call with a zero gas price and an unused signature
+    fn zero_price_tx(
+        sender: H160,
+        nonce: u64,
+        to: H160,
+        data: Vec<u8>,
+    ) -> SignedTransaction {
+        use rt_evm_model::types::{
+            LegacyTransaction, UnsignedTransaction, UnverifiedTransaction,
+        };
+
+        SignedTransaction {
+            transaction: UnverifiedTransaction {
+                unsigned: UnsignedTransaction::Legacy(LegacyTransaction {
+                    nonce: nonce.into(),
+                    gas_price: U256::zero(),
+                    gas_limit: 100_000.into(),
+                    action: TransactionAction::Call(to),
+                    value: U256::zero(),
+                    data,
+                }),
+                signature: None,
+                chain_id: 0,
+                hash: Hasher::digest([sender.as_bytes(), &nonce.to_be_bytes()].concat()),
+            },
+            sender,
+            public: None,
+        }
+    }
+
+    #[test]
+    fn test_exec_zero_gas_price() {
+        let contract = H160::repeat_byte(0xcc);
+        let mut backend = MemBackend::default();
+        backend.codes.insert(contract, STORE_OR_LOAD.to_vec());
+
+        // the sender has no balance at all, as on a fee-free chain
+        let sender = H160::repeat_byte(1);
+        let value = H256::repeat_byte(7);
+        let tx = zero_price_tx(sender, 0, contract, value.as_bytes().to_vec());
+
+        let resp = RTEvmExecutor::default().exec(&mut backend, &[tx]);
+        let r = &resp.txs_resp[0];
+        assert!(r.exit_reason.is_succeed());
+        assert!(r.gas_used > 0);
+        assert_eq!(U256::zero(), r.fee_cost);
+        assert_eq!(U256::zero(), resp.fee_used);
+
+        assert_eq!(
+            Some(&value),
+            backend.storages.get(&(contract, H256::zero()))
+        );
+        let account = backend.get_account(sender);
+        assert_eq!(U256::zero(), account.balance);
+        assert_eq!(U256::one(), account.nonce);
+    }
+
+    #[test]
+    fn test_exec_logs_limit() {
+        // stores 1 into the slot 0, then emits 3 empty logs
+        const STORE_AND_LOG3: [u8; 21] = [
+            0x60, 0x01, 0x60, 0x00, 0x55, 0x60, 0x00, 0x60, 0x00, 0xa0, 0x60, 0x00,
+            0x60, 0x00, 0xa0, 0x60, 0x00, 0x60, 0x00, 0xa0, 0x00,
+        ];
+
+        let contract = H160::repeat_byte(0xcc);
+        let sender = H160::repeat_byte(1);
+
+        // (<logs of the tx>, <whether the slot is written>)
+        let exec = |height: u64, max_logs_per_tx| {
+            let mut backend = MemBackend::default();
+            backend.ctx.block_number = height.into();
+            backend.codes.insert(contract, STORE_AND_LOG3.to_vec());
+
+            let tx = zero_price_tx(sender, 0, contract, vec![]);
+            let resp = RTEvmExecutor::new(Hardfork::default())
+                .with_checkpoint(CheckPointConfig {
+                    max_logs_per_tx,
+                    ..Default::default()
+                })
+                .exec(&mut backend, &[tx]);
+
+            let r = &resp.txs_resp[0];
+            assert!(r.gas_used > 0);
+            assert_eq!(U256::one(), backend.get_account(sender).nonce);
+
+            let written = backend.storages.contains_key(&(contract, H256::zero()));
+            assert_eq!(written, r.exit_reason.is_succeed());
+            (r.logs.len(), written)
+        };
+
+        assert_eq!((3, true), exec(100, None));
+
+        let fail = Some((100, LogsLimit::Fail(2)));
+        assert_eq!((3, true), exec(99, fail));
+        assert_eq!((0, false), exec(100, fail));
+        assert_eq!((3, true), exec(100, Some((100, LogsLimit::Fail(3)))));
+
+        let cap = Some((100, LogsLimit::Cap(2)));
+        assert_eq!((3, true), exec(99, cap));
+        assert_eq!((2, true), exec(100, cap));
+    }
+
+    #[test]
+    fn test_basefee_opcode() {
+        use rt_evm_model::types::{Header, Proposal, BASE_FEE_PER_GAS};
+
+        // returns the BASEFEE as a word
+        const RETURN_BASEFEE: [u8; 9] =
+            [0x48, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+
+        let contract = H160::repeat_byte(0xcc);
+        let header = Header {
+            number: 100,
+            base_fee_per_gas: U256::from(0x1234),
+            ..Default::default()
+        };
+        assert_ne!(U256::from(BASE_FEE_PER_GAS), header.base_fee_per_gas);
+
+        // the contexts of both the block production and the RPC calls
+        for ctx in [
+            ExecutorContext::from(&Proposal::from(&header)),
+            ExecutorContext::from(&header),
+        ] {
+            let mut backend = MemBackend {
+                ctx,
+                ..Default::default()
+            };
+            backend.codes.insert(contract, RETURN_BASEFEE.to_vec());
+
+            let resp = RTEvmExecutor::default().call(
+                &backend,
+                1_000_000,
+                Some(H160::repeat_byte(1)),
+                Some(contract),
+                U256::zero(),
+                vec![],
+            );
+            assert!(resp.exit_reason.is_succeed());
+
+            let mut expected = [0; 32];
+            header.base_fee_per_gas.to_big_endian(&mut expected);
+            assert_eq!(expected.to_vec(), resp.ret);
+        }
+    }
+
+    #[test]
+    fn test_exec_deadline() {
+        use std::{cell::Cell, time::Duration};
+
+        thread_local! {
+            static START: Instant = Instant::now();
+            static TICKS: Cell<u64> = Cell::new(0);
+        }
+
+        // one second passes on every reading
+        fn ticking_clock() -> Instant {
+            let ticks = TICKS.with(|t| t.replace(t.get() + 1));
+            START.with(|s| *s + Duration::from_secs(ticks))
+        }
+
+        let contract = H160::repeat_byte(0xcc);
+        let sender = H160::repeat_byte(1);
+        let txs = (0..10)
+            .map(|nonce| zero_price_tx(sender, nonce, contract, vec![]))
+            .collect::<Vec<_>>();
+
+        // `deadline`: in seconds from the start
+        let exec = |deadline: Option<u64>| {
+            TICKS.with(|t| t.set(0));
+            let deadline =
+                deadline.map(|secs| START.with(|s| *s + Duration::from_secs(secs)));
+
+            let mut backend = MemBackend::default();
+            backend.codes.insert(contract, STORE_OR_LOAD.to_vec());
+            let resp = RTEvmExecutor::default()
+                .with_deadline(deadline)
+                .with_clock(ticking_clock)
+                .exec(&mut backend, &txs);
+            assert_eq!(
+                U256::from(resp.txs_resp.len()),
+                backend.get_account(sender).nonce
+            );
+            resp.txs_resp.len()
+        };
+
+        assert_eq!(10, exec(None));
+        assert_eq!(10, exec(Some(3600)));
+
+        // the clock reads 0, 1, 2 ... before the txs,
+        // a tx starting at the deadline is not executed
+        assert_eq!(0, exec(Some(0)));
+        assert_eq!(1, exec(Some(1)));
+        assert_eq!(5, exec(Some(5)));
+        assert_eq!(9, exec(Some(9)));
+        assert_eq!(10, exec(Some(10)));
+    }
+
+    #[test]
+    fn test_call_without_from() {
+        // returns the CALLER as a word
+        const RETURN_CALLER: [u8; 9] =
+            [0x33, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+
+        let contract = H160::repeat_byte(0xcc);
+        let mut backend = MemBackend::default();
+        backend.codes.insert(contract, RETURN_CALLER.to_vec());
+
+        let call = |from| {
+            let resp = RTEvmExecutor::default().call(
+                &backend,
+                1_000_000,
+                from,
+                Some(contract),
+                U256::zero(),
+                vec![],
+            );
+            assert!(resp.exit_reason.is_succeed());
+            H256::from_slice(&resp.ret)
+        };
+
+        // executed from the zero address, like geth
+        assert_eq!(H256::zero(), call(None));
+
+        let from = H160::repeat_byte(1);
+        assert_eq!(H256::from(from), call(Some(from)));
+    }
+
+    #[test]
+    fn test_state_diff() {
+        use rt_evm_model::types::UnsignedTransaction;
+
+        let contract = H160::repeat_byte(0xcc);
+        let sender = H160::repeat_byte(1);
+        let balance = U256::from(1_000_000_000u64);
+
+        let mut backend = MemBackend::default();
+        backend.codes.insert(contract, STORE_OR_LOAD.to_vec());
+        backend.basics.insert(
+            sender,
+            Basic {
+                balance,
+                nonce: U256::zero(),
+            },
+        );
+
+        let value = H256::repeat_byte(7);
+        let mut tx = zero_price_tx(sender, 0, contract, value.as_bytes().to_vec());
+        if let UnsignedTransaction::Legacy(ref mut utx) = tx.transaction.unsigned {
+            utx.gas_price = U256::one();
+        }
+
+        let mut recorder = StateDiffRecorder::new(&mut backend);
+        let resp = RTEvmExecutor::default().exec(&mut recorder, &[tx]);
+        let diff = recorder.into_diff();
+
+        let r = &resp.txs_resp[0];
+        assert!(r.exit_reason.is_succeed());
+        assert_eq!(2, diff.pre.len());
+        assert_eq!(2, diff.post.len());
+
+        // the sender pays the fee
+        let (pre, post) = (&diff.pre[&sender], &diff.post[&sender]);
+        assert_eq!(Some(balance), pre.balance);
+        assert_eq!(Some(balance - r.fee_cost), post.balance);
+        assert_eq!(Some(U256::zero()), pre.nonce);
+        assert_eq!(Some(U256::one()), post.nonce);
+        assert!(pre.storage.is_empty() && pre.code.is_none());
+
+        // only the written slot of the contract
+        let (pre, post) = (&diff.pre[&contract], &diff.post[&contract]);
+        assert_eq!(None, pre.balance);
+        assert_eq!(None, pre.nonce);
+        assert_eq!(None, pre.code);
+        assert_eq!(Some(&H256::zero()), pre.storage.get(&H256::zero()));
+        assert_eq!(Some(&value), post.storage.get(&H256::zero()));
+        assert_eq!(1, post.storage.len());
+    }
+
+    #[test]
+    fn test_exec_error_keeps_ctx() {
+        use rt_evm_model::types::UnsignedTransaction;
+
+        // ORIGIN PUSH1 0 SSTORE GASPRICE PUSH1 1 SSTORE STOP
+        const STORE_CTX: [u8; 9] =
+            [0x32, 0x60, 0x00, 0x55, 0x3a, 0x60, 0x01, 0x55, 0x00];
+        // INVALID
+        const FAIL: [u8; 1] = [0xfe];
+
+        let (recorder, failing) = (H160::repeat_byte(0xcc), H160::repeat_byte(0xdd));
+        let (alice, bob) = (H160::repeat_byte(1), H160::repeat_byte(2));
+        let (origin, gas_price) = (H160::repeat_byte(9), U256::from(9));
+
+        let mut backend = MemBackend::default();
+        backend.codes.insert(recorder, STORE_CTX.to_vec());
+        backend.codes.insert(failing, FAIL.to_vec());
+        for sender in [alice, bob] {
+            let basic = Basic {
+                balance: U256::from(1_000_000_000u64),
+                nonce: U256::zero(),
+            };
+            backend.basics.insert(sender, basic);
+        }
+        backend.ctx.origin = origin;
+        backend.ctx.gas_price = gas_price;
+
+        let priced_tx = |sender, nonce, to, price: u64| {
+            let mut tx = zero_price_tx(sender, nonce, to, vec![]);
+            if let UnsignedTransaction::Legacy(ref mut utx) = tx.transaction.unsigned {
+                utx.gas_price = price.into();
+            }
+            tx
+        };
+        let slot = |backend: &MemBackend, idx: u8| {
+            backend.storages[&(recorder, H256::from_low_u64_be(idx.into()))]
+        };
+
+        let txs = [
+            // a failed execution and an invalid nonce
+            priced_tx(alice, 0, failing, 3),
+            priced_tx(alice, 7, recorder, 5),
+            priced_tx(bob, 0, recorder, 2),
+        ];
+        let resp = RTEvmExecutor::default().exec(&mut backend, &txs);
+        assert!(!resp.txs_resp[0].exit_reason.is_succeed());
+        assert!(!resp.txs_resp[1].exit_reason.is_succeed());
+        assert!(resp.txs_resp[2].exit_reason.is_succeed());
+
+        // the last tx runs in its own context
+        assert_eq!(H256::from(bob), slot(&backend, 0));
+        assert_eq!(H256::from_low_u64_be(2), slot(&backend, 1));
+
+        // and the context of the backend is restored
+        assert_eq!(origin, backend.ctx.origin);
+        assert_eq!(gas_price, backend.ctx.gas_price);
+
+        // so it is after a crash
+        backend.commit_panics = true;
+        let crashed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            RTEvmExecutor::default()
+                .exec(&mut backend, &[priced_tx(alice, 2, recorder, 5)])
+        }));
+        assert!(crashed.is_err());
+        assert_eq!(origin, backend.ctx.origin);
+        assert_eq!(gas_price, backend.ctx.gas_price);
+
+        backend.commit_panics = false;
+        let resp = RTEvmExecutor::default()
+            .exec(&mut backend, &[priced_tx(bob, 1, recorder, 4)]);
+        assert!(resp.txs_resp[0].exit_reason.is_succeed());
+        assert_eq!(H256::from(bob), slot(&backend, 0));
+        assert_eq!(H256::from_low_u64_be(4), slot(&backend, 1));
+    }
+
+    #[test]
+    fn test_exec_with_precompile_cache() {
+        use sha2::Digest;
+
+        // the sha256 precompile
+        let sha256 = H160::from_low_u64_be(2);
+        let sender = H160::repeat_byte(1);
+        let data = b"permit".to_vec();
+
+        // the same txs with and without a cache, twice with the cache to hit it
+        let cache = Arc::new(PrecompileCache::default());
+        let mut resps = vec![];
+        for cache in [None, Some(&cache), Some(&cache)] {
+            let mut backend = MemBackend::default();
+            let tx = zero_price_tx(sender, 0, sha256, data.clone());
+            let resp = RTEvmExecutor::default()
+                .with_precompile_cache(cache.cloned())
+                .exec(&mut backend, &[tx]);
+            assert!(resp.txs_resp[0].exit_reason.is_succeed());
+            resps.push((resp.txs_resp[0].ret.clone(), resp.gas_used));
+        }
+
+        assert_eq!(
+            sha2::Sha256::digest(&data).as_slice(),
+            resps[0].0.as_slice()
+        );
+        assert_eq!(resps[0], resps[1]);
+        assert_eq!(resps[0], resps[2]);
+    }
 }
@@ -0,0 +1,260 @@
+use std::collections::{HashMap, VecDeque};
+
+use evm::executor::stack::{PrecompileFailure, PrecompileOutput};
+use evm::{Context, ExitError, ExitSucceed};
+use parking_lot::Mutex;
+
+use rt_evm_model::types::H160;
+
+use crate::err;
+use crate::precompiles::PrecompileContract;
+
+// the max number of the cached results
+const CACHE_CAP: usize = 4096;
+
+// the longer inputs are never cached, to bound the memory usage
+const MAX_CACHED_INPUT_LEN: usize = 256;
+
+/// Memoize the results of the pure precompiles(ecRecover, sha256) by their inputs,
+/// useful when many txs verify the same signatures, e.g. permit-style approvals;
+/// the gas is charged exactly the same as without the cache.
+///
+/// Shared by the executors it is passed to, see `RTEvmExecutor::with_precompile_cache`.
+pub struct PrecompileCache {
+    results: Mutex<ResultCache>,
+}
+
+impl PrecompileCache {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            results: Mutex::new(ResultCache::new(cap)),
+        }
+    }
+}
+
+impl Default for PrecompileCache {
+    fn default() -> Self {
+        Self::new(CACHE_CAP)
+    }
+}
+
+type Key = (H160, Vec<u8>);
+
+struct ResultCache {
+    cap: usize,
+    results: HashMap<Key, (ExitSucceed, Vec<u8>)>,
+
+    // the oldest one is evicted first
+    keys: VecDeque<Key>,
+}
+
+impl ResultCache {
+    fn new(cap: usize) -> Self {
+        Self {
+            cap,
+            results: HashMap::new(),
+            keys: VecDeque::new(),
+        }
+    }
+
+    fn get(&self, key: &Key) -> Option<(ExitSucceed, Vec<u8>)> {
+        self.results.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: Key, result: (ExitSucceed, Vec<u8>)) {
+        if self.results.insert(key.clone(), result).is_some() {
+            return;
+        }
+        self.keys.push_back(key);
+        while self.keys.len() > self.cap {
+            if let Some(k) = self.keys.pop_front() {
+                self.results.remove(&k);
+            }
+        }
+    }
+}
+
+pub(super) type CachedPrecompileFn =
+    fn(
+        &PrecompileCache,
+        &[u8],
+        Option<u64>,
+        &Context,
+        bool,
+    ) -> Result<(PrecompileOutput, u64), PrecompileFailure>;
+
+// the cached version of `C::exec_fn`,
+// only for the precompiles whose outputs depend on nothing but the input
+pub(super) fn cached_exec_fn<C: PrecompileContract>(
+    cache: &PrecompileCache,
+    input: &[u8],
+    gas_limit: Option<u64>,
+    context: &Context,
+    is_static: bool,
+) -> Result<(PrecompileOutput, u64), PrecompileFailure> {
+    if input.len() > MAX_CACHED_INPUT_LEN {
+        return C::exec_fn(input, gas_limit, context, is_static);
+    }
+
+    // the same gas check as `exec_fn`, a hit must cost the same as a miss
+    let gas = C::gas_cost(input);
+    if let Some(limit) = gas_limit {
+        if limit < gas {
+            return err!();
+        }
+    }
+
+    let key = (C::ADDRESS, input.to_vec());
+    if let Some((exit_status, output)) = cache.results.lock().get(&key) {
+        return Ok((
+            PrecompileOutput {
+                exit_status,
+                output,
+            },
+            gas,
+        ));
+    }
+
+    // the failures are not cached, they are cheap to reproduce
+    let ret = C::exec_fn(input, gas_limit, context, is_static);
+    if let Ok((out, _)) = ret.as_ref() {
+        cache
+            .results
+            .lock()
+            .insert(key, (out.exit_status, out.output.clone()));
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::precompiles::{ecrecover::EcRecover, sha256::Sha256};
+    use rt_evm_crypto::{
+        HashValue, PrivateKey, Secp256k1RecoverablePrivateKey, Signature,
+    };
+    use rt_evm_model::types::{Hasher, U256};
+
+    fn ecrecover_input() -> Vec<u8> {
+        let sk = Secp256k1RecoverablePrivateKey::try_from([7u8; 32].as_ref()).unwrap();
+        let msg = Hasher::digest(b"permit");
+        let sig = sk
+            .sign_message(&HashValue::try_from(msg.as_bytes()).unwrap())
+            .to_bytes();
+
+        // hash, v, r, s
+        let mut input = msg.as_bytes().to_vec();
+        input.extend_from_slice(&[0; 31]);
+        input.push(sig[64] + 27);
+        input.extend_from_slice(&sig[..64]);
+        input
+    }
+
+    fn assert_same_as_uncached<C: PrecompileContract>(
+        cache: &PrecompileCache,
+        input: &[u8],
+    ) {
+        let ctx = Context {
+            address: C::ADDRESS,
+            caller: H160::zero(),
+            apparent_value: U256::zero(),
+        };
+
+        let (expected, expected_gas) =
+            C::exec_fn(input, Some(100_000), &ctx, false).unwrap();
+
+        // the first one fills the cache, the second one hits it
+        for _ in 0..2 {
+            let (out, gas) =
+                cached_exec_fn::<C>(cache, input, Some(100_000), &ctx, false).unwrap();
+            assert_eq!(expected.exit_status, out.exit_status);
+            assert_eq!(expected.output, out.output);
+            assert_eq!(expected_gas, gas);
+        }
+        assert!(cache
+            .results
+            .lock()
+            .get(&(C::ADDRESS, input.to_vec()))
+            .is_some());
+
+        // a hit is not free
+        assert!(
+            cached_exec_fn::<C>(cache, input, Some(expected_gas - 1), &ctx, false)
+                .is_err()
+        );
+        assert!(C::exec_fn(input, Some(expected_gas - 1), &ctx, false).is_err());
+    }
+
+    #[test]
+    fn test_cached_precompiles() {
+        // owned by this test, nothing is shared with the parallel ones
+        let cache = PrecompileCache::default();
+
+        let input = ecrecover_input();
+        assert_same_as_uncached::<EcRecover>(&cache, &input);
+        assert_same_as_uncached::<Sha256>(&cache, &input);
+        assert_same_as_uncached::<Sha256>(&cache, b"");
+
+        // an invalid signature is not cached
+        let mut invalid = input;
+        invalid[63] = 29;
+        let ctx = Context {
+            address: EcRecover::ADDRESS,
+            caller: H160::zero(),
+            apparent_value: U256::zero(),
+        };
+        assert!(
+            cached_exec_fn::<EcRecover>(&cache, &invalid, None, &ctx, false).is_err()
+        );
+        assert!(cache
+            .results
+            .lock()
+            .get(&(EcRecover::ADDRESS, invalid))
+            .is_none());
+
+        // a separate cache starts empty
+        let other = PrecompileCache::default();
+        assert!(other
+            .results
+            .lock()
+            .get(&(Sha256::ADDRESS, input))
+            .is_none());
+    }
+
+    #[test]
+    fn test_long_inputs_uncached() {
+        let cache = PrecompileCache::default();
+        let ctx = Context {
+            address: Sha256::ADDRESS,
+            caller: H160::zero(),
+            apparent_value: U256::zero(),
+        };
+
+        let input = vec![7u8; MAX_CACHED_INPUT_LEN + 1];
+        let (expected, expected_gas) =
+            Sha256::exec_fn(&input, None, &ctx, false).unwrap();
+        let (out, gas) =
+            cached_exec_fn::<Sha256>(&cache, &input, None, &ctx, false).unwrap();
+        assert_eq!(expected.output, out.output);
+        assert_eq!(expected_gas, gas);
+        assert!(cache.results.lock().results.is_empty());
+    }
+
+    #[test]
+    fn test_result_cache_eviction() {
+        let mut cache = ResultCache::new(2);
+        let key = |n: u8| (H160::zero(), vec![n]);
+        let result = |n: u8| (ExitSucceed::Returned, vec![n]);
+
+        cache.insert(key(1), result(1));
+        cache.insert(key(2), result(2));
+        cache.insert(key(2), result(2));
+        assert_eq!(Some(result(1)), cache.get(&key(1)));
+
+        cache.insert(key(3), result(3));
+        assert!(cache.get(&key(1)).is_none());
+        assert_eq!(Some(result(2)), cache.get(&key(2)));
+        assert_eq!(Some(result(3)), cache.get(&key(3)));
+        assert_eq!(2, cache.keys.len());
+    }
+}
@@ -1,4 +1,5 @@
 mod blake2_f;
+mod cache;
 mod ec_add;
 mod ec_mul;
 mod ec_pairing;
@@ -10,17 +11,27 @@ mod rsa;
 mod secp256r1;
 mod sha256;
 
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, sync::Arc};
 
 use bn::{AffineG1, Fq, Fr, Group, G1};
-use evm::executor::stack::{PrecompileFailure, PrecompileFn, PrecompileOutput};
+use evm::executor::stack::{
+    PrecompileFailure, PrecompileFn, PrecompileHandle, PrecompileOutput,
+    PrecompileResult, PrecompileSet,
+};
 use evm::{Context, ExitError};
 
 use rt_evm_model::types::H160;
 
 use crate::precompiles::{
-    blake2_f::Blake2F, ec_add::EcAdd, ec_mul::EcMul, ec_pairing::EcPairing,
-    ecrecover::EcRecover, identity::Identity, modexp::ModExp, ripemd160::Ripemd160,
+    blake2_f::Blake2F,
+    cache::{cached_exec_fn, CachedPrecompileFn},
+    ec_add::EcAdd,
+    ec_mul::EcMul,
+    ec_pairing::EcPairing,
+    ecrecover::EcRecover,
+    identity::Identity,
+    modexp::ModExp,
+    ripemd160::Ripemd160,
     sha256::Sha256,
 };
 
@@ -78,10 +89,60 @@ const fn precompile_address(addr: u8) -> H160 {
     ])
 }
 
-pub fn build_precompile_set() -> BTreeMap<H160, PrecompileFn> {
-    precompiles!(
+pub use cache::PrecompileCache;
+
+/// The precompiles of one executor, with the results cache passed to it, if any.
+pub struct Precompiles {
+    set: BTreeMap<H160, PrecompileFn>,
+
+    // the pure ones, looked up in the `cache` first
+    cached: BTreeMap<H160, CachedPrecompileFn>,
+    cache: Option<Arc<PrecompileCache>>,
+}
+
+impl PrecompileSet for Precompiles {
+    fn execute(&self, handle: &mut impl PrecompileHandle) -> Option<PrecompileResult> {
+        let address = handle.code_address();
+        let (input, gas_limit, context, is_static) = (
+            handle.input(),
+            handle.gas_limit(),
+            handle.context(),
+            handle.is_static(),
+        );
+
+        let ret = match (self.cache.as_deref(), self.cached.get(&address)) {
+            (Some(cache), Some(f)) => f(cache, input, gas_limit, context, is_static),
+            _ => (self.set.get(&address)?)(input, gas_limit, context, is_static),
+        };
+
+        Some(ret.and_then(|(output, cost)| {
+            handle.record_cost(cost)?;
+            Ok(output)
+        }))
+    }
+
+    fn is_precompile(&self, address: H160) -> bool {
+        self.set.contains_key(&address)
+    }
+}
+
+pub fn build_precompile_set(cache: Option<Arc<PrecompileCache>>) -> Precompiles {
+    let set = precompiles!(
         EcRecover, Sha256, Ripemd160, Identity, ModExp, EcAdd, EcMul, EcPairing, Blake2F
-    )
+    );
+
+    // `Identity` is pure too, but a lookup costs more than copying the input
+    let mut cached = BTreeMap::new();
+    cached.insert(
+        EcRecover::ADDRESS,
+        cached_exec_fn::<EcRecover> as CachedPrecompileFn,
+    );
+    cached.insert(
+        Sha256::ADDRESS,
+        cached_exec_fn::<Sha256> as CachedPrecompileFn,
+    );
+
+    Precompiles { set, cached, cache }
 }
 
 pub(crate) fn read_point(input: &[u8], start: usize) -> Result<G1, PrecompileFailure> {
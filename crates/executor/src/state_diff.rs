@@ -0,0 +1,236 @@
+use rt_evm_model::{
+    traits::{ApplyBackend, Backend, ExecutorAdapter as Adapter},
+    types::{
+        Account, AccountState, Apply, Basic, ExecutorContext, Log, MerkleRoot,
+        StateDiff, H160, H256, U256,
+    },
+};
+use std::collections::BTreeMap;
+
+/// Record the values of the accounts and the slots before they are changed,
+/// the changes themselves are passed to the wrapped backend as is,
+/// see `into_diff`.
+///
+/// NOTE:
+/// the slots cleared by a deleted or a reset account are not listed
+/// unless they are also written.
+pub struct StateDiffRecorder<'a, B> {
+    backend: &'a mut B,
+
+    // the values before the first change
+    accounts: BTreeMap<H160, (Basic, Vec<u8>)>,
+    slots: BTreeMap<(H160, H256), H256>,
+}
+
+impl<'a, B: Backend + ApplyBackend + Adapter> StateDiffRecorder<'a, B> {
+    pub fn new(backend: &'a mut B) -> Self {
+        Self {
+            backend,
+            accounts: BTreeMap::new(),
+            slots: BTreeMap::new(),
+        }
+    }
+
+    fn record_account(&mut self, address: H160) {
+        if !self.accounts.contains_key(&address) {
+            let pre = (self.backend.basic(address), self.backend.code(address));
+            self.accounts.insert(address, pre);
+        }
+    }
+
+    fn record_slot(&mut self, address: H160, index: H256) {
+        if !self.slots.contains_key(&(address, index)) {
+            let pre = self.backend.storage(address, index);
+            self.slots.insert((address, index), pre);
+        }
+    }
+
+    /// Compare the recorded values with the current ones of the backend.
+    pub fn into_diff(self) -> StateDiff {
+        let mut diff = StateDiff::default();
+
+        for (address, (basic, code)) in self.accounts.into_iter() {
+            let (post_basic, post_code) =
+                (self.backend.basic(address), self.backend.code(address));
+            let pre = diff.pre.entry(address).or_default();
+            let post = diff.post.entry(address).or_default();
+
+            if basic.balance != post_basic.balance {
+                pre.balance = Some(basic.balance);
+                post.balance = Some(post_basic.balance);
+            }
+            if basic.nonce != post_basic.nonce {
+                pre.nonce = Some(basic.nonce);
+                post.nonce = Some(post_basic.nonce);
+            }
+            if code != post_code {
+                pre.code = Some(code);
+                post.code = Some(post_code);
+            }
+        }
+
+        for ((address, index), value) in self.slots.into_iter() {
+            let post_value = self.backend.storage(address, index);
+            if value != post_value {
+                diff.pre
+                    .entry(address)
+                    .or_default()
+                    .storage
+                    .insert(index, value);
+                diff.post
+                    .entry(address)
+                    .or_default()
+                    .storage
+                    .insert(index, post_value);
+            }
+        }
+
+        let unchanged = AccountState::default();
+        diff.pre.retain(|_, a| a != &unchanged);
+        diff.post.retain(|_, a| a != &unchanged);
+
+        diff
+    }
+}
+
+impl<B: Backend> Backend for StateDiffRecorder<'_, B> {
+    fn gas_price(&self) -> U256 {
+        self.backend.gas_price()
+    }
+
+    fn origin(&self) -> H160 {
+        self.backend.origin()
+    }
+
+    fn block_hash(&self, number: U256) -> H256 {
+        self.backend.block_hash(number)
+    }
+
+    fn block_number(&self) -> U256 {
+        self.backend.block_number()
+    }
+
+    fn block_coinbase(&self) -> H160 {
+        self.backend.block_coinbase()
+    }
+
+    fn block_timestamp(&self) -> U256 {
+        self.backend.block_timestamp()
+    }
+
+    fn block_difficulty(&self) -> U256 {
+        self.backend.block_difficulty()
+    }
+
+    fn block_gas_limit(&self) -> U256 {
+        self.backend.block_gas_limit()
+    }
+
+    fn block_base_fee_per_gas(&self) -> U256 {
+        self.backend.block_base_fee_per_gas()
+    }
+
+    fn chain_id(&self) -> U256 {
+        self.backend.chain_id()
+    }
+
+    fn exists(&self, address: H160) -> bool {
+        self.backend.exists(address)
+    }
+
+    fn basic(&self, address: H160) -> Basic {
+        self.backend.basic(address)
+    }
+
+    fn code(&self, address: H160) -> Vec<u8> {
+        self.backend.code(address)
+    }
+
+    fn storage(&self, address: H160, index: H256) -> H256 {
+        self.backend.storage(address, index)
+    }
+
+    fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
+        self.backend.original_storage(address, index)
+    }
+}
+
+impl<B: Backend + ApplyBackend + Adapter> ApplyBackend for StateDiffRecorder<'_, B> {
+    fn apply<A, I, L>(&mut self, values: A, logs: L, delete_empty: bool)
+    where
+        A: IntoIterator<Item = Apply<I>>,
+        I: IntoIterator<Item = (H256, H256)>,
+        L: IntoIterator<Item = Log>,
+    {
+        let values = values
+            .into_iter()
+            .map(|apply| match apply {
+                Apply::Modify {
+                    address,
+                    basic,
+                    code,
+                    storage,
+                    reset_storage,
+                } => {
+                    self.record_account(address);
+                    let storage = storage.into_iter().collect::<Vec<_>>();
+                    for (index, _) in storage.iter() {
+                        self.record_slot(address, *index);
+                    }
+                    Apply::Modify {
+                        address,
+                        basic,
+                        code,
+                        storage,
+                        reset_storage,
+                    }
+                }
+                Apply::Delete { address } => {
+                    self.record_account(address);
+                    Apply::Delete { address }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        self.backend.apply(values, logs, delete_empty)
+    }
+}
+
+impl<B: Backend + ApplyBackend + Adapter> Adapter for StateDiffRecorder<'_, B> {
+    fn set_origin(&mut self, origin: H160) {
+        self.backend.set_origin(origin)
+    }
+
+    fn set_gas_price(&mut self, gas_price: U256) {
+        self.backend.set_gas_price(gas_price)
+    }
+
+    fn get_logs(&mut self) -> Vec<Log> {
+        self.backend.get_logs()
+    }
+
+    fn commit(&mut self) -> MerkleRoot {
+        self.backend.commit()
+    }
+
+    fn current_root(&self) -> MerkleRoot {
+        self.backend.current_root()
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.backend.get(key)
+    }
+
+    fn get_ctx(&self) -> ExecutorContext {
+        self.backend.get_ctx()
+    }
+
+    fn get_account(&self, address: H160) -> Account {
+        self.backend.get_account(address)
+    }
+
+    fn save_account(&mut self, address: H160, account: &Account) {
+        self.record_account(address);
+        self.backend.save_account(address, account)
+    }
+}
@@ -1,5 +1,6 @@
 use rt_evm_model::types::{
-    Bloom, Hasher, Log, MerkleRoot, SignedTransaction, H160, H256, NIL_HASH, U256,
+    Apply, Basic, CheckPointConfig, Hasher, MerkleRoot, SignedTransaction, H160, H256,
+    NIL_HASH, U256,
 };
 use std::fmt::Debug;
 
@@ -7,7 +8,6 @@ const FUNC_SELECTOR_LEN: usize = 4;
 const U256_BE_BYTES_LEN: usize = 32;
 const REVERT_MSG_LEN_OFFSET: usize = FUNC_SELECTOR_LEN + U256_BE_BYTES_LEN;
 const REVERT_EFFECT_MSG_OFFSET: usize = REVERT_MSG_LEN_OFFSET + U256_BE_BYTES_LEN;
-const BLOOM_BYTE_LENGTH: usize = 256;
 const EXEC_REVERT: &str = "execution reverted: ";
 
 pub fn code_address(sender: H160, nonce: &U256) -> H256 {
@@ -42,27 +42,50 @@ pub fn decode_revert_msg(input: &[u8]) -> String {
     decode_reason(&input[REVERT_EFFECT_MSG_OFFSET..end_offset])
 }
 
-pub fn logs_bloom<'a, I>(logs: I) -> Bloom
+// EIP-6780, SELFDESTRUCT only deletes the accounts created in the same tx,
+// for the others, only the balance is transferred, which has been done by the evm.
+//
+// `prev_state` returns the nonce and the code size of an account before the tx,
+// an account without any of them is created in this tx.
+pub(crate) fn eip6780_apply<I>(
+    apply: Apply<I>,
+    prev_state: impl Fn(H160) -> (U256, usize),
+) -> Apply<Vec<(H256, H256)>>
 where
-    I: Iterator<Item = &'a Log>,
+    I: IntoIterator<Item = (H256, H256)>,
 {
-    let mut bloom = Bloom::zero();
-
-    for log in logs {
-        m3_2048(&mut bloom, log.address.as_bytes());
-        for topic in log.topics.iter() {
-            m3_2048(&mut bloom, topic.as_bytes());
+    match apply {
+        Apply::Modify {
+            address,
+            basic,
+            code,
+            storage,
+            reset_storage,
+        } => Apply::Modify {
+            address,
+            basic,
+            code,
+            storage: storage.into_iter().collect(),
+            reset_storage,
+        },
+        Apply::Delete { address } => {
+            let (nonce, code_size) = prev_state(address);
+            if nonce.is_zero() && 0 == code_size {
+                Apply::Delete { address }
+            } else {
+                Apply::Modify {
+                    address,
+                    basic: Basic {
+                        balance: U256::zero(),
+                        nonce,
+                    },
+                    code: None,
+                    storage: vec![],
+                    reset_storage: false,
+                }
+            }
         }
     }
-    bloom
-}
-
-fn m3_2048(bloom: &mut Bloom, x: &[u8]) {
-    let hash = Hasher::digest(x).0;
-    for i in [0, 2, 4] {
-        let bit = (hash[i + 1] as usize + ((hash[i] as usize) << 8)) & 0x7FF;
-        bloom.0[BLOOM_BYTE_LENGTH - 1 - bit / 8] |= 1 << (bit % 8);
-    }
 }
 
 pub fn trie_root<A, B>(input: Vec<(A, B)>) -> MerkleRoot
@@ -93,6 +116,23 @@ where
     }
 }
 
+/// Same as `trie_root_indexed`, but the root of an empty input
+/// is decided by the `checkpoint` for the block at `height`.
+pub fn trie_root_indexed_at<I>(
+    checkpoint: &CheckPointConfig,
+    height: u64,
+    input: &[I],
+) -> MerkleRoot
+where
+    I: AsRef<[u8]> + Debug,
+{
+    if input.is_empty() {
+        checkpoint.empty_root(height)
+    } else {
+        trie_root_indexed(input)
+    }
+}
+
 pub fn trie_root_txs(input: &[SignedTransaction]) -> MerkleRoot {
     trie_root_indexed(
         &input
@@ -133,4 +173,87 @@ mod tests {
             "cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"
         )
     }
+
+    #[test]
+    fn test_eip6780_apply() {
+        let existing = H160::random();
+        let created = H160::random();
+
+        let prev_state = |addr: H160| {
+            if addr == existing {
+                (U256::one(), 128)
+            } else {
+                (U256::zero(), 0)
+            }
+        };
+
+        // a contract created in a previous tx keeps its code and storage,
+        // only the balance is transferred out
+        let apply = Apply::<Vec<(H256, H256)>>::Delete { address: existing };
+        match eip6780_apply(apply, prev_state) {
+            Apply::Modify {
+                address,
+                basic,
+                code,
+                storage,
+                reset_storage,
+            } => {
+                assert_eq!(address, existing);
+                assert_eq!(basic.balance, U256::zero());
+                assert_eq!(basic.nonce, U256::one());
+                assert!(code.is_none());
+                assert!(storage.is_empty());
+                assert!(!reset_storage);
+            }
+            Apply::Delete { .. } => panic!("should not be deleted"),
+        }
+
+        // a contract created in the same tx is deleted
+        let apply = Apply::<Vec<(H256, H256)>>::Delete { address: created };
+        assert!(matches!(
+            eip6780_apply(apply, prev_state),
+            Apply::Delete { address } if address == created
+        ));
+
+        // modifications are kept as is
+        let kv = (H256::random(), H256::random());
+        let apply = Apply::Modify {
+            address: existing,
+            basic: Basic {
+                balance: U256::one(),
+                nonce: U256::one(),
+            },
+            code: None,
+            storage: vec![kv],
+            reset_storage: false,
+        };
+        assert!(matches!(
+            eip6780_apply(apply, prev_state),
+            Apply::Modify { storage, .. } if storage == vec![kv]
+        ));
+    }
+
+    #[test]
+    fn test_empty_trie_root() {
+        use rt_evm_model::types::RLP_NULL;
+
+        let empty: &[H256] = &[];
+        let hashes = [Hasher::digest(b"receipt")];
+
+        let disabled = CheckPointConfig::default();
+        assert_eq!(NIL_HASH, trie_root_indexed(empty));
+        assert_eq!(NIL_HASH, trie_root_indexed_at(&disabled, 0, empty));
+
+        let cp = CheckPointConfig {
+            empty_root_height: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(RLP_NULL, trie_root_indexed_at(&cp, 0, empty));
+        assert_eq!(RLP_NULL, trie_root_indexed_at(&cp, u64::MAX, empty));
+        assert_eq!(
+            trie_root_indexed(&hashes),
+            trie_root_indexed_at(&cp, 0, &hashes)
+        );
+        assert_ne!(RLP_NULL, trie_root_indexed_at(&cp, 0, &hashes));
+    }
 }
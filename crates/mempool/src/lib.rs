@@ -3,17 +3,18 @@
 
 use parking_lot::{Mutex, RwLock};
 use rt_evm_model::{
+    lazy::{base_fee_per_gas, is_fee_free},
     traits::TxStorage,
     types::{
-        Account, BlockNumber, Hash, SignedTransaction as SignedTx, H160,
-        MAX_BLOCK_GAS_LIMIT, MIN_TRANSACTION_GAS_LIMIT, U256,
+        Account, BlockNumber, Hash, SignedTransaction as SignedTx, UnsignedTransaction,
+        H160, MAX_BLOCK_GAS_LIMIT, MIN_TRANSACTION_GAS_LIMIT, U256,
     },
 };
 use rt_evm_storage::{get_account_by_backend, MptStore, Storage};
 use ruc::*;
 use std::{
     cmp::Ordering,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     mem,
     sync::{
         atomic::{AtomicBool, AtomicU64, Ordering as AtoOrd},
@@ -25,29 +26,41 @@ use std::{
 // decrease from u64::MAX
 static TX_INDEXER: AtomicU64 = AtomicU64::new(u64::MAX);
 
+// the interval of the background cleaner
+const CLEANER_INTERVAL_MS: u64 = 1000;
+
+// the max number of the evicted txs to remember
+const EVICTED_TXS_CAP: usize = 10_0000;
+
 pub use TinyMempool as Mempool;
 
+// `Send` and `Sync` are derived, there is no `unsafe impl` for them:
+// every mutable field is behind an `Arc` of a lock or of atomics,
+// and the locks are always taken in the order numbered on the fields,
+// so no thread can wait for a lock while holding a higher numbered one.
 #[derive(Clone)]
 pub struct TinyMempool {
     // if number of tx exceed the capacity, deny new txs
     //
+    // key: the index of tx, the newer the smaller
+    // value: (<expiry timestamp of tx>, tx)
+    //
     // NOTE: lock order number is 1
-    txs: Arc<Mutex<BTreeMap<u64, SignedTx>>>,
+    txs: Arc<Mutex<BTreeMap<u64, (u64, SignedTx)>>>,
 
-    // key: <timestamp of tx> % <lifetime limitation>
-    // value: the index of tx in `txs`
+    // transactions with future nonces,
+    // will be promoted into `txs` when the nonce gap is filled
     //
-    // discard_guard = tx_lifetime_fields.split_off(ts!() % <lifetime limitation> - 2)
+    // key: <sender>, value: {<nonce> => (<expiry timestamp of tx>, tx)}
     //
-    // min_tx_index_to_discard = discard_gurad.pop_last().1
-    // txs_to_discard = txs.split_off(min_tx_index_to_discard)
-    //
-    // decrease pending cnter based on txs_to_discard
-    //
-    tx_lifetime_fields: Arc<Mutex<BTreeMap<u64, u64>>>,
+    // NOTE: lock order number is 2
+    queued_txs: Arc<Mutex<HashMap<H160, BTreeMap<U256, (u64, SignedTx)>>>>,
 
-    // record transactions that need to be broadcasted
-    broadcast_queue: Arc<Mutex<Vec<SignedTx>>>,
+    // record transactions that need to be broadcasted,
+    // bounded by the capacity in case nobody takes them
+    //
+    // NOTE: lock order number is 4
+    broadcast_queue: Arc<Mutex<BroadcastQueue>>,
 
     // pending transactions of each account
     //
@@ -57,6 +70,35 @@ pub struct TinyMempool {
     // if `true`, the background thread will exit itself.
     stop_cleaner: Arc<AtomicBool>,
 
+    // the hashes of the transactions confirmed by this instance
+    mined_txs: Arc<TxHashFilter>,
+
+    // the transactions dropped without being confirmed
+    //
+    // NOTE: lock order number is 3
+    evicted_txs: Arc<Mutex<EvictedTxs>>,
+
+    // which addresses can send or receive txs,
+    // can be replaced at runtime through the shared handler
+    //
+    // NOTE: never held together with any other lock
+    address_policy: Arc<RwLock<AddressPolicy>>,
+
+    // how to handle the new txs that can not afford the current base fee
+    //
+    // NOTE: never held together with any other lock
+    base_fee_policy: Arc<RwLock<BaseFeePolicy>>,
+
+    // how to handle the resubmitted txs that are already in the pool
+    //
+    // NOTE: never held together with any other lock
+    duplicate_policy: Arc<RwLock<DuplicatePolicy>>,
+
+    // in which order the txs are broadcasted
+    //
+    // NOTE: never held together with any other lock
+    broadcast_priority: Arc<RwLock<BroadcastPriority>>,
+
     // for tx pre-check
     trie_db: Arc<MptStore>,
 
@@ -73,77 +115,128 @@ impl TinyMempool {
         tx_gas_cap: Option<u64>,
         trie_db: Arc<MptStore>,
         storage: Arc<Storage>,
+    ) -> Arc<Self> {
+        Self::new_with_clock(
+            capacity,
+            tx_lifetime_in_secs,
+            tx_gas_cap,
+            trie_db,
+            storage,
+            now_secs,
+        )
+    }
+
+    /// Same as `new`, but read the current timestamp in seconds from the `clock`,
+    /// which decides when the txs expire, e.g. a mock clock in the tests.
+    pub fn new_with_clock(
+        capacity: u64,
+        tx_lifetime_in_secs: u64,
+        tx_gas_cap: Option<u64>,
+        trie_db: Arc<MptStore>,
+        storage: Arc<Storage>,
+        clock: fn() -> u64,
     ) -> Arc<Self> {
         let address_pending_cnter = Arc::new(RwLock::new(map! {}));
 
         let ret = Self {
             txs: Arc::new(Mutex::new(BTreeMap::new())),
-            tx_lifetime_fields: Arc::new(Mutex::new(BTreeMap::new())),
-            broadcast_queue: Arc::new(Mutex::new(vec![])),
+            queued_txs: Arc::new(Mutex::new(map! {})),
+            broadcast_queue: Arc::new(Mutex::new(BroadcastQueue::new(
+                capacity as usize,
+            ))),
             address_pending_cnter,
             stop_cleaner: Arc::new(AtomicBool::new(false)),
+            mined_txs: Arc::new(TxHashFilter::new()),
+            evicted_txs: Arc::new(Mutex::new(EvictedTxs::new(EVICTED_TXS_CAP))),
+            address_policy: Arc::new(RwLock::new(AddressPolicy::default())),
+            base_fee_policy: Arc::new(RwLock::new(BaseFeePolicy::default())),
+            duplicate_policy: Arc::new(RwLock::new(DuplicatePolicy::default())),
+            broadcast_priority: Arc::new(RwLock::new(BroadcastPriority::default())),
             trie_db,
             storage,
             cfg: TinyMempoolCfg {
                 capacity,
                 tx_lifetime_in_secs,
                 tx_gas_cap: tx_gas_cap.unwrap_or(MAX_BLOCK_GAS_LIMIT).into(),
+                clock,
             },
         };
         let ret = Arc::new(ret);
 
         let hdr_ret = Arc::clone(&ret);
-        thread::spawn(move || {
-            loop {
-                sleep_ms!(tx_lifetime_in_secs * 1000);
+        thread::spawn(move || loop {
+            sleep_ms!(CLEANER_INTERVAL_MS);
 
-                if hdr_ret.stop_cleaner.load(AtoOrd::Relaxed) {
-                    return;
-                }
-
-                let mut ts_guard = ts!() % tx_lifetime_in_secs;
-                alt!(3 > ts_guard, continue);
-                ts_guard -= 2;
-
-                let mut to_discard =
-                    if let Some(mut tlf) = hdr_ret.tx_lifetime_fields.try_lock() {
-                        let mut to_keep = tlf.split_off(&ts_guard);
-                        mem::swap(&mut to_keep, &mut tlf);
-                        to_keep // now is 'to_discard'
-                    } else {
-                        continue;
-                    };
-
-                let idx_gurad = if let Some((_, idx)) = to_discard.pop_last() {
-                    idx
-                } else {
-                    continue;
-                };
-
-                // For avoiding 'dead lock',
-                // we call `collect` and then `iter` again
-                let to_del = hdr_ret
-                    .txs
-                    .lock()
-                    .split_off(&idx_gurad)
-                    .into_values()
-                    .collect::<Vec<_>>();
-                let mut pending_cnter = hdr_ret.address_pending_cnter.write();
-                to_del.iter().for_each(|tx| {
-                    if let Some(i) = pending_cnter.get_mut(&tx.sender) {
-                        i.remove(&tx.transaction.hash);
-                    }
-                });
+            if hdr_ret.stop_cleaner.load(AtoOrd::Relaxed) {
+                return;
             }
+
+            hdr_ret.evict_expired();
         });
 
         ret
     }
 
+    // drop the txs whose lifetime has passed, run by the background cleaner
+    fn evict_expired(&self) {
+        let ts = (self.cfg.clock)();
+
+        let mut expired_queued = vec![];
+        self.queued_txs.lock().retain(|_, txs| {
+            txs.retain(|_, (expire_at, tx)| {
+                let alive = *expire_at > ts;
+                if !alive {
+                    expired_queued.push(tx.transaction.hash);
+                }
+                alive
+            });
+            !txs.is_empty()
+        });
+        self.evicted_txs
+            .lock()
+            .extend(expired_queued, EvictionReason::Expired);
+
+        // For avoiding 'dead lock',
+        // we call `collect` and then `iter` again
+        let to_del = {
+            let mut txs = self.txs.lock();
+
+            // the older the larger index, so scan from the end
+            let expired = txs
+                .iter()
+                .rev()
+                .take_while(|(_, (expire_at, _))| *expire_at <= ts)
+                .map(|(idx, _)| *idx)
+                .collect::<Vec<_>>();
+
+            expired
+                .into_iter()
+                .filter_map(|idx| txs.remove(&idx))
+                .map(|(_, tx)| tx)
+                .collect::<Vec<_>>()
+        };
+
+        if to_del.is_empty() {
+            return;
+        }
+
+        self.evicted_txs.lock().extend(
+            to_del.iter().map(|tx| tx.transaction.hash),
+            EvictionReason::Expired,
+        );
+
+        let mut pending_cnter = self.address_pending_cnter.write();
+        to_del.iter().for_each(|tx| {
+            if let Some(i) = pending_cnter.get_mut(&tx.sender) {
+                i.remove(&tx.transaction.hash);
+            }
+        });
+    }
+
     // Add a new transaction to mempool
     #[cfg_attr(feature = "benchmark", allow(dead_code))]
     pub fn tx_insert(&self, tx: SignedTx, signature_checked: bool) -> Result<()> {
-        if self.tx_pending_cnt(None) > self.cfg.capacity {
+        if self.tx_pending_cnt(None) + self.tx_queued_cnt(None) > self.cfg.capacity {
             return Err(eg!("Mempool is full"));
         }
 
@@ -154,29 +247,104 @@ impl TinyMempool {
             .and_then(|m| m.get(&tx.transaction.hash))
             .is_some()
         {
+            return on_duplicate(self.duplicate_policy());
+        }
+
+        let sender = tx.sender;
+        let nonce = *tx.transaction.unsigned.nonce();
+
+        let queued_hash = self
+            .queued_txs
+            .lock()
+            .get(&sender)
+            .and_then(|m| m.get(&nonce))
+            .map(|(_, q)| q.transaction.hash);
+        if let Some(h) = queued_hash {
+            // only an identical tx is a duplicate
+            if h == tx.transaction.hash {
+                return on_duplicate(self.duplicate_policy());
+            }
             return Err(eg!("Already cached in mempool"));
         }
 
         #[cfg(not(feature = "benchmark"))]
-        self.tx_pre_check(&tx, signature_checked).c(d!())?;
+        {
+            let queue_underpriced = BaseFeePolicy::Queue == *self.base_fee_policy.read();
+            self.tx_check(&tx, signature_checked, !queue_underpriced)
+                .c(d!())?;
+        }
 
         self.broadcast_queue.lock().push(tx.clone());
 
+        let account_nonce = self.get_account(sender, None).c(d!())?.nonce;
+        let next_nonce =
+            account_nonce + self.tx_pending_contiguous_cnt(sender, account_nonce);
+
+        // there is a nonce gap, wait for it to be filled
+        if nonce > next_nonce {
+            self.queued_txs
+                .lock()
+                .entry(sender)
+                .or_default()
+                .insert(nonce, (self.expiry(), tx));
+            return Ok(());
+        }
+
+        if !self.tx_insert_pending(tx) {
+            return Err(eg!("Already cached in mempool"));
+        }
+        self.tx_promote(sender, nonce + 1);
+
+        Ok(())
+    }
+
+    // return `false` if the tx is already pending
+    //
+    // The duplicate check at the beginning of `tx_insert` does not hold the lock
+    // during the pre-check, two threads inserting the same tx may both pass it,
+    // so check again here, and keep the counter locked until the tx is in `txs`,
+    // or a concurrent `tx_cleanup` may miss it and leave it in `txs` forever.
+    fn tx_insert_pending(&self, tx: SignedTx) -> bool {
+        let mut pending_cnter = self.address_pending_cnter.write();
+        let pending = pending_cnter.entry(tx.sender).or_insert(map! {});
+        if pending.contains_key(&tx.transaction.hash) {
+            return false;
+        }
+
         let idx = TX_INDEXER.fetch_sub(1, AtoOrd::Relaxed);
+        pending.insert(tx.transaction.hash, idx);
 
-        self.address_pending_cnter
-            .write()
-            .entry(tx.sender)
-            .or_insert(map! {})
-            .insert(tx.transaction.hash, idx);
+        self.txs.lock().insert(idx, (self.expiry(), tx));
 
-        self.tx_lifetime_fields
-            .lock()
-            .insert(ts!() % self.cfg.tx_lifetime_in_secs, idx);
+        true
+    }
 
-        self.txs.lock().insert(idx, tx);
+    // the expiry timestamp of a tx inserted now
+    fn expiry(&self) -> u64 {
+        (self.cfg.clock)() + self.cfg.tx_lifetime_in_secs
+    }
 
-        Ok(())
+    // move the queued transactions of the `sender` into the pending pool,
+    // starting from `next_nonce`, until a nonce gap is met
+    fn tx_promote(&self, sender: H160, mut next_nonce: U256) {
+        let mut to_promote = vec![];
+
+        {
+            let mut queued = self.queued_txs.lock();
+            if let Some(txs) = queued.get_mut(&sender) {
+                while let Some((_, tx)) = txs.remove(&next_nonce) {
+                    to_promote.push(tx);
+                    next_nonce += U256::one();
+                }
+                if txs.is_empty() {
+                    queued.remove(&sender);
+                }
+            }
+        }
+
+        to_promote.into_iter().for_each(|tx| {
+            self.tx_insert_pending(tx);
+        });
     }
 
     // transactions that !maybe! have not been confirmed
@@ -192,66 +360,246 @@ impl TinyMempool {
         }
     }
 
+    // the number of pending txs of `addr` whose nonces are contiguous from `start_nonce`,
+    // that is, `start_nonce` + <the returned value> is the next nonce of `addr`
+    pub fn tx_pending_contiguous_cnt(&self, addr: H160, start_nonce: U256) -> u64 {
+        let pending_cnter = self.address_pending_cnter.read();
+        let idxs = match pending_cnter.get(&addr) {
+            Some(i) => i,
+            None => return 0,
+        };
+
+        let txs = self.txs.lock();
+        let nonces = idxs
+            .values()
+            .filter_map(|idx| txs.get(idx))
+            .map(|(_, tx)| *tx.transaction.unsigned.nonce())
+            .collect::<BTreeSet<_>>();
+
+        contiguous_cnt(start_nonce, &nonces)
+    }
+
+    // transactions that are waiting for their nonce gaps to be filled
+    pub fn tx_queued_cnt(&self, addr: Option<H160>) -> u64 {
+        let queued = self.queued_txs.lock();
+        if let Some(addr) = addr {
+            queued
+                .get(&addr)
+                .map(|i| i.len() as u64)
+                .unwrap_or_default()
+        } else {
+            queued.values().map(|i| i.len() as u64).sum()
+        }
+    }
+
+    // whether the tx is waiting in the pending pool or the queue
+    pub fn tx_is_pooled(&self, hash: &Hash) -> bool {
+        self.address_pending_cnter
+            .read()
+            .values()
+            .any(|i| i.contains_key(hash))
+            || self
+                .queued_txs
+                .lock()
+                .values()
+                .flat_map(|i| i.values())
+                .any(|(_, tx)| &tx.transaction.hash == hash)
+    }
+
+    // why the tx has been dropped from the mempool without being confirmed,
+    // only the recently evicted txs are remembered
+    pub fn was_recently_evicted(&self, hash: &Hash) -> Option<EvictionReason> {
+        self.evicted_txs.lock().get(hash).map(|(reason, _)| reason)
+    }
+
+    /// The shared handler of the address policy,
+    /// changes made through it take effect on the next pre-check.
+    pub fn address_policy(&self) -> Arc<RwLock<AddressPolicy>> {
+        Arc::clone(&self.address_policy)
+    }
+
+    /// Replace the address policy, no restart is needed.
+    pub fn set_address_policy(&self, policy: AddressPolicy) {
+        *self.address_policy.write() = policy;
+    }
+
+    pub fn base_fee_policy(&self) -> BaseFeePolicy {
+        *self.base_fee_policy.read()
+    }
+
+    pub fn set_base_fee_policy(&self, policy: BaseFeePolicy) {
+        *self.base_fee_policy.write() = policy;
+    }
+
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        *self.duplicate_policy.read()
+    }
+
+    pub fn set_duplicate_policy(&self, policy: DuplicatePolicy) {
+        *self.duplicate_policy.write() = policy;
+    }
+
+    pub fn broadcast_priority(&self) -> BroadcastPriority {
+        *self.broadcast_priority.read()
+    }
+
+    pub fn set_broadcast_priority(&self, policy: BroadcastPriority) {
+        *self.broadcast_priority.write() = policy;
+    }
+
+    /// Drop all the pending and queued txs at once, e.g. after a spam flood,
+    /// return the number of the dropped ones.
+    ///
+    /// The dropped txs are not recorded as evicted, and the later txs of the same
+    /// senders are queued until the nonce gaps left by them are filled again.
+    pub fn clear(&self) -> u64 {
+        // take all the locks in order, so no tx is half inserted or half cleaned,
+        // the background cleaner only removes what is still left
+        let mut pending_cnter = self.address_pending_cnter.write();
+        let mut txs = self.txs.lock();
+        let mut queued = self.queued_txs.lock();
+        let mut broadcast = self.broadcast_queue.lock();
+
+        let n = txs.len() + queued.values().map(|i| i.len()).sum::<usize>();
+
+        pending_cnter.clear();
+        txs.clear();
+        queued.clear();
+        broadcast.clear();
+
+        n as u64
+    }
+
+    // (<number of pending txs>, <number of queued txs>)
+    pub fn tx_pool_status(&self) -> (u64, u64) {
+        (self.tx_pending_cnt(None), self.tx_queued_cnt(None))
+    }
+
     // broadcast transactions to other nodes ?
+    //
+    // only the ones still in the mempool are returned,
+    // each of them once, in the order of the `BroadcastPriority`
     pub fn tx_take_broadcast(&self) -> Vec<SignedTx> {
-        mem::take(&mut *self.broadcast_queue.lock())
+        let priority = self.broadcast_priority();
+
+        let pending_cnter = self.address_pending_cnter.read();
+        let queued = self.queued_txs.lock();
+
+        let mut ret = self.broadcast_queue.lock().take(|tx| {
+            let hash = &tx.transaction.hash;
+            pending_cnter
+                .get(&tx.sender)
+                .map_or(false, |m| m.contains_key(hash))
+                || queued
+                    .get(&tx.sender)
+                    .and_then(|m| m.get(tx.transaction.unsigned.nonce()))
+                    .map_or(false, |(_, q)| &q.transaction.hash == hash)
+        });
+
+        sort_broadcast(&mut ret, priority, base_fee_per_gas());
+
+        ret
     }
 
     // package some transactions for proposing a new block ?
     pub fn tx_take_propose(&self, limit: usize) -> Vec<SignedTx> {
+        let base_fee = base_fee_per_gas();
+
+        // the underpriced ones wait in the pool until the base fee drops enough
         let mut ret = self
             .txs
             .lock()
             .iter()
             .rev()
+            .map(|(_, (_, tx))| tx)
+            .filter(|tx| check_base_fee(&tx.transaction.unsigned, base_fee).is_ok())
             .take(limit)
-            .map(|(_, tx)| tx.clone())
+            .cloned()
             .collect::<Vec<_>>();
 
-        ret.sort_unstable_by(|a, b| {
-            let price_cmp = b
-                .transaction
-                .unsigned
-                .gas_price()
-                .cmp(&a.transaction.unsigned.gas_price());
-            if matches!(price_cmp, Ordering::Equal) {
-                a.transaction
-                    .unsigned
-                    .nonce()
-                    .cmp(b.transaction.unsigned.nonce())
-            } else {
-                price_cmp
-            }
-        });
+        ret.sort_unstable_by(|a, b| tx_propose_cmp(a, b, base_fee));
 
         ret
     }
 
     // Remove transactions after they have been confirmed ?
     pub fn tx_cleanup(&self, to_del: &[SignedTx]) {
-        let mut pending_cnter = self.address_pending_cnter.write();
-        let mut txs = self.txs.lock();
-        to_del.iter().for_each(|tx| {
-            if let Some(i) = pending_cnter.get_mut(&tx.sender) {
-                if let Some(idx) = i.remove(&tx.transaction.hash) {
-                    txs.remove(&idx);
+        // the next nonce of each sender after the confirmation
+        let mut next_nonces: HashMap<H160, U256> = map! {};
+
+        to_del
+            .iter()
+            .for_each(|tx| self.mined_txs.insert(&tx.transaction.hash));
+
+        {
+            let mut pending_cnter = self.address_pending_cnter.write();
+            let mut txs = self.txs.lock();
+            to_del.iter().for_each(|tx| {
+                if let Some(i) = pending_cnter.get_mut(&tx.sender) {
+                    if let Some(idx) = i.remove(&tx.transaction.hash) {
+                        txs.remove(&idx);
+                    }
                 }
-            }
+                let n = *tx.transaction.unsigned.nonce() + U256::one();
+                let next = next_nonces.entry(tx.sender).or_insert(n);
+                if *next < n {
+                    *next = n;
+                }
+            });
+        }
+
+        // the queued txs whose nonces have been used can never be confirmed
+        let mut stale = vec![];
+        {
+            let mut queued = self.queued_txs.lock();
+            next_nonces.iter().for_each(|(sender, n)| {
+                if let Some(mut txs) = queued.remove(sender) {
+                    let alive = txs.split_off(n);
+                    stale.extend(txs.values().map(|(_, tx)| tx.transaction.hash));
+                    if !alive.is_empty() {
+                        queued.insert(*sender, alive);
+                    }
+                }
+            });
+        }
+        if !stale.is_empty() {
+            self.evicted_txs
+                .lock()
+                .extend(stale, EvictionReason::NonceTooLow);
+        }
+
+        next_nonces.into_iter().for_each(|(sender, n)| {
+            let next_nonce = n + self.tx_pending_contiguous_cnt(sender, n);
+            self.tx_promote(sender, next_nonce);
         });
     }
 
     // Pre-check the tx before execute it.
+    /// Check if the `tx` can be included in a new block,
+    /// a tx that can not afford the current base fee is always rejected here,
+    /// whatever the `BaseFeePolicy` is.
     pub fn tx_pre_check(&self, tx: &SignedTx, signature_checked: bool) -> Result<()> {
+        self.tx_check(tx, signature_checked, true)
+    }
+
+    fn tx_check(
+        &self,
+        tx: &SignedTx,
+        signature_checked: bool,
+        base_fee_checked: bool,
+    ) -> Result<()> {
         let utx = &tx.transaction;
 
-        let gas_price = utx.unsigned.gas_price();
+        self.address_policy
+            .read()
+            .check(tx.sender, tx.get_to())
+            .c(d!())?;
 
-        if gas_price == U256::zero() {
-            return Err(eg!("The 'gas price' is zero"));
-        }
+        let gas_price = utx.unsigned.gas_price();
+        check_gas_price(gas_price, is_fee_free()).c(d!())?;
 
-        if gas_price >= U256::from(u64::MAX) {
-            return Err(eg!("The 'gas price' exceeds the limition(u64::MAX)"));
+        if base_fee_checked {
+            check_base_fee(&utx.unsigned, base_fee_per_gas()).c(d!())?;
         }
 
         let gas_limit = *utx.unsigned.gas_limit();
@@ -263,6 +611,15 @@ impl TinyMempool {
             ));
         }
 
+        let intrinsic_gas = utx.unsigned.intrinsic_gas();
+
+        if gas_limit < intrinsic_gas.into() {
+            return Err(eg!(
+                "The 'gas limit' less than the intrinsic gas({})",
+                intrinsic_gas
+            ));
+        }
+
         if gas_limit > self.cfg.tx_gas_cap {
             return Err(eg!(
                 "The 'gas limit' exceeds the gas capacity({})",
@@ -286,7 +643,12 @@ impl TinyMempool {
             return Err(eg!("Insufficient balance to cover possible gas"));
         }
 
-        if self.storage.get_tx_by_hash(&utx.hash).c(d!())?.is_some() {
+        // A miss of the filter is also safe for the txs confirmed before
+        // this instance was created (e.g. before a restart),
+        // they have been rejected by the nonce check above.
+        if self.mined_txs.may_contain(&utx.hash)
+            && self.storage.get_tx_by_hash(&utx.hash).c(d!())?.is_some()
+        {
             return Err(eg!("Historical transaction detected"));
         }
 
@@ -307,5 +669,645 @@ impl TinyMempool {
 struct TinyMempoolCfg {
     capacity: u64,
     tx_lifetime_in_secs: u64,
-    tx_gas_cap: U256, // for tx pre-check
+    tx_gas_cap: U256,   // for tx pre-check
+    clock: fn() -> u64, // the current timestamp in seconds
+}
+
+fn now_secs() -> u64 {
+    ts!()
+}
+
+/// Restrict the senders and the recipients of the accepted txs.
+///
+/// # NOTE
+///
+/// The policy also applies to the txs of the proposals from other nodes,
+/// so all nodes of a network should share the same policy.
+#[derive(Clone, Debug, Default)]
+pub struct AddressPolicy {
+    pub senders: AddressFilter,
+    // contract creations have no recipient, they are not restricted by this filter
+    pub recipients: AddressFilter,
+}
+
+impl AddressPolicy {
+    pub fn check(&self, sender: H160, recipient: Option<H160>) -> Result<()> {
+        if !self.senders.permits(&sender) {
+            return Err(eg!("The sender({:?}) is rejected by the policy", sender));
+        }
+
+        if let Some(recipient) = recipient {
+            if !self.recipients.permits(&recipient) {
+                return Err(eg!(
+                    "The recipient({:?}) is rejected by the policy",
+                    recipient
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How to handle the new txs whose max fee per gas can not cover the current base fee,
+/// such txs can never be included in a block before the base fee drops enough.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BaseFeePolicy {
+    // reject them at once
+    #[default]
+    Reject,
+    // keep them in the pool, but never propose them before the base fee drops enough,
+    // they are dropped at last if they expire
+    Queue,
+}
+
+/// How to handle a resubmitted tx that is already in the pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    // reject it with an error
+    #[default]
+    Reject,
+    // accept it without inserting it again, for the relayers resubmitting txs
+    Ignore,
+}
+
+/// In which order the txs are broadcasted to other nodes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BroadcastPriority {
+    // the earlier inserted first
+    #[default]
+    InsertionOrder,
+    // the higher effective tip under the current base fee first,
+    // so the most valuable txs reach the peers fastest
+    FeeDescending,
+}
+
+fn sort_broadcast(txs: &mut [SignedTx], priority: BroadcastPriority, base_fee: U256) {
+    if BroadcastPriority::FeeDescending == priority {
+        txs.sort_by(|a, b| tx_propose_cmp(a, b, base_fee));
+    }
+}
+
+fn on_duplicate(policy: DuplicatePolicy) -> Result<()> {
+    match policy {
+        DuplicatePolicy::Reject => Err(eg!("Already cached in mempool")),
+        DuplicatePolicy::Ignore => Ok(()),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum AddressFilter {
+    // no filtering
+    #[default]
+    Any,
+    // only the listed addresses are permitted
+    Allow(HashSet<H160>),
+    // all addresses except the listed ones are permitted
+    Deny(HashSet<H160>),
+}
+
+impl AddressFilter {
+    pub fn permits(&self, addr: &H160) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Allow(list) => list.contains(addr),
+            Self::Deny(list) => !list.contains(addr),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictionReason {
+    // not confirmed within the lifetime
+    Expired,
+    // the nonce has been used by another confirmed tx
+    NonceTooLow,
+}
+
+// A bounded queue of the txs to be broadcasted,
+// the oldest ones are dropped first if nobody takes them.
+struct BroadcastQueue {
+    cap: usize,
+    txs: VecDeque<SignedTx>,
+}
+
+impl BroadcastQueue {
+    fn new(cap: usize) -> Self {
+        Self {
+            cap,
+            txs: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, tx: SignedTx) {
+        self.txs.push_back(tx);
+        while self.txs.len() > self.cap {
+            self.txs.pop_front();
+        }
+    }
+
+    // take all of them, but only return the ones still wanted,
+    // a tx reinserted after being dropped is returned once
+    fn take(&mut self, mut wanted: impl FnMut(&SignedTx) -> bool) -> Vec<SignedTx> {
+        let mut seen = HashSet::new();
+        mem::take(&mut self.txs)
+            .into_iter()
+            .filter(|tx| seen.insert(tx.transaction.hash) && wanted(tx))
+            .collect()
+    }
+
+    fn clear(&mut self) {
+        self.txs.clear();
+    }
+}
+
+// A bounded record of the evicted txs,
+// the least recently evicted ones are forgotten first.
+struct EvictedTxs {
+    cap: usize,
+    // tx hash => (<reason>, <eviction timestamp>)
+    txs: HashMap<Hash, (EvictionReason, u64)>,
+    order: VecDeque<Hash>,
+}
+
+impl EvictedTxs {
+    fn new(cap: usize) -> Self {
+        Self {
+            cap,
+            txs: map! {},
+            order: VecDeque::new(),
+        }
+    }
+
+    fn extend(
+        &mut self,
+        hashes: impl IntoIterator<Item = Hash>,
+        reason: EvictionReason,
+    ) {
+        let ts = ts!();
+
+        hashes.into_iter().for_each(|h| {
+            if self.txs.insert(h, (reason, ts)).is_none() {
+                self.order.push_back(h);
+            }
+        });
+
+        while self.order.len() > self.cap {
+            if let Some(h) = self.order.pop_front() {
+                self.txs.remove(&h);
+            }
+        }
+    }
+
+    fn get(&self, hash: &Hash) -> Option<(EvictionReason, u64)> {
+        self.txs.get(hash).copied()
+    }
+}
+
+// A bloom filter of tx hashes,
+// a miss means the hash has definitely not been inserted.
+struct TxHashFilter {
+    bits: Vec<AtomicU64>,
+}
+
+impl TxHashFilter {
+    // 2^24 bits, 2 MB in memory
+    const BITS_NUM: usize = 1 << 24;
+
+    fn new() -> Self {
+        Self {
+            bits: (0..Self::BITS_NUM / 64)
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+        }
+    }
+
+    // tx hashes are keccak256 digests which are uniformly distributed,
+    // so slices of them can be used as the bit positions directly
+    fn positions(hash: &Hash) -> impl Iterator<Item = usize> + '_ {
+        hash.as_bytes()
+            .chunks(4)
+            .take(3)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as usize)
+            .map(|n| n % Self::BITS_NUM)
+    }
+
+    fn insert(&self, hash: &Hash) {
+        Self::positions(hash).for_each(|p| {
+            self.bits[p / 64].fetch_or(1 << (p % 64), AtoOrd::Relaxed);
+        });
+    }
+
+    fn may_contain(&self, hash: &Hash) -> bool {
+        Self::positions(hash)
+            .all(|p| self.bits[p / 64].load(AtoOrd::Relaxed) & (1 << (p % 64)) != 0)
+    }
+}
+
+// a zero gas price is only acceptable in the fee-free mode
+fn check_gas_price(gas_price: U256, fee_free: bool) -> Result<()> {
+    if gas_price == U256::zero() && !fee_free {
+        return Err(eg!("The 'gas price' is zero"));
+    }
+
+    if gas_price >= U256::from(u64::MAX) {
+        return Err(eg!("The 'gas price' exceeds the limition(u64::MAX)"));
+    }
+
+    Ok(())
+}
+
+// the max fee per gas of a tx must cover the base fee of the block including it
+fn check_base_fee(utx: &UnsignedTransaction, base_fee: U256) -> Result<()> {
+    let max_fee = utx.max_fee_per_gas();
+    if max_fee < &base_fee {
+        return Err(eg!(
+            "The max fee per gas({}) is less than the base fee({})",
+            max_fee,
+            base_fee
+        ));
+    }
+    Ok(())
+}
+
+// count the nonces in `nonces` that follow `start` one by one without any gap
+fn contiguous_cnt(start: U256, nonces: &BTreeSet<U256>) -> u64 {
+    nonces
+        .range(start..)
+        .zip(0u64..)
+        .take_while(|(n, i)| **n == start + U256::from(*i))
+        .count() as u64
+}
+
+// higher effective tip under the `base_fee` first, then lower nonce first,
+// the tx hash is used as the final tiebreaker,
+// so identical mempools always yield identical proposals
+fn tx_propose_cmp(a: &SignedTx, b: &SignedTx, base_fee: U256) -> Ordering {
+    b.transaction
+        .unsigned
+        .effective_priority_fee(base_fee)
+        .cmp(&a.transaction.unsigned.effective_priority_fee(base_fee))
+        .then_with(|| {
+            a.transaction
+                .unsigned
+                .nonce()
+                .cmp(b.transaction.unsigned.nonce())
+        })
+        .then_with(|| a.transaction.hash.cmp(&b.transaction.hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rt_evm_model::types::{
+        Eip1559Transaction, LegacyTransaction, TransactionAction, UnsignedTransaction,
+        UnverifiedTransaction, H256,
+    };
+
+    fn mock_tx(gas_price: u64, nonce: u64) -> SignedTx {
+        SignedTx {
+            transaction: UnverifiedTransaction {
+                unsigned: UnsignedTransaction::Legacy(LegacyTransaction {
+                    nonce: nonce.into(),
+                    gas_price: gas_price.into(),
+                    gas_limit: MIN_TRANSACTION_GAS_LIMIT.into(),
+                    action: TransactionAction::Create,
+                    value: U256::zero(),
+                    data: vec![],
+                }),
+                signature: None,
+                chain_id: 0,
+                hash: H256::random(),
+            },
+            sender: H160::random(),
+            public: None,
+        }
+    }
+
+    #[test]
+    fn test_tx_propose_order() {
+        let a = mock_tx(10, 0);
+        let b = mock_tx(10, 0);
+
+        let mut x = vec![a.clone(), b.clone()];
+        let mut y = vec![b, a];
+        x.sort_unstable_by(|a, b| tx_propose_cmp(a, b, U256::zero()));
+        y.sort_unstable_by(|a, b| tx_propose_cmp(a, b, U256::zero()));
+        assert_eq!(x, y);
+        assert!(x[0].transaction.hash < x[1].transaction.hash);
+
+        let high_price = mock_tx(11, 1);
+        let low_nonce = mock_tx(10, 0);
+        let high_nonce = mock_tx(10, 1);
+
+        let mut txs = vec![high_nonce.clone(), low_nonce.clone(), high_price.clone()];
+        txs.sort_unstable_by(|a, b| tx_propose_cmp(a, b, U256::zero()));
+        assert_eq!(txs, vec![high_price, low_nonce, high_nonce]);
+    }
+
+    fn mock_eip1559_tx(max_fee: u64, max_priority_fee: u64) -> SignedTx {
+        let mut tx = mock_tx(0, 0);
+        tx.transaction.unsigned = UnsignedTransaction::Eip1559(Eip1559Transaction {
+            nonce: U256::zero(),
+            max_priority_fee_per_gas: max_priority_fee.into(),
+            gas_price: max_fee.into(),
+            gas_limit: MIN_TRANSACTION_GAS_LIMIT.into(),
+            action: TransactionAction::Create,
+            value: U256::zero(),
+            data: vec![],
+            access_list: vec![],
+        });
+        tx
+    }
+
+    #[test]
+    fn test_tx_propose_order_by_effective_tip() {
+        let base_fee = U256::from(100);
+
+        // tip: 20
+        let legacy = mock_tx(120, 0);
+        // tip: min(50, 200 - 100) = 50
+        let high_tip = mock_eip1559_tx(200, 50);
+        // tip: min(50, 110 - 100) = 10, a high max fee does not mean a high tip
+        let capped_tip = mock_eip1559_tx(110, 50);
+        // can not afford the base fee
+        let underpriced = mock_tx(90, 0);
+
+        let mut txs = vec![
+            underpriced.clone(),
+            capped_tip.clone(),
+            legacy.clone(),
+            high_tip.clone(),
+        ];
+        txs.sort_unstable_by(|a, b| tx_propose_cmp(a, b, base_fee));
+        assert_eq!(txs, vec![high_tip, legacy, capped_tip, underpriced]);
+    }
+
+    #[test]
+    fn test_check_base_fee() {
+        let base_fee = U256::from(100);
+        let check = |tx: SignedTx| check_base_fee(&tx.transaction.unsigned, base_fee);
+
+        // legacy
+        assert!(check(mock_tx(99, 0)).is_err());
+        assert!(check(mock_tx(100, 0)).is_ok());
+
+        // the max fee counts, not the tip
+        assert!(check(mock_eip1559_tx(99, 99)).is_err());
+        assert!(check(mock_eip1559_tx(100, 0)).is_ok());
+        assert!(check(mock_eip1559_tx(200, 1)).is_ok());
+
+        // all of them can afford a zero base fee
+        assert!(
+            check_base_fee(&mock_tx(0, 0).transaction.unsigned, U256::zero()).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_on_duplicate() {
+        assert_eq!(DuplicatePolicy::Reject, DuplicatePolicy::default());
+        assert!(on_duplicate(DuplicatePolicy::Reject).is_err());
+        assert!(on_duplicate(DuplicatePolicy::Ignore).is_ok());
+    }
+
+    #[test]
+    fn test_address_policy() {
+        let (alice, bob, carol) = (H160::random(), H160::random(), H160::random());
+
+        let policy = AddressPolicy::default();
+        assert!(policy.check(alice, Some(bob)).is_ok());
+        assert!(policy.check(alice, None).is_ok());
+
+        // denied sender
+        let policy = AddressPolicy {
+            senders: AddressFilter::Deny([alice].into_iter().collect()),
+            recipients: AddressFilter::Any,
+        };
+        assert!(policy.check(alice, Some(bob)).is_err());
+        assert!(policy.check(bob, Some(alice)).is_ok());
+
+        // denied recipient
+        let policy = AddressPolicy {
+            senders: AddressFilter::Any,
+            recipients: AddressFilter::Deny([bob].into_iter().collect()),
+        };
+        assert!(policy.check(alice, Some(bob)).is_err());
+        assert!(policy.check(bob, Some(alice)).is_ok());
+        assert!(policy.check(alice, None).is_ok());
+
+        // allow-list
+        let policy = AddressPolicy {
+            senders: AddressFilter::Allow([alice, bob].into_iter().collect()),
+            recipients: AddressFilter::Allow([carol].into_iter().collect()),
+        };
+        assert!(policy.check(alice, Some(carol)).is_ok());
+        assert!(policy.check(bob, None).is_ok());
+        assert!(policy.check(carol, Some(carol)).is_err());
+        assert!(policy.check(alice, Some(bob)).is_err());
+    }
+
+    #[test]
+    fn test_evicted_txs() {
+        let mut evicted = EvictedTxs::new(3);
+
+        let expired = (0..3).map(|_| H256::random()).collect::<Vec<_>>();
+        evicted.extend(expired.clone(), EvictionReason::Expired);
+        assert!(expired
+            .iter()
+            .all(|h| matches!(evicted.get(h), Some((EvictionReason::Expired, _)))));
+
+        let stale = H256::random();
+        evicted.extend([stale], EvictionReason::NonceTooLow);
+        assert!(matches!(
+            evicted.get(&stale),
+            Some((EvictionReason::NonceTooLow, _))
+        ));
+
+        // the oldest one has been forgotten
+        assert!(evicted.get(&expired[0]).is_none());
+        assert!(evicted.get(&expired[1]).is_some());
+        assert_eq!(evicted.txs.len(), 3);
+    }
+
+    #[test]
+    fn test_broadcast_queue() {
+        let mut queue = BroadcastQueue::new(3);
+
+        // never drained
+        let txs = (0..10).map(|n| mock_tx(1, n)).collect::<Vec<_>>();
+        txs.iter().cloned().for_each(|tx| queue.push(tx));
+        assert_eq!(3, queue.txs.len());
+
+        // the newest ones are kept, the removed ones are not returned
+        let removed = txs[8].transaction.hash;
+        let taken = queue.take(|tx| tx.transaction.hash != removed);
+        assert_eq!(
+            vec![txs[7].transaction.hash, txs[9].transaction.hash],
+            taken
+                .iter()
+                .map(|tx| tx.transaction.hash)
+                .collect::<Vec<_>>()
+        );
+        assert!(queue.txs.is_empty());
+
+        queue.push(txs[0].clone());
+        queue.clear();
+        assert!(queue.take(|_| true).is_empty());
+    }
+
+    #[test]
+    fn test_check_gas_price_fee_free() {
+        // the global switch is shared by the tests running in parallel,
+        // so pass the mode explicitly and leave the global one untouched
+        assert!(check_gas_price(U256::zero(), false).is_err());
+        assert!(check_gas_price(U256::one(), false).is_ok());
+
+        assert!(check_gas_price(U256::zero(), true).is_ok());
+        assert!(check_gas_price(U256::one(), true).is_ok());
+        assert!(check_gas_price(U256::from(u64::MAX), true).is_err());
+        assert!(check_gas_price(U256::from(u64::MAX), false).is_err());
+
+        // the base fee of the fee-free mode is zero,
+        // so all the tips are the full gas prices
+        let base_fee = U256::zero();
+        let free = mock_tx(0, 0);
+        let paid = mock_tx(1, 0);
+        assert_eq!(
+            U256::zero(),
+            free.transaction.unsigned.effective_priority_fee(base_fee)
+        );
+        let mut txs = vec![free.clone(), paid.clone()];
+        txs.sort_unstable_by(|a, b| tx_propose_cmp(a, b, base_fee));
+        assert_eq!(txs, vec![paid, free]);
+    }
+
+    #[test]
+    fn test_tx_hash_filter() {
+        let filter = TxHashFilter::new();
+
+        let mined = (0..1000).map(|_| H256::random()).collect::<Vec<_>>();
+        mined.iter().for_each(|h| filter.insert(h));
+        assert!(mined.iter().all(|h| filter.may_contain(h)));
+
+        let false_positives = (0..1000)
+            .filter(|_| filter.may_contain(&H256::random()))
+            .count();
+        assert!(false_positives < 10);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        // shared by the RPC servers and the block producer
+        assert_send_sync::<TinyMempool>();
+        assert_send_sync::<MptStore>();
+        assert_send_sync::<Storage>();
+    }
+
+    #[test]
+    fn test_tx_hash_filter_concurrent() {
+        let filter = TxHashFilter::new();
+
+        let mined = (0..8)
+            .map(|_| (0..1000).map(|_| H256::random()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        // the bits set by one thread must not be lost by the others
+        thread::scope(|s| {
+            mined.iter().for_each(|hashes| {
+                let filter = &filter;
+                s.spawn(move || hashes.iter().for_each(|h| filter.insert(h)));
+            });
+        });
+
+        assert!(mined.iter().flatten().all(|h| filter.may_contain(h)));
+    }
+
+    // <on-chain nonce> + `contiguous_cnt` is the next nonce of an account
+    #[test]
+    fn test_next_nonce() {
+        let next_nonce = |nonce: u64, pending: &[u64]| {
+            let pending = pending.iter().copied().map(U256::from).collect();
+            U256::from(nonce) + contiguous_cnt(nonce.into(), &pending)
+        };
+
+        // no pending txs
+        assert_eq!(next_nonce(5, &[]), 5.into());
+
+        // contiguous pending txs
+        assert_eq!(next_nonce(5, &[5, 6, 7]), 8.into());
+
+        // the txs after a gap can not be confirmed before the gap is filled
+        assert_eq!(next_nonce(5, &[5, 6, 8, 9]), 7.into());
+        assert_eq!(next_nonce(5, &[6, 7]), 5.into());
+
+        // the confirmed ones not cleaned up yet
+        assert_eq!(next_nonce(5, &[3, 4, 5]), 6.into());
+    }
+
+    #[test]
+    fn test_contiguous_cnt() {
+        let nonces = [1u64, 2, 3, 5, 6]
+            .into_iter()
+            .map(U256::from)
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(contiguous_cnt(0.into(), &nonces), 0);
+        assert_eq!(contiguous_cnt(1.into(), &nonces), 3);
+        assert_eq!(contiguous_cnt(2.into(), &nonces), 2);
+        assert_eq!(contiguous_cnt(4.into(), &nonces), 0);
+        assert_eq!(contiguous_cnt(5.into(), &nonces), 2);
+        assert_eq!(contiguous_cnt(7.into(), &nonces), 0);
+        assert_eq!(contiguous_cnt(0.into(), &BTreeSet::new()), 0);
+    }
+
+    #[test]
+    fn test_broadcast_priority() {
+        assert_eq!(
+            BroadcastPriority::InsertionOrder,
+            BroadcastPriority::default()
+        );
+
+        let mut queue = BroadcastQueue::new(10);
+        let txs = [3, 9, 1, 9, 5]
+            .into_iter()
+            .enumerate()
+            .map(|(n, price)| mock_tx(price, n as u64))
+            .collect::<Vec<_>>();
+        txs.iter().cloned().for_each(|tx| queue.push(tx));
+
+        // pushed again, but returned once
+        queue.push(txs[2].clone());
+
+        let taken = queue.take(|_| true);
+        assert_eq!(txs.len(), taken.len());
+
+        let mut by_insertion = taken.clone();
+        sort_broadcast(
+            &mut by_insertion,
+            BroadcastPriority::InsertionOrder,
+            U256::zero(),
+        );
+        assert_eq!(
+            txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>(),
+            by_insertion
+                .iter()
+                .map(|tx| tx.transaction.hash)
+                .collect::<Vec<_>>()
+        );
+
+        let mut by_fee = taken;
+        sort_broadcast(&mut by_fee, BroadcastPriority::FeeDescending, U256::zero());
+        assert_eq!(
+            vec![9, 9, 5, 3, 1],
+            by_fee
+                .iter()
+                .map(|tx| tx.transaction.unsigned.gas_price().as_u64())
+                .collect::<Vec<_>>()
+        );
+        // same fee, the lower nonce first
+        assert_eq!(txs[1].transaction.hash, by_fee[0].transaction.hash);
+        assert_eq!(txs[3].transaction.hash, by_fee[1].transaction.hash);
+    }
 }
@@ -1,6 +1,6 @@
 use crate::{
-    lazy::CHAIN_ID,
-    types::{Proposal, BASE_FEE_PER_GAS, MAX_BLOCK_GAS_LIMIT},
+    lazy::{base_fee_per_gas, CHAIN_ID},
+    types::{Proposal, MAX_BLOCK_GAS_LIMIT},
 };
 use rlp::{Decodable, DecoderError, Encodable, Prototype, Rlp, RlpStream};
 
@@ -28,7 +28,7 @@ impl Decodable for Proposal {
                 gas_limit: MAX_BLOCK_GAS_LIMIT.into(),
                 extra_data: Default::default(),
                 mixed_hash: None,
-                base_fee_per_gas: BASE_FEE_PER_GAS.into(),
+                base_fee_per_gas: base_fee_per_gas(),
                 chain_id: **CHAIN_ID.load(),
                 tx_hashes: r.list_at(5)?,
             }),
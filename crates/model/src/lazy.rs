@@ -1,13 +1,84 @@
-use crate::types::Hex;
-use arc_swap::ArcSwap;
+use crate::types::{Hex, BASE_FEE_PER_GAS, U256};
+use arc_swap::{ArcSwap, ArcSwapOption};
 use once_cell::sync::Lazy;
-use std::sync::Arc;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use ruc::*;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 pub static CHAIN_ID: Lazy<ArcSwap<u64>> =
     Lazy::new(|| ArcSwap::from_pointee(Default::default()));
 pub static PROTOCOL_VERSION: Lazy<ArcSwap<Hex>> =
     Lazy::new(|| ArcSwap::from_pointee(Default::default()));
 
+// `None` means the global pool of rayon
+static THREAD_POOL: Lazy<ArcSwapOption<ThreadPool>> = Lazy::new(ArcSwapOption::empty);
+
+static FEE_FREE: AtomicBool = AtomicBool::new(false);
+
 pub fn set_chain_id(id: u64) {
     CHAIN_ID.store(Arc::from(id));
 }
+
+/// Run a fee-free chain, the base fee of the new blocks is zero,
+/// and the txs with a zero gas price are accepted.
+///
+/// NOTE: all nodes of a network must use the same setting,
+/// and it should be set before any block is produced.
+pub fn set_fee_free(enabled: bool) {
+    FEE_FREE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_fee_free() -> bool {
+    FEE_FREE.load(Ordering::Relaxed)
+}
+
+/// The base fee per gas of the new blocks,
+/// `BASE_FEE_PER_GAS`, or zero in the fee-free mode.
+pub fn base_fee_per_gas() -> U256 {
+    alt!(is_fee_free(), U256::zero(), BASE_FEE_PER_GAS.into())
+}
+
+/// Run the parallel operations of rt-evm in a dedicated pool of `n` threads,
+/// instead of contending with the other users of the global rayon pool;
+/// `0` switches back to the global pool, which is the default.
+pub fn set_rayon_threads(n: usize) -> Result<()> {
+    if 0 == n {
+        THREAD_POOL.store(None);
+        return Ok(());
+    }
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(n)
+        .thread_name(|i| format!("rt-evm-{}", i))
+        .build()
+        .c(d!())?;
+    THREAD_POOL.store(Some(Arc::new(pool)));
+
+    Ok(())
+}
+
+/// Run `op` in the pool selected by `set_rayon_threads`,
+/// the parallel iterators inside it are driven by that pool.
+pub fn rayon_install<OP, R>(op: OP) -> R
+where
+    OP: FnOnce() -> R + Send,
+    R: Send,
+{
+    rayon_install_in(THREAD_POOL.load_full().as_deref(), op)
+}
+
+/// Same as `rayon_install`, but in the given `pool`,
+/// `None` means the global pool of rayon.
+pub fn rayon_install_in<OP, R>(pool: Option<&ThreadPool>, op: OP) -> R
+where
+    OP: FnOnce() -> R + Send,
+    R: Send,
+{
+    match pool {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
+}
@@ -1,11 +1,13 @@
 use crate::{
     async_trait,
     types::{
-        Account, Block, BlockNumber, Hash, Header, Proposal, Receipt, SignedTransaction,
-        TxResp, H160, U256,
+        Account, Block, BlockNumber, CallRequest, CheckPointConfig, Hash, Header,
+        Proposal, Receipt, SignedTransaction, StateDiff, StateOverride, TxResp, H160,
+        H256, U256,
     },
 };
 use ruc::*;
+use std::collections::BTreeMap;
 
 #[async_trait]
 pub trait APIAdapter: Send + Sync {
@@ -28,8 +30,21 @@ pub trait APIAdapter: Send + Sync {
         tx_hashes: &[Hash],
     ) -> Result<Vec<Option<Receipt>>>;
 
+    async fn get_receipts_by_block_number(
+        &self,
+        block_number: u64,
+    ) -> Result<Option<Vec<Receipt>>>;
+
     async fn get_tx_by_hash(&self, tx_hash: Hash) -> Result<Option<SignedTransaction>>;
 
+    async fn get_tx_with_block(
+        &self,
+        tx_hash: Hash,
+    ) -> Result<Option<(BlockNumber, SignedTransaction)>>;
+
+    async fn get_tx_position(&self, tx_hash: Hash)
+        -> Result<Option<(BlockNumber, u32)>>;
+
     async fn get_txs_by_hashes(
         &self,
         block_number: u64,
@@ -44,6 +59,22 @@ pub trait APIAdapter: Send + Sync {
 
     async fn get_pending_tx_count(&self, address: H160) -> Result<U256>;
 
+    // the on-chain nonce plus the number of the pending txs following it without a gap,
+    // that is, the nonce a wallet should use for its next tx
+    async fn get_next_nonce(&self, address: H160) -> Result<U256>;
+
+    // whether the tx is waiting in the mempool
+    async fn is_tx_pooled(&self, tx_hash: Hash) -> Result<bool>;
+
+    // whether the tx has been dropped from the mempool without being confirmed
+    async fn is_tx_evicted(&self, tx_hash: Hash) -> Result<bool>;
+
+    // (<number of pending txs>, <number of queued txs>)
+    async fn get_txpool_status(&self) -> Result<(U256, U256)>;
+
+    // drop all the txs in the mempool, return the number of the dropped ones
+    async fn clear_txpool(&self) -> Result<U256>;
+
     #[allow(clippy::too_many_arguments)]
     async fn evm_call(
         &self,
@@ -57,12 +88,82 @@ pub trait APIAdapter: Send + Sync {
         proposal: Proposal,
     ) -> Result<TxResp>;
 
+    // the same as `evm_call`, but against the state right after the first `tx_index` txs
+    // of the block at `number`, rebuilt by replaying them on top of the parent state,
+    // so it is as expensive as executing them again
+    #[allow(clippy::too_many_arguments)]
+    async fn evm_call_at_tx_index(
+        &self,
+        from: Option<H160>,
+        to: Option<H160>,
+        gas_price: Option<U256>,
+        gas_limit: Option<U256>,
+        value: U256,
+        data: Vec<u8>,
+        number: BlockNumber,
+        tx_index: usize,
+    ) -> Result<TxResp>;
+
+    // execute the calls in order against the state of `number` with the overrides applied,
+    // each one observes the changes of the previous ones, nothing is persisted
+    async fn evm_call_many(
+        &self,
+        calls: Vec<CallRequest>,
+        state_overrides: BTreeMap<H160, StateOverride>,
+        number: Option<BlockNumber>,
+    ) -> Result<Vec<TxResp>>;
+
     async fn get_code_by_hash(&self, hash: &Hash) -> Result<Option<Vec<u8>>>;
 
+    async fn get_code_size_by_hash(&self, hash: &Hash) -> Result<Option<u64>>;
+
     async fn get_storage_at(
         &self,
         address: H160,
         position: U256,
         state_root: Hash,
     ) -> Result<Vec<u8>>;
+
+    // same as `get_storage_at`, but by the exact key in the storage trie,
+    // e.g. the slot of a mapping entry computed by the caller
+    async fn get_storage_at_raw(
+        &self,
+        address: H160,
+        key: H256,
+        state_root: Hash,
+    ) -> Result<Vec<u8>>;
+
+    // same as `get_storage_at`, but restore the tries only once for all the positions,
+    // `None` for the positions never set
+    async fn get_storage_at_batch(
+        &self,
+        address: H160,
+        positions: &[U256],
+        state_root: Hash,
+    ) -> Result<Vec<Option<Vec<u8>>>>;
+
+    // a page of (<address hash>, <address>, <account>) starting from the `start` hash,
+    // and the cursor of the next page
+    #[allow(clippy::type_complexity)]
+    async fn get_account_range(
+        &self,
+        number: Option<BlockNumber>,
+        start: Hash,
+        limit: usize,
+    ) -> Result<(Vec<(Hash, H160, Account)>, Option<Hash>)>;
+
+    // (<total number of the txs of the address>, <hashes of a page of them>),
+    // from the oldest one, skipping the first `offset` ones
+    async fn get_txs_by_address(
+        &self,
+        address: H160,
+        offset: u64,
+        limit: usize,
+    ) -> Result<(u64, Vec<Hash>)>;
+
+    // replay the tx on the state right before it, `None` if the tx is not found
+    async fn get_tx_state_diff(&self, tx_hash: Hash) -> Result<Option<StateDiff>>;
+
+    // the activation heights followed by the chain served by this adapter
+    fn checkpoint_config(&self) -> CheckPointConfig;
 }
@@ -14,6 +14,8 @@ pub trait ExecutorAdapter {
 
     fn commit(&mut self) -> MerkleRoot;
 
+    fn current_root(&self) -> MerkleRoot;
+
     fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
 
     fn get_ctx(&self) -> ExecutorContext;
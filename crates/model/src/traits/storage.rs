@@ -1,4 +1,6 @@
-use crate::types::{Block, FatBlock, Hash, Header, Receipt, SignedTransaction, H256};
+use crate::types::{
+    Block, BlockNumber, FatBlock, Hash, Header, Receipt, SignedTransaction, H256,
+};
 use ruc::*;
 
 pub trait BlockStorage: Send + Sync {
@@ -10,11 +12,18 @@ pub trait BlockStorage: Send + Sync {
 
     fn get_block_header(&self, height: u64) -> Result<Option<Header>>;
 
+    /// The hash of the block at `height`,
+    /// computed at most once per block instead of re-encoding the header on every call.
+    fn get_block_hash(&self, height: u64) -> Result<Option<Hash>>;
+
     fn get_latest_block(&self) -> Result<Block>;
 
     fn set_latest_block(&self, block: Block) -> Result<()>;
 
     fn get_latest_block_header(&self) -> Result<Header>;
+
+    // cheaper than `get_latest_block_header`, no block is cloned
+    fn get_latest_block_number(&self) -> Result<BlockNumber>;
 }
 
 pub trait TxStorage {
@@ -34,6 +43,14 @@ pub trait TxStorage {
 
     fn get_tx_by_hash(&self, hash: &Hash) -> Result<Option<SignedTransaction>>;
 
+    fn get_tx_with_block(
+        &self,
+        hash: &Hash,
+    ) -> Result<Option<(BlockNumber, SignedTransaction)>>;
+
+    // (<block number>, <tx index in the block>)
+    fn get_tx_position(&self, hash: &Hash) -> Result<Option<(BlockNumber, u32)>>;
+
     fn insert_receipts(&self, block_height: u64, receipts: Vec<Receipt>) -> Result<()>;
 
     fn insert_code(
@@ -47,6 +64,11 @@ pub trait TxStorage {
 
     fn get_code_by_address(&self, address: &H256) -> Result<Option<Vec<u8>>>;
 
+    // the length of the code in bytes, without loading the code
+    fn get_code_size_by_hash(&self, hash: &Hash) -> Result<Option<u64>>;
+
+    fn get_code_size_by_address(&self, address: &H256) -> Result<Option<u64>>;
+
     fn get_receipt_by_hash(&self, hash: &Hash) -> Result<Option<Receipt>>;
 
     fn get_receipts(
@@ -54,6 +76,10 @@ pub trait TxStorage {
         block_height: u64,
         hashes: &[Hash],
     ) -> Result<Vec<Option<Receipt>>>;
+
+    // all the receipts of a block in the tx order,
+    // `None` if the block does not exist
+    fn get_block_receipts(&self, block_height: u64) -> Result<Option<Vec<Receipt>>>;
 }
 
 pub trait Storage: BlockStorage + TxStorage {}
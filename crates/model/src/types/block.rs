@@ -2,9 +2,10 @@ use rlp_derive::{RlpDecodable, RlpEncodable};
 use serde::{Deserialize, Serialize};
 
 use crate::codec::ProtocolCodec;
+use crate::lazy::base_fee_per_gas;
 use crate::types::{
-    Bloom, BloomInput, Bytes, ExecResp, Hash, Hasher, MerkleRoot, SignedTransaction,
-    H160, H64, NIL_HASH, U256,
+    logs_bloom, Bloom, Bytes, CheckPointConfig, ExecResp, Hash, Hasher, MerkleRoot,
+    Receipt, SignedTransaction, H160, H64, NIL_HASH, U256,
 };
 
 pub type BlockNumber = u64;
@@ -12,6 +13,9 @@ pub type BlockNumber = u64;
 pub const MAX_BLOCK_GAS_LIMIT: u64 = 50_000_000;
 pub const BASE_FEE_PER_GAS: u64 = 0x539;
 
+// the same limit as Ethereum
+pub const MAX_EXTRA_DATA_SIZE: usize = 32;
+
 #[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, Eq)]
 pub struct Proposal {
     pub prev_hash: Hash,
@@ -27,6 +31,9 @@ pub struct Proposal {
     pub tx_hashes: Vec<Hash>,
 }
 
+// NOTE:
+// the `tx_hashes` are dropped, so the block hash equals to the header hash,
+// use `Proposal::from_block_full` if the tx list matters
 impl From<&Block> for Proposal {
     fn from(b: &Block) -> Self {
         Self::from(&b.header)
@@ -70,6 +77,15 @@ impl From<Header> for Proposal {
 }
 
 impl Proposal {
+    /// Restore the proposal of a block along with its `tx_hashes`,
+    /// e.g. for verifying the `transactions_root` of it.
+    pub fn from_block_full(b: &Block) -> Self {
+        Proposal {
+            tx_hashes: b.tx_hashes.clone(),
+            ..Self::from(&b.header)
+        }
+    }
+
     pub fn hash(&self) -> Hash {
         Hasher::digest(self.encode().unwrap())
     }
@@ -98,18 +114,13 @@ pub struct Block {
 
 impl Block {
     pub fn new(proposal: Proposal, exec_resp: &ExecResp) -> Self {
-        let logs = exec_resp
-            .txs_resp
-            .iter()
-            .map(|r| Bloom::from(BloomInput::Raw(rlp::encode_list(&r.logs).as_ref())))
-            .collect::<Vec<_>>();
         let header = Header {
             prev_hash: proposal.prev_hash,
             proposer: proposal.proposer,
             state_root: exec_resp.state_root,
             transactions_root: proposal.transactions_root,
             receipts_root: exec_resp.receipt_root,
-            log_bloom: Bloom::from(BloomInput::Raw(rlp::encode_list(&logs).as_ref())),
+            log_bloom: logs_bloom(exec_resp.txs_resp.iter().flat_map(|r| r.logs.iter())),
             difficulty: U256::one(),
             timestamp: proposal.timestamp,
             number: proposal.number,
@@ -132,6 +143,56 @@ impl Block {
         Proposal::from(self).hash()
     }
 
+    pub fn size(&self) -> usize {
+        self.encode().unwrap().len()
+    }
+
+    /// Estimate the bytes written into the storage by persisting this block
+    /// along with its transactions and receipts, for projecting the disk growth.
+    ///
+    /// The keys and the indexes are counted, but the overhead of the backend is not.
+    pub fn estimated_storage_bytes(
+        &self,
+        txs: &[SignedTransaction],
+        receipts: &[Receipt],
+    ) -> usize {
+        const HASH: usize = 32;
+        const NUM: usize = 8;
+
+        // number => block, number => header, hash => number
+        let block = NUM + self.size() + NUM + encoded_len(&self.header) + HASH + NUM;
+
+        // hash => (number, tx), hash => (number, index)
+        let txs = txs
+            .iter()
+            .map(|tx| HASH + NUM + encoded_len(tx) + HASH + NUM + 4)
+            .sum::<usize>();
+
+        // hash => receipt
+        let receipts = receipts
+            .iter()
+            .map(|r| HASH + encoded_len(r))
+            .sum::<usize>();
+
+        block + txs + receipts
+    }
+
+    /// The block at height 0, the `timestamp` must be a static value,
+    /// shared by all the nodes of a network, NOT the local time of any of them,
+    /// so is the `checkpoint`, which decides the roots of its empty lists.
+    pub fn genesis(
+        chain_id: u64,
+        state_root: MerkleRoot,
+        timestamp: u64,
+        checkpoint: &CheckPointConfig,
+    ) -> Self {
+        let mut block = Self::mock(chain_id, 0, state_root, timestamp);
+        block.header.transactions_root = checkpoint.empty_root(0);
+        block.header.receipts_root = checkpoint.empty_root(0);
+        block
+    }
+
+    /// A block without txs, whose empty roots are the legacy ones.
     pub fn mock(
         chain_id: u64,
         number: u64,
@@ -153,7 +214,7 @@ impl Block {
             extra_data: Default::default(),
             mixed_hash: None,
             nonce: Default::default(),
-            base_fee_per_gas: BASE_FEE_PER_GAS.into(),
+            base_fee_per_gas: base_fee_per_gas(),
             chain_id,
         };
 
@@ -226,3 +287,220 @@ impl<'a> From<&'a FatBlock> for FatBlockRef<'a> {
         Self::new(&fb.block, &fb.txs)
     }
 }
+
+/// Published to the subscribers every time a block is set into the storage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockEvent {
+    NewBlock(Header),
+
+    // a block with a different hash has replaced the one at the same height,
+    // the `removed` one is no longer canonical, neither are the logs in it
+    Reorg { removed: Hash, header: Header },
+}
+
+impl BlockEvent {
+    /// `prev`: the hash of the block stored at the same height, if any
+    pub fn new(prev: Option<Hash>, header: Header) -> Self {
+        match prev {
+            Some(removed) if removed != header.hash() => Self::Reorg { removed, header },
+            _ => Self::NewBlock(header),
+        }
+    }
+}
+
+fn encoded_len<T: ProtocolCodec>(v: &T) -> usize {
+    v.encode().map(|b| b.len()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{BloomInput, Log, TxResp};
+
+    #[test]
+    fn test_genesis_is_static() {
+        let root = Hasher::digest(b"genesis state");
+
+        let cp = CheckPointConfig::default();
+        let a = Block::genesis(9527, root, 1_680_000_000, &cp);
+        let b = Block::genesis(9527, root, 1_680_000_000, &cp);
+        assert_eq!(a.hash(), b.hash());
+        assert_eq!(0, a.header.number);
+        assert_eq!(1_680_000_000, a.header.timestamp);
+
+        assert_ne!(a.hash(), Block::genesis(9527, root, 0, &cp).hash());
+        assert_ne!(
+            a.hash(),
+            Block::genesis(9528, root, 1_680_000_000, &cp).hash()
+        );
+
+        let cp = CheckPointConfig {
+            empty_root_height: Some(0),
+            ..Default::default()
+        };
+        assert_ne!(
+            a.hash(),
+            Block::genesis(9527, root, 1_680_000_000, &cp).hash()
+        );
+    }
+
+    #[test]
+    fn test_header_hash_is_block_hash() {
+        let mut block = Block {
+            header: Header {
+                prev_hash: Hash::repeat_byte(1),
+                proposer: H160::repeat_byte(2),
+                transactions_root: Hash::repeat_byte(3),
+                timestamp: 1668000000,
+                number: 9,
+                chain_id: 9527,
+                ..Default::default()
+            },
+            tx_hashes: vec![Hash::repeat_byte(4)],
+        };
+        let bh = block.hash();
+
+        // the hash can be memoized by the block number,
+        // it is derived from the header alone ...
+        assert_eq!(bh, block.header.hash());
+
+        // ... and not changed by the fields filled after the execution
+        block.header.state_root = Hash::repeat_byte(5);
+        block.header.difficulty = U256::one();
+        block.header.gas_used = U256::from(21000);
+        assert_eq!(bh, block.hash());
+
+        block.header.number += 1;
+        assert_ne!(bh, block.hash());
+    }
+
+    fn mock_log(address: u64, topic: u64) -> Log {
+        Log {
+            address: H160::from_low_u64_be(address),
+            topics: vec![Hash::from_low_u64_be(topic)],
+            data: vec![],
+        }
+    }
+
+    #[test]
+    fn test_header_bloom_is_or_of_receipt_blooms() {
+        let txs_logs = vec![
+            vec![mock_log(1, 11), mock_log(2, 22)],
+            vec![],
+            vec![mock_log(3, 33)],
+        ];
+        let exec_resp = ExecResp {
+            state_root: Default::default(),
+            transaction_root: Default::default(),
+            receipt_root: Default::default(),
+            gas_used: 0,
+            fee_used: U256::zero(),
+            txs_resp: txs_logs
+                .iter()
+                .map(|logs| TxResp {
+                    logs: logs.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        let header = Block::new(Proposal::default(), &exec_resp).header;
+
+        let mut receipts_bloom = Bloom::zero();
+        txs_logs.iter().for_each(|logs| {
+            let receipt_bloom = logs_bloom(logs.iter());
+            logs.iter().for_each(|log| {
+                assert!(receipt_bloom
+                    .contains_input(BloomInput::Raw(log.address.as_bytes())));
+                assert!(receipt_bloom
+                    .contains_input(BloomInput::Raw(log.topics[0].as_bytes())));
+            });
+            receipts_bloom.accrue_bloom(&receipt_bloom);
+        });
+        assert_eq!(receipts_bloom, header.log_bloom);
+
+        txs_logs.iter().flatten().for_each(|log| {
+            assert!(header
+                .log_bloom
+                .contains_input(BloomInput::Raw(log.address.as_bytes())));
+            assert!(header
+                .log_bloom
+                .contains_input(BloomInput::Raw(log.topics[0].as_bytes())));
+        });
+    }
+
+    #[test]
+    fn test_logs_bloom_bits() {
+        // the 3 bits of each input are picked from the low 11 bits of
+        // the first 3 big-endian u16 of its keccak256 digest
+        let log = mock_log(1, 11);
+        let mut expected = Bloom::zero();
+        for x in [log.address.as_bytes(), log.topics[0].as_bytes()] {
+            let hash = Hasher::digest(x).0;
+            for i in [0, 2, 4] {
+                let bit = (hash[i + 1] as usize + ((hash[i] as usize) << 8)) & 0x7FF;
+                expected.0[255 - bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        assert_eq!(expected, logs_bloom([log].iter()));
+    }
+
+    #[test]
+    fn test_estimated_storage_bytes() {
+        let block = Block::mock(9527, 1, Default::default(), 1_680_000_000);
+
+        let base = block.estimated_storage_bytes(&[], &[]);
+        assert!(base > block.size() + encoded_len(&block.header));
+
+        let receipt = Receipt {
+            logs: vec![Log {
+                data: vec![7; 100],
+                ..mock_log(1, 11)
+            }],
+            ..Default::default()
+        };
+        let one = block.estimated_storage_bytes(&[], &[receipt.clone()]);
+        assert_eq!(base + 32 + encoded_len(&receipt), one);
+        assert!(one - base > 100);
+
+        let receipts = vec![receipt; 10];
+        assert_eq!(
+            base + 10 * (one - base),
+            block.estimated_storage_bytes(&[], &receipts)
+        );
+    }
+
+    #[test]
+    fn test_proposal_from_block_full() {
+        let proposal = Proposal {
+            prev_hash: Hash::repeat_byte(1),
+            proposer: H160::repeat_byte(2),
+            transactions_root: Hash::repeat_byte(3),
+            timestamp: 1668000000,
+            number: 9,
+            chain_id: 9527,
+            tx_hashes: vec![Hash::repeat_byte(4), Hash::repeat_byte(5)],
+            ..Default::default()
+        };
+        let exec_resp = ExecResp {
+            state_root: Hash::repeat_byte(6),
+            transaction_root: Default::default(),
+            receipt_root: Default::default(),
+            gas_used: 42000,
+            fee_used: U256::zero(),
+            txs_resp: vec![],
+        };
+        let block = Block::new(proposal.clone(), &exec_resp);
+
+        // the round trip keeps the tx list
+        assert_eq!(proposal, Proposal::from_block_full(&block));
+        assert_eq!(
+            block,
+            Block::new(Proposal::from_block_full(&block), &exec_resp)
+        );
+
+        // the plain conversion drops it, and so does the hash
+        assert!(Proposal::from(&block).tx_hashes.is_empty());
+        assert_eq!(block.hash(), Proposal::from(&block).hash());
+        assert_ne!(block.hash(), Proposal::from_block_full(&block).hash());
+    }
+}
@@ -6,10 +6,94 @@ pub use evm::{
 use rlp_derive::{RlpDecodable, RlpEncodable};
 
 use crate::codec::ProtocolCodec;
-use crate::types::{Hash, Hasher, Header, MerkleRoot, Proposal, H160, U256};
+use crate::types::{
+    access_list_gas_cost, data_gas_cost, Hash, Hasher, Header, MerkleRoot, Proposal,
+    GAS_CALL_TRANSACTION, GAS_CREATE_TRANSACTION, H160, H256, NIL_HASH, RLP_NULL, U256,
+};
+use std::collections::BTreeMap;
 
 pub const WORLD_STATE_META_KEY: [u8; 1] = [0];
 
+/// The hardfork rules followed by the executor.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Hardfork {
+    #[default]
+    London,
+    // NOTE: only the SELFDESTRUCT semantics of EIP-6780 are applied,
+    // the other changes are not supported by the `evm` crate yet
+    Cancun,
+}
+
+impl Hardfork {
+    pub fn evm_config(self) -> Config {
+        Config::london()
+    }
+
+    /// EIP-6780: SELFDESTRUCT only deletes the accounts created in the same tx.
+    pub fn is_eip6780_enabled(self) -> bool {
+        matches!(self, Self::Cancun)
+    }
+}
+
+/// How a transaction emitting more logs than the limit is handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogsLimit {
+    /// The transaction fails, none of its changes are applied,
+    /// and the gas used is still charged.
+    Fail(usize),
+    /// Only the first logs are kept, the transaction itself is not affected.
+    Cap(usize),
+}
+
+/// The heights at which the protocol changes are activated, `None` for the inactive ones,
+/// all nodes of a network must use the same ones.
+///
+/// All of them are inactive by default, so the existing blocks can still be verified,
+/// the runtime passes its own one to the components, there is no global one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CheckPointConfig {
+    /// From which the empty blocks use `RLP_NULL` as their transactions and receipts roots
+    pub empty_root_height: Option<u64>,
+
+    /// From which the logs of one transaction are limited, and the limit
+    pub max_logs_per_tx: Option<(u64, LogsLimit)>,
+}
+
+impl CheckPointConfig {
+    /// The limit of the logs of one transaction in the block at `height`.
+    pub fn logs_limit(&self, height: u64) -> Option<LogsLimit> {
+        match self.max_logs_per_tx {
+            Some((h, limit)) if height >= h => Some(limit),
+            _ => None,
+        }
+    }
+
+    /// The root of an empty list in the block at `height`.
+    pub fn empty_root(&self, height: u64) -> MerkleRoot {
+        match self.empty_root_height {
+            Some(h) if height >= h => RLP_NULL,
+            _ => NIL_HASH,
+        }
+    }
+}
+
+/// The gas that a transaction must pay before any EVM execution,
+/// that is, the base cost, the calldata cost, the access list cost,
+/// and the surcharge of contract creation.
+pub fn intrinsic_gas(
+    is_create: bool,
+    data: &[u8],
+    access_list: &[AccessListItem],
+) -> u64 {
+    let base = if is_create {
+        GAS_CREATE_TRANSACTION + GAS_CALL_TRANSACTION
+    } else {
+        GAS_CALL_TRANSACTION
+    };
+
+    base + data_gas_cost(data) + access_list_gas_cost(access_list)
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ExecResp {
     pub state_root: MerkleRoot,
@@ -20,6 +104,44 @@ pub struct ExecResp {
     pub txs_resp: Vec<TxResp>,
 }
 
+/// One call of a simulated bundle.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallRequest {
+    pub from: Option<H160>,
+    pub to: Option<H160>,
+    pub gas_price: Option<U256>,
+    pub gas_limit: Option<U256>,
+    pub value: U256,
+    pub data: Vec<u8>,
+}
+
+/// Replace the state of an account before a simulation,
+/// the `None` fields and the slots not listed are kept as is.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateOverride {
+    pub balance: Option<U256>,
+    pub nonce: Option<U256>,
+    pub code: Option<Vec<u8>>,
+    pub state_diff: BTreeMap<H256, H256>,
+}
+
+/// The changed fields and slots of an account, before or after a transaction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountState {
+    pub balance: Option<U256>,
+    pub nonce: Option<U256>,
+    pub code: Option<Vec<u8>>,
+    pub storage: BTreeMap<H256, H256>,
+}
+
+/// The changes of the world state made by a transaction,
+/// the accounts, the fields and the slots not changed are not included.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub pre: BTreeMap<H160, AccountState>,
+    pub post: BTreeMap<H160, AccountState>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TxResp {
     pub exit_reason: ExitReason,
@@ -112,3 +234,36 @@ impl From<&Header> for ExecutorContext {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::H256;
+
+    #[test]
+    fn test_intrinsic_gas_known_values() {
+        // a plain transfer
+        assert_eq!(intrinsic_gas(false, &[], &[]), 21_000);
+
+        // a contract creation without init code
+        assert_eq!(intrinsic_gas(true, &[], &[]), 53_000);
+
+        // 4 gas per zero byte, 16 gas per non-zero byte (EIP-2028)
+        assert_eq!(intrinsic_gas(false, &[0, 0, 1, 0xff], &[]), 21_040);
+        assert_eq!(intrinsic_gas(true, &[0, 0, 1, 0xff], &[]), 53_040);
+
+        // 2400 gas per address, 1900 gas per storage key (EIP-2930)
+        let access_list = vec![
+            AccessListItem {
+                address: H160::random(),
+                storage_keys: vec![H256::random(), H256::random()],
+            },
+            AccessListItem {
+                address: H160::random(),
+                storage_keys: vec![],
+            },
+        ];
+        assert_eq!(intrinsic_gas(false, &[], &access_list), 29_600);
+        assert_eq!(intrinsic_gas(true, &[1], &access_list), 61_616);
+    }
+}
@@ -3,8 +3,9 @@ pub use ethereum::Log;
 pub use block::*;
 pub use evm::{backend::*, ExitError, ExitRevert, ExitSucceed};
 pub use executor::{
-    AccessList, AccessListItem, Account, Config, ExecResp, ExecutorContext, ExitReason,
-    TxResp, WORLD_STATE_META_KEY,
+    intrinsic_gas, AccessList, AccessListItem, Account, AccountState, CallRequest,
+    CheckPointConfig, Config, ExecResp, ExecutorContext, ExitReason, Hardfork,
+    LogsLimit, StateDiff, StateOverride, TxResp, WORLD_STATE_META_KEY,
 };
 pub use primitive::*;
 pub use receipt::*;
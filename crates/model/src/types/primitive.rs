@@ -29,6 +29,14 @@ const HEX_PREFIX_UPPER: &str = "0X";
 
 pub const NIL_HASH: H256 = H256::zero();
 
+/// The root of an empty trie, that is, keccak256(rlp("")),
+/// the `transactionsRoot` of the empty blocks of Ethereum.
+pub const RLP_NULL: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0,
+    0xf8, 0x6e, 0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5,
+    0xe3, 0x63, 0xb4, 0x21,
+]);
+
 pub const KB: usize = 1024;
 pub const MB: usize = 1024 * KB;
 pub const GB: usize = 1024 * MB;
@@ -49,3 +49,20 @@ impl Receipt {
         }
     }
 }
+
+// the address and the topics of every log are accrued, the same as Ethereum,
+// so the bloom of a block is the OR of the blooms of its receipts
+pub fn logs_bloom<'a, I>(logs: I) -> Bloom
+where
+    I: Iterator<Item = &'a Log>,
+{
+    let mut bloom = Bloom::zero();
+
+    for log in logs {
+        bloom.accrue(BloomInput::Raw(log.address.as_bytes()));
+        for topic in log.topics.iter() {
+            bloom.accrue(BloomInput::Raw(topic.as_bytes()));
+        }
+    }
+    bloom
+}
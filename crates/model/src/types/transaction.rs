@@ -1,17 +1,25 @@
-use crate::types::{Bytes, Hash, Hasher, Public, TypesError, H160, H256, H520, U256};
+use crate::{
+    lazy::rayon_install,
+    types::{
+        intrinsic_gas, Bytes, Hash, Hasher, Public, TypesError, H160, H256, H520, U256,
+    },
+};
 pub use ethereum::{
     AccessList, AccessListItem, EIP1559TransactionMessage as TransactionMessage,
     TransactionAction, TransactionRecoveryId, TransactionSignature,
 };
+use rayon::prelude::*;
 use rlp::{Encodable, RlpStream};
 use rt_evm_crypto::secp256k1_recover;
 use ruc::*;
 use serde::{Deserialize, Serialize};
 
 pub const GAS_PER_ZERO_BYTE: u64 = 4;
-pub const GAS_PER_NONZERO_BYTE: u64 = 68;
+pub const GAS_PER_NONZERO_BYTE: u64 = 16;
 pub const GAS_CALL_TRANSACTION: u64 = 21_000;
 pub const GAS_CREATE_TRANSACTION: u64 = 32_000;
+pub const GAS_PER_ACCESS_LIST_ADDRESS: u64 = 2_400;
+pub const GAS_PER_ACCESS_LIST_STORAGE_KEY: u64 = 1_900;
 pub const MAX_PRIORITY_FEE_PER_GAS: u64 = 1_337;
 pub const MIN_TRANSACTION_GAS_LIMIT: u64 = 21_000;
 
@@ -42,12 +50,17 @@ impl UnsignedTransaction {
     }
 
     pub fn base_gas(&self) -> u64 {
-        let base = match self.action() {
-            TransactionAction::Call(_) => GAS_CALL_TRANSACTION,
-            TransactionAction::Create => GAS_CREATE_TRANSACTION + GAS_CALL_TRANSACTION,
-        };
+        intrinsic_gas(self.is_create(), self.data(), &[])
+    }
+
+    // base gas + access list cost,
+    // the minimum gas that a transaction must pay before execution
+    pub fn intrinsic_gas(&self) -> u64 {
+        intrinsic_gas(self.is_create(), self.data(), &self.access_list())
+    }
 
-        base + data_gas_cost(self.data())
+    pub fn is_create(&self) -> bool {
+        matches!(self.action(), TransactionAction::Create)
     }
 
     pub fn is_legacy(&self) -> bool {
@@ -92,6 +105,28 @@ impl UnsignedTransaction {
         }
     }
 
+    /// The tip per gas paid to the proposer under the `base_fee`,
+    /// zero if the tx can not afford the `base_fee`.
+    pub fn effective_priority_fee(&self, base_fee: U256) -> U256 {
+        match self {
+            UnsignedTransaction::Legacy(tx) => tx.gas_price.saturating_sub(base_fee),
+            UnsignedTransaction::Eip2930(tx) => tx.gas_price.saturating_sub(base_fee),
+            UnsignedTransaction::Eip1559(tx) => tx
+                .gas_price
+                .saturating_sub(base_fee)
+                .min(tx.max_priority_fee_per_gas),
+        }
+    }
+
+    /// The max fee per gas paid in total, including the `base_fee`.
+    pub fn max_fee_per_gas(&self) -> &U256 {
+        match self {
+            UnsignedTransaction::Legacy(tx) => &tx.gas_price,
+            UnsignedTransaction::Eip2930(tx) => &tx.gas_price,
+            UnsignedTransaction::Eip1559(tx) => &tx.gas_price,
+        }
+    }
+
     pub fn max_priority_fee_per_gas(&self) -> &U256 {
         match self {
             UnsignedTransaction::Legacy(tx) => &tx.gas_price,
@@ -459,6 +494,23 @@ impl SignedTransaction {
     }
 }
 
+/// Recover the signers of all the transactions in parallel,
+/// each one must match the `sender` recorded in the transaction.
+pub fn verify_signatures_batch(txs: &[SignedTransaction]) -> Result<Vec<H160>> {
+    rayon_install(|| {
+        txs.par_iter()
+            .map(|tx| {
+                let recovered =
+                    SignedTransaction::try_from(tx.transaction.clone()).c(d!())?;
+                if tx != &recovered {
+                    return Err(eg!("Signature verify failed"));
+                }
+                Ok(recovered.sender)
+            })
+            .collect()
+    })
+}
+
 pub fn public_to_address(public: &Public) -> H160 {
     let hash = Hasher::digest(public);
     let mut ret = H160::zero();
@@ -485,3 +537,116 @@ pub fn data_gas_cost(data: &[u8]) -> u64 {
 
     ret
 }
+
+pub fn access_list_gas_cost(access_list: &[AccessListItem]) -> u64 {
+    access_list.iter().fold(0, |acc, item| {
+        acc + GAS_PER_ACCESS_LIST_ADDRESS
+            + GAS_PER_ACCESS_LIST_STORAGE_KEY * item.storage_keys.len() as u64
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lazy::rayon_install_in;
+    use rt_evm_crypto::{
+        HashValue, PrivateKey, Secp256k1RecoverablePrivateKey, Signature,
+    };
+
+    fn mock_eip2930_tx(data: Bytes, access_list: AccessList) -> UnsignedTransaction {
+        UnsignedTransaction::Eip2930(Eip2930Transaction {
+            nonce: U256::zero(),
+            gas_price: U256::one(),
+            gas_limit: MIN_TRANSACTION_GAS_LIMIT.into(),
+            action: TransactionAction::Call(H160::random()),
+            value: U256::zero(),
+            data,
+            access_list,
+        })
+    }
+
+    #[test]
+    fn test_intrinsic_gas() {
+        let tx = mock_eip2930_tx(vec![], vec![]);
+        assert_eq!(tx.intrinsic_gas(), GAS_CALL_TRANSACTION);
+
+        let tx = mock_eip2930_tx(
+            vec![0, 1],
+            vec![AccessListItem {
+                address: H160::random(),
+                storage_keys: vec![H256::random(), H256::random()],
+            }],
+        );
+        assert_eq!(
+            tx.intrinsic_gas(),
+            GAS_CALL_TRANSACTION
+                + GAS_PER_ZERO_BYTE
+                + GAS_PER_NONZERO_BYTE
+                + GAS_PER_ACCESS_LIST_ADDRESS
+                + 2 * GAS_PER_ACCESS_LIST_STORAGE_KEY
+        );
+
+        // enough for the base cost, but not for the calldata
+        let tx = mock_eip2930_tx(vec![1; 1024], vec![]);
+        assert!(U256::from(tx.intrinsic_gas()) > *tx.gas_limit());
+    }
+
+    fn mock_signed_tx(seed: u8) -> SignedTransaction {
+        let sk = Secp256k1RecoverablePrivateKey::try_from([seed; 32].as_ref()).unwrap();
+
+        let mut utx = UnverifiedTransaction {
+            unsigned: mock_eip2930_tx(vec![seed], vec![]),
+            signature: None,
+            chain_id: 1,
+            hash: Default::default(),
+        };
+        let msg = HashValue::try_from(utx.signature_hash(true).as_bytes()).unwrap();
+        utx.signature = Some(sk.sign_message(&msg).to_bytes().to_vec().into());
+
+        SignedTransaction::try_from(utx).unwrap()
+    }
+
+    #[test]
+    fn test_verify_signatures_batch() {
+        let txs = (1..=16).map(mock_signed_tx).collect::<Vec<_>>();
+
+        let senders = txs
+            .iter()
+            .map(|tx| public_to_address(&tx.transaction.recover_public(true).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(verify_signatures_batch(&txs).unwrap(), senders);
+
+        let mut txs = txs;
+        txs[7].sender = H160::random();
+        assert!(verify_signatures_batch(&txs).is_err());
+    }
+
+    #[test]
+    fn test_verify_signatures_batch_in_dedicated_pool() {
+        // a local pool instead of the global one of `set_rayon_threads`,
+        // which is shared by the tests running in parallel
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let pool = Some(&pool);
+        assert_eq!(2, rayon_install_in(pool, rayon::current_num_threads));
+
+        let txs = (1..=16).map(mock_signed_tx).collect::<Vec<_>>();
+        let senders = txs.iter().map(|tx| tx.sender).collect::<Vec<_>>();
+        assert_eq!(
+            rayon_install_in(pool, || verify_signatures_batch(&txs)).unwrap(),
+            senders
+        );
+
+        let mut txs = txs;
+        txs[3].sender = H160::random();
+        assert!(rayon_install_in(pool, || verify_signatures_batch(&txs)).is_err());
+
+        // the global pool of rayon
+        assert_eq!(
+            rayon::current_num_threads(),
+            rayon_install_in(None, rayon::current_num_threads)
+        );
+    }
+}
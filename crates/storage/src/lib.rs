@@ -10,25 +10,118 @@ use parking_lot::RwLock;
 use rayon::prelude::*;
 use rt_evm_model::{
     codec::ProtocolCodec,
+    lazy::rayon_install,
     traits::{BlockStorage, TxStorage},
     types::{
-        Account, Block, BlockNumber, FatBlock, Hash, Header, Receipt, SignedTransaction,
-        H160, H256, NIL_HASH, U256, WORLD_STATE_META_KEY,
+        Account, Block, BlockEvent, BlockNumber, FatBlock, Hash, Hasher, Header,
+        MerkleRoot, Receipt, SignedTransaction, H160, H256, NIL_HASH, U256,
+        WORLD_STATE_META_KEY,
     },
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use tokio::sync::broadcast::{self, Receiver, Sender};
 use trie_db::MptOnce;
 use vsdb::{MapxOrd, MapxRaw};
 
-const BATCH_LIMIT: usize = 1000;
+const DEFAULT_BATCH_LIMIT: usize = 1000;
+
+// the number of the block events kept for the slow subscribers
+const BLOCK_EVENTS_CAP: usize = 1024;
+
+// at most so many recorded addresses are visited per returned account
+// by one `get_account_range`, most of them may be absent from an old state
+const ACCOUNT_RANGE_SCAN_FACTOR: usize = 8;
+
+/// The version of the serialized layout of `FunStorage`,
+/// bumped whenever a field is added to or removed from the `DB`,
+/// see `FunStorage::from_bytes`.
+pub const STORAGE_LAYOUT_VERSION: u8 = 3;
+
+static TRIE_RESTORE_RETRIES: AtomicU32 = AtomicU32::new(0);
+static TRIE_RESTORE_BACKOFF_MS: AtomicU64 = AtomicU64::new(10);
+
+/// Retry a failed `trie_restore` of the read paths at most `retries` times,
+/// e.g. when the data is being moved by a concurrent compaction,
+/// waiting `backoff`, `backoff * 2`, `backoff * 4` ... between the attempts.
+///
+/// Disabled by default, that is, `retries` is `0`.
+pub fn set_trie_restore_retry(retries: u32, backoff: Duration) {
+    TRIE_RESTORE_RETRIES.store(retries, Ordering::Relaxed);
+    TRIE_RESTORE_BACKOFF_MS.store(backoff.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Same as `MptStore::trie_restore`, but retry the failures as configured
+/// by `set_trie_restore_retry`.
+///
+/// NOTE:
+/// vsdb does not tell a transient failure from a permanent one,
+/// all of them are retried, but only for the roots that may exist,
+/// a permanent failure costs at most the configured retries.
+pub fn trie_restore(
+    trie_db: &MptStore,
+    backend_key: &[u8],
+    root: MerkleRoot,
+) -> Result<MptOnce> {
+    // a trie is never persisted at the zero root, there is nothing to wait for
+    let retries = alt!(
+        NIL_HASH == root,
+        0,
+        TRIE_RESTORE_RETRIES.load(Ordering::Relaxed)
+    );
+    let backoff = Duration::from_millis(TRIE_RESTORE_BACKOFF_MS.load(Ordering::Relaxed));
+
+    with_retry(retries, backoff, || {
+        trie_db.trie_restore(backend_key, root.into()).c(d!())
+    })
+}
+
+fn with_retry<T>(
+    retries: u32,
+    backoff: Duration,
+    mut op: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let mut wait = backoff;
+    for _ in 0..retries {
+        if let Ok(v) = op() {
+            return Ok(v);
+        }
+        thread::sleep(wait);
+        wait = wait.saturating_mul(2);
+    }
+    op()
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FunStorage {
     db: DB,
     #[serde(skip)]
     cache: Cache,
+
+    // the max number of items in one `get_txs`/`get_receipts` request
+    #[serde(skip, default = "default_batch_limit")]
+    batch_limit: AtomicUsize,
+
+    #[serde(skip)]
+    block_events: BlockEventHub,
+
+    // the estimated bytes of the blocks persisted since the process started
+    #[serde(skip)]
+    persisted_bytes: AtomicU64,
+
+    // the number of the storage tries released since the process started
+    #[serde(skip)]
+    released_storage_tries: AtomicU64,
 }
 
 const DEFAULT_CACHE_SIZE: u64 = 100_0000;
@@ -36,24 +129,394 @@ const DEFAULT_CACHE_SIZE: u64 = 100_0000;
 impl FunStorage {
     pub fn new(cache_size: u64) -> Self {
         Self {
-            db: DB::new(),
             cache: Cache::new(cache_size),
+            ..Self::from_db(DB::new())
+        }
+    }
+
+    fn from_db(db: DB) -> Self {
+        Self {
+            db,
+            cache: Cache::default(),
+            batch_limit: default_batch_limit(),
+            block_events: BlockEventHub::default(),
+            persisted_bytes: AtomicU64::new(0),
+            released_storage_tries: AtomicU64::new(0),
+        }
+    }
+
+    /// Decode the `bcs` bytes of a `FunStorage` serialized in the layout `version`,
+    /// `0` is the one written before the meta files were versioned.
+    ///
+    /// `bcs` is positional, the bytes of an older layout can not be decoded
+    /// as the current one, so each of them is decoded by its own definition,
+    /// and the indexes missing in it are created empty,
+    /// they only cover the data written afterwards.
+    pub fn from_bytes(bytes: &[u8], version: u8) -> Result<Self> {
+        let db = match version {
+            0 => bcs::from_bytes::<DBV0>(bytes).c(d!())?.into(),
+            1 => bcs::from_bytes::<DBV1>(bytes).c(d!())?.into(),
+            2 => bcs::from_bytes::<DBV2>(bytes).c(d!())?.into(),
+            STORAGE_LAYOUT_VERSION => bcs::from_bytes::<DB>(bytes).c(d!())?,
+            v => return Err(eg!("unsupported storage layout: {}", v)),
+        };
+        Ok(Self::from_db(db))
+    }
+
+    /// Receive a `BlockEvent` for every block set from now on,
+    /// including the reorgs, e.g. for the indexers tracking the canonical chain.
+    ///
+    /// A receiver falling more than 1024 events behind gets `RecvError::Lagged`,
+    /// the oldest events are dropped, and it continues from the kept ones.
+    ///
+    /// Drop the receiver to unsubscribe.
+    pub fn subscribe_block_events(&self) -> Receiver<BlockEvent> {
+        self.block_events.subscribe()
+    }
+
+    pub fn batch_limit(&self) -> usize {
+        self.batch_limit.load(Ordering::Relaxed)
+    }
+
+    /// Set the max number of items in one `get_txs`/`get_receipts` request,
+    /// `DEFAULT_BATCH_LIMIT` by default, it is not persisted.
+    pub fn set_batch_limit(&self, limit: usize) {
+        self.batch_limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// Persist all the data of a block.
+    ///
+    /// vsdb has no write batch across the maps, so the block header is written
+    /// at last as the commit marker, every read of a block, a tx or a receipt
+    /// is gated on the header of its height, see `is_committed`,
+    /// and a crash in the middle will never leave a partially visible block,
+    /// the leftovers will be overwritten when the block is produced again.
+    ///
+    /// The header of a block replaced by a reorg is removed at first,
+    /// or the data of the new block would be visible through it.
+    pub fn persist_block(
+        &self,
+        block: Block,
+        txs: Vec<SignedTransaction>,
+        receipts: Vec<Receipt>,
+    ) -> Result<()> {
+        let number = block.header.number;
+        let bytes = block.estimated_storage_bytes(&txs, &receipts);
+
+        let replaced = self.get_block_hash(number).c(d!())?;
+        if replaced.is_some() {
+            self.uncommit(number);
+        }
+
+        self.insert_txs(number, txs).c(d!())?;
+        self.insert_receipts(number, receipts).c(d!())?;
+        self.commit_block(block, replaced).c(d!())?;
+
+        self.persisted_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// The estimated bytes of the blocks persisted since the process started,
+    /// see `Block::estimated_storage_bytes`, e.g. for projecting the disk growth.
+    pub fn estimated_persisted_bytes(&self) -> u64 {
+        self.persisted_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Record an address, so it can be visited by `get_account_range`.
+    pub fn index_account(&self, address: H160) -> Result<()> {
+        let key = Hasher::digest(address);
+        if !self.db.account_keys.contains_key(&key) {
+            self.db.shadow().account_keys.insert(&key, &address);
+        }
+        Ok(())
+    }
+
+    /// Record a storage slot written to the contract at `address`,
+    /// so its storage trie can be walked by `release_storage_trie`.
+    pub fn index_storage_slot(&self, address: H160, slot: H256) -> Result<()> {
+        let key = Hasher::digest([address.as_bytes(), slot.as_bytes()].concat());
+        if self.db.storage_slot_keys.contains_key(&key) {
+            return Ok(());
+        }
+
+        let mut db = self.db.shadow();
+        let cnt_key = Hasher::digest(address);
+        let seq = self.db.slot_counts.get(&cnt_key).unwrap_or_default();
+        db.address_slots
+            .insert(&address_slot_key(&address, seq), &slot);
+        db.slot_counts.insert(&cnt_key, &(seq + 1));
+        db.storage_slot_keys.insert(&key, &seq);
+        Ok(())
+    }
+
+    /// Release the nodes of the storage trie of `address` at `root`,
+    /// e.g. the one of a destructed contract, after the latest state
+    /// no longer refers to it, return the number of the released slots.
+    ///
+    /// The trie backend does not support iteration, so the recorded slots,
+    /// see `index_storage_slot`, are removed from the trie one by one,
+    /// then the removal is committed, and the trie decrements the reference count
+    /// of every node it replaces, as it does for any commit,
+    /// so each node of the trie is decremented once and becomes prunable
+    /// if nothing else refers to it.
+    ///
+    /// NOTE:
+    /// nothing is purged here, the states persisted before the release,
+    /// e.g. the blocks before a `SELFDESTRUCT`, can still read the trie.
+    pub fn release_storage_trie(
+        &self,
+        trie_db: &MptStore,
+        address: H160,
+        root: MerkleRoot,
+    ) -> Result<usize> {
+        let mut trie = trie_restore(trie_db, address.as_bytes(), root).c(d!())?;
+
+        let cnt = self
+            .db
+            .slot_counts
+            .get(&Hasher::digest(address))
+            .unwrap_or_default();
+
+        let mut released = 0;
+        for seq in 0..cnt {
+            let slot = self
+                .db
+                .address_slots
+                .get(&address_slot_key(&address, seq))
+                .c(d!("the slots of {:?} are incomplete", address))?;
+
+            // the slots written after the `root`
+            if trie.contains(slot.as_bytes()).c(d!())? {
+                trie.remove(slot.as_bytes()).c(d!())?;
+                released += 1;
+            }
+        }
+        trie.commit();
+
+        self.released_storage_tries.fetch_add(1, Ordering::Relaxed);
+        Ok(released)
+    }
+
+    /// The number of the storage tries released since the process started,
+    /// see `release_storage_trie`.
+    pub fn released_storage_trie_cnt(&self) -> u64 {
+        self.released_storage_tries.load(Ordering::Relaxed)
+    }
+
+    /// Get a page of the hashes of the txs sent or received by the `address`,
+    /// from the oldest one, skipping the first `offset` ones,
+    /// along with the total number of them.
+    ///
+    /// NOTE:
+    /// only the txs persisted since this index was introduced are recorded,
+    /// and the txs of the blocks replaced by a reorg are not removed,
+    /// though they can not be found by `get_tx_by_hash` any more.
+    pub fn get_txs_by_address(
+        &self,
+        address: H160,
+        offset: u64,
+        limit: usize,
+    ) -> Result<(u64, Vec<Hash>)> {
+        let total = self
+            .db
+            .tx_counts
+            .get(&Hasher::digest(address))
+            .unwrap_or_default();
+        let limit = limit.min(self.batch_limit()) as u64;
+
+        page_range(total, offset, limit)
+            .map(|seq| {
+                self.db
+                    .address_txs
+                    .get(&address_tx_key(&address, seq))
+                    .c(d!("the history of {:?} is incomplete", address))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|hashes| (total, hashes))
+    }
+
+    /// Get a page of the accounts existing in the `state`,
+    /// in the order of their address hashes, starting from the `start` hash.
+    ///
+    /// The `next` cursor is `None` if no more accounts are left.
+    ///
+    /// NOTE:
+    /// the trie backend does not support iteration,
+    /// so the recorded addresses are walked and looked up in the `state` one by one,
+    /// the walk stops after `ACCOUNT_RANGE_SCAN_FACTOR` addresses per requested account,
+    /// so a page may be short or even empty while the `next` cursor is still returned.
+    #[allow(clippy::type_complexity)]
+    pub fn get_account_range(
+        &self,
+        state: &MptOnce,
+        start: H256,
+        limit: usize,
+    ) -> Result<(Vec<(H256, H160, Account)>, Option<H256>)> {
+        let limit = limit.min(self.batch_limit());
+        let max_scanned = limit.max(1).saturating_mul(ACCOUNT_RANGE_SCAN_FACTOR);
+        let mut ret = vec![];
+
+        for (scanned, (key, address)) in self.db.account_keys.range(start..).enumerate()
+        {
+            if scanned == max_scanned {
+                return Ok((ret, Some(key)));
+            }
+
+            // created after or deleted before the `state`
+            let account = match state.get(address.as_bytes()).c(d!())? {
+                Some(bytes) => Account::decode(bytes).c(d!())?,
+                None => continue,
+            };
+
+            if ret.len() == limit {
+                return Ok((ret, Some(key)));
+            }
+
+            ret.push((key, address, account));
+        }
+
+        Ok((ret, None))
+    }
+
+    /// Rebuild the index from the contract addresses to their code hashes,
+    /// e.g. after it has been lost or corrupted,
+    /// by walking the accounts existing in the world state at `state_root`.
+    ///
+    /// Return the number of the indexed contracts.
+    ///
+    /// NOTE:
+    /// like `get_account_range`, only the recorded addresses can be walked,
+    /// and the contracts whose codes are missing are skipped.
+    pub fn rebuild_code_index(
+        &self,
+        trie_db: &MptStore,
+        state_root: MerkleRoot,
+    ) -> Result<usize> {
+        let state = trie_restore(trie_db, &WORLD_STATE_META_KEY, state_root).c(d!())?;
+
+        let mut db = self.db.shadow();
+        let mut cnt = 0;
+
+        for (_, address) in self.db.account_keys.iter() {
+            let account = match state.get(address.as_bytes()).c(d!())? {
+                Some(bytes) => Account::decode(bytes).c(d!())?,
+                None => continue,
+            };
+
+            let (code_address, code_hash) = match code_index_entry(address, &account) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            if !self.db.codes.contains_key(code_hash.as_bytes()) {
+                continue;
+            }
+
+            db.codes_addr_to_hash
+                .insert(code_address.as_bytes(), code_hash.as_bytes());
+            self.cache
+                .codes_addr_to_hash
+                .insert(code_address, code_hash);
+            cnt += 1;
+        }
+
+        Ok(cnt)
+    }
+
+    // whether the block at `number` has been fully persisted,
+    // it is a hit of the header cache for the recent blocks
+    fn is_committed(&self, number: BlockNumber) -> bool {
+        matches!(self.get_block_header(number), Ok(Some(_)))
+    }
+
+    // whether the `block` is the one committed at its height,
+    // `set_block` writes the block before the header of it
+    fn is_committed_block(&self, block: &Block) -> bool {
+        matches!(
+            self.get_block_header(block.header.number),
+            Ok(Some(h)) if h == block.header
+        )
+    }
+
+    // remove the commit marker of the block at `number`,
+    // all the data of this height is invisible until the next `commit_block`
+    fn uncommit(&self, number: BlockNumber) {
+        self.db.shadow().headers.remove(&number);
+
+        self.cache.headers.invalidate(&number);
+        self.cache.block_hashes.invalidate(&number);
+        self.cache.blocks.invalidate(&number);
+    }
+
+    // `replaced`: the hash of the block committed at the same height before
+    fn commit_block(&self, block: Block, replaced: Option<Hash>) -> Result<()> {
+        let mut db = self.db.shadow();
+
+        let bh = block.hash();
+        let header = block.header.clone();
+        let number = header.number;
+
+        let event = BlockEvent::new(replaced, header.clone());
+        if let BlockEvent::Reorg { removed, .. } = &event {
+            // the replaced block must not be found by its hash any more
+            db.block_numbers.remove(removed);
+            self.cache.block_numbers.invalidate(removed);
+
+            // nor the txs only included in it,
+            // the ones included again have been re-pointed by `insert_txs`
+            if let Some(replaced_block) = self.db.blocks.get(&number) {
+                let included = block.tx_hashes.iter().collect::<HashSet<_>>();
+                replaced_block
+                    .tx_hashes
+                    .iter()
+                    .filter(|h| !included.contains(h))
+                    .filter(|h| {
+                        matches!(self.db.transactions.get(h), Some((n, _)) if n == number)
+                    })
+                    .for_each(|h| {
+                        db.transactions.remove(h);
+                        db.tx_positions.remove(h);
+                        db.receipts.remove(h);
+                        self.cache.transactions.invalidate(h);
+                        self.cache.tx_positions.invalidate(h);
+                        self.cache.receipts.invalidate(h);
+                    });
+            }
         }
+
+        db.block_numbers.insert(&bh, &number);
+        db.blocks.insert(&number, &block);
+
+        // the header is the commit marker of the block, write it at last
+        db.headers.insert(&number, &header);
+
+        self.cache.block_numbers.insert(bh, number);
+        self.cache.block_hashes.insert(number, bh);
+        self.cache.blocks.insert(number, block.clone());
+        self.cache.headers.insert(number, header);
+
+        self.set_latest_block(block).c(d!())?;
+        self.block_events.publish(event);
+
+        Ok(())
     }
 
     fn get_txs_unlimited(
         &self,
         hashes: &[Hash],
     ) -> Vec<Option<(BlockNumber, SignedTransaction)>> {
-        hashes
-            .par_iter()
-            .map(|txh| {
-                self.cache
-                    .transactions
-                    .get(txh)
-                    .or_else(|| self.db.transactions.get(txh))
-            })
-            .collect()
+        rayon_install(|| {
+            hashes
+                .par_iter()
+                .map(|txh| {
+                    self.cache
+                        .transactions
+                        .get(txh)
+                        .or_else(|| self.db.transactions.get(txh))
+                })
+                .collect()
+        })
     }
 }
 
@@ -63,6 +526,61 @@ impl Default for FunStorage {
     }
 }
 
+fn address_tx_key(address: &H160, seq: u64) -> H256 {
+    Hasher::digest([address.as_bytes(), &seq.to_be_bytes()].concat())
+}
+
+fn address_slot_key(address: &H160, seq: u64) -> H256 {
+    Hasher::digest([address.as_bytes(), b"slot", &seq.to_be_bytes()].concat())
+}
+
+// the sequences of the page in a history of `total` txs
+fn page_range(total: u64, offset: u64, limit: u64) -> Range<u64> {
+    offset.min(total)..offset.saturating_add(limit).min(total)
+}
+
+// the same key and value as `insert_code`, `None` for the accounts without code
+fn code_index_entry(address: H160, account: &Account) -> Option<(H256, Hash)> {
+    alt!(
+        NIL_HASH == account.code_hash,
+        None,
+        Some((address.into(), account.code_hash))
+    )
+}
+
+fn default_batch_limit() -> AtomicUsize {
+    AtomicUsize::new(DEFAULT_BATCH_LIMIT)
+}
+
+// broadcast the block events to all the alive subscribers
+#[derive(Debug)]
+struct BlockEventHub {
+    sender: Sender<BlockEvent>,
+}
+
+impl BlockEventHub {
+    fn new(cap: usize) -> Self {
+        Self {
+            sender: broadcast::channel(cap).0,
+        }
+    }
+
+    fn subscribe(&self) -> Receiver<BlockEvent> {
+        self.sender.subscribe()
+    }
+
+    fn publish(&self, event: BlockEvent) {
+        // an error only means that there are no subscribers
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Default for BlockEventHub {
+    fn default() -> Self {
+        Self::new(BLOCK_EVENTS_CAP)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct DB {
     blocks: MapxOrd<u64, Block>,
@@ -71,10 +589,36 @@ struct DB {
 
     transactions: MapxOrd<Hash, (BlockNumber, SignedTransaction)>,
 
+    // tx hash => (block number, tx index in the block)
+    tx_positions: MapxOrd<Hash, (BlockNumber, u32)>,
+
     codes: MapxRaw,
     codes_addr_to_hash: MapxRaw,
 
+    // code hash => the length of the code in bytes
+    code_sizes: MapxOrd<Hash, u64>,
+
     receipts: MapxOrd<Hash, Receipt>,
+
+    // keccak256(address) => address, all the addresses ever written
+    account_keys: MapxOrd<H256, H160>,
+
+    // keccak256(address) => the number of the txs sent or received by the address
+    tx_counts: MapxOrd<H256, u64>,
+
+    // keccak256(address ++ <sequence in big-endian>) => tx hash,
+    // the sequences of an address start from 0, in the order of persisting
+    address_txs: MapxOrd<H256, Hash>,
+
+    // keccak256(address ++ slot) => the sequence of the slot,
+    // all the storage slots ever written, see `index_storage_slot`
+    storage_slot_keys: MapxOrd<H256, u64>,
+
+    // keccak256(address) => the number of the storage slots of the address
+    slot_counts: MapxOrd<H256, u64>,
+
+    // keccak256(address ++ "slot" ++ <sequence in big-endian>) => slot
+    address_slots: MapxOrd<H256, H256>,
 }
 
 impl DB {
@@ -86,10 +630,22 @@ impl DB {
 
             transactions: MapxOrd::new(),
 
+            tx_positions: MapxOrd::new(),
+
             codes: MapxRaw::new(),
             codes_addr_to_hash: MapxRaw::new(),
+            code_sizes: MapxOrd::new(),
 
             receipts: MapxOrd::new(),
+
+            account_keys: MapxOrd::new(),
+
+            tx_counts: MapxOrd::new(),
+            address_txs: MapxOrd::new(),
+
+            storage_slot_keys: MapxOrd::new(),
+            slot_counts: MapxOrd::new(),
+            address_slots: MapxOrd::new(),
         }
     }
 
@@ -101,12 +657,146 @@ impl DB {
                 block_numbers: self.block_numbers.shadow(),
                 headers: self.headers.shadow(),
                 transactions: self.transactions.shadow(),
+                tx_positions: self.tx_positions.shadow(),
                 codes: self.codes.shadow(),
                 codes_addr_to_hash: self.codes_addr_to_hash.shadow(),
+                code_sizes: self.code_sizes.shadow(),
                 receipts: self.receipts.shadow(),
+                account_keys: self.account_keys.shadow(),
+                tx_counts: self.tx_counts.shadow(),
+                address_txs: self.address_txs.shadow(),
+                storage_slot_keys: self.storage_slot_keys.shadow(),
+                slot_counts: self.slot_counts.shadow(),
+                address_slots: self.address_slots.shadow(),
             }
         }
     }
+
+    // append the tx to the histories of its sender and its recipient
+    fn index_tx_addresses(&mut self, tx: &SignedTransaction) {
+        let mut addresses = vec![tx.sender];
+        if let Some(to) = tx.get_to().filter(|to| to != &tx.sender) {
+            addresses.push(to);
+        }
+
+        for address in addresses {
+            let key = Hasher::digest(address);
+            let seq = self.tx_counts.get(&key).unwrap_or_default();
+            self.address_txs
+                .insert(&address_tx_key(&address, seq), &tx.transaction.hash);
+            self.tx_counts.insert(&key, &(seq + 1));
+        }
+    }
+}
+
+// The layout 0 of the `DB`, before any index was added.
+#[derive(Serialize, Deserialize)]
+struct DBV0 {
+    blocks: MapxOrd<u64, Block>,
+    block_numbers: MapxOrd<Hash, u64>,
+    headers: MapxOrd<u64, Header>,
+    transactions: MapxOrd<Hash, (BlockNumber, SignedTransaction)>,
+    codes: MapxRaw,
+    codes_addr_to_hash: MapxRaw,
+    receipts: MapxOrd<Hash, Receipt>,
+}
+
+impl From<DBV0> for DB {
+    fn from(db: DBV0) -> Self {
+        Self {
+            blocks: db.blocks,
+            block_numbers: db.block_numbers,
+            headers: db.headers,
+            transactions: db.transactions,
+            tx_positions: MapxOrd::new(),
+            codes: db.codes,
+            codes_addr_to_hash: db.codes_addr_to_hash,
+            code_sizes: MapxOrd::new(),
+            receipts: db.receipts,
+            account_keys: MapxOrd::new(),
+            tx_counts: MapxOrd::new(),
+            address_txs: MapxOrd::new(),
+            storage_slot_keys: MapxOrd::new(),
+            slot_counts: MapxOrd::new(),
+            address_slots: MapxOrd::new(),
+        }
+    }
+}
+
+// The layout 1 of the `DB`, before the histories of the addresses were indexed.
+#[derive(Serialize, Deserialize)]
+struct DBV1 {
+    blocks: MapxOrd<u64, Block>,
+    block_numbers: MapxOrd<Hash, u64>,
+    headers: MapxOrd<u64, Header>,
+    transactions: MapxOrd<Hash, (BlockNumber, SignedTransaction)>,
+    tx_positions: MapxOrd<Hash, (BlockNumber, u32)>,
+    codes: MapxRaw,
+    codes_addr_to_hash: MapxRaw,
+    code_sizes: MapxOrd<Hash, u64>,
+    receipts: MapxOrd<Hash, Receipt>,
+    account_keys: MapxOrd<H256, H160>,
+}
+
+impl From<DBV1> for DB {
+    fn from(db: DBV1) -> Self {
+        Self {
+            blocks: db.blocks,
+            block_numbers: db.block_numbers,
+            headers: db.headers,
+            transactions: db.transactions,
+            tx_positions: db.tx_positions,
+            codes: db.codes,
+            codes_addr_to_hash: db.codes_addr_to_hash,
+            code_sizes: db.code_sizes,
+            receipts: db.receipts,
+            account_keys: db.account_keys,
+            tx_counts: MapxOrd::new(),
+            address_txs: MapxOrd::new(),
+            storage_slot_keys: MapxOrd::new(),
+            slot_counts: MapxOrd::new(),
+            address_slots: MapxOrd::new(),
+        }
+    }
+}
+
+// The layout 2 of the `DB`, before the storage slots were indexed.
+#[derive(Serialize, Deserialize)]
+struct DBV2 {
+    blocks: MapxOrd<u64, Block>,
+    block_numbers: MapxOrd<Hash, u64>,
+    headers: MapxOrd<u64, Header>,
+    transactions: MapxOrd<Hash, (BlockNumber, SignedTransaction)>,
+    tx_positions: MapxOrd<Hash, (BlockNumber, u32)>,
+    codes: MapxRaw,
+    codes_addr_to_hash: MapxRaw,
+    code_sizes: MapxOrd<Hash, u64>,
+    receipts: MapxOrd<Hash, Receipt>,
+    account_keys: MapxOrd<H256, H160>,
+    tx_counts: MapxOrd<H256, u64>,
+    address_txs: MapxOrd<H256, Hash>,
+}
+
+impl From<DBV2> for DB {
+    fn from(db: DBV2) -> Self {
+        Self {
+            blocks: db.blocks,
+            block_numbers: db.block_numbers,
+            headers: db.headers,
+            transactions: db.transactions,
+            tx_positions: db.tx_positions,
+            codes: db.codes,
+            codes_addr_to_hash: db.codes_addr_to_hash,
+            code_sizes: db.code_sizes,
+            receipts: db.receipts,
+            account_keys: db.account_keys,
+            tx_counts: db.tx_counts,
+            address_txs: db.address_txs,
+            storage_slot_keys: MapxOrd::new(),
+            slot_counts: MapxOrd::new(),
+            address_slots: MapxOrd::new(),
+        }
+    }
 }
 
 #[macro_export(local_inner_macros)]
@@ -123,11 +813,15 @@ macro_rules! gen_lru {
 struct Cache {
     blocks: Lru<u64, Block>,
     block_numbers: Lru<Hash, u64>,
+    block_hashes: Lru<u64, Hash>,
     headers: Lru<u64, Header>,
 
     transactions: Lru<Hash, (BlockNumber, SignedTransaction)>,
 
+    tx_positions: Lru<Hash, (BlockNumber, u32)>,
+
     codes: Lru<Hash, Vec<u8>>,
+    code_sizes: Lru<Hash, u64>,
     codes_addr_to_hash: Lru<H256, Hash>,
 
     receipts: Lru<Hash, Receipt>,
@@ -140,10 +834,13 @@ impl Cache {
         Self {
             blocks: gen_lru!(size),
             block_numbers: gen_lru!(size),
+            block_hashes: gen_lru!(size),
             headers: gen_lru!(size),
             transactions: gen_lru!(size),
+            tx_positions: gen_lru!(size),
             codes: gen_lru!(size),
             codes_addr_to_hash: gen_lru!(size),
+            code_sizes: gen_lru!(size),
             receipts: gen_lru!(size),
             latest_block: Arc::new(RwLock::new(None)),
         }
@@ -158,29 +855,21 @@ impl Default for Cache {
 
 impl BlockStorage for FunStorage {
     fn set_block(&self, block: Block) -> Result<()> {
-        let mut db = self.db.shadow();
-
-        let bh = block.hash();
-        let header = block.header.clone();
-        let number = header.number;
-
-        db.block_numbers.insert(&bh, &number);
-        db.headers.insert(&number, &header);
-        db.blocks.insert(&number, &block);
-
-        self.cache.block_numbers.insert(bh, number);
-        self.cache.headers.insert(number, header);
-        self.cache.blocks.insert(number, block.clone());
-
-        self.set_latest_block(block).c(d!())
+        let replaced = self.get_block_hash(block.header.number).c(d!())?;
+        self.commit_block(block, replaced).c(d!())
     }
 
     fn get_block(&self, number: u64) -> Result<Option<Block>> {
+        // the cached ones are always committed
+        if let Some(block) = self.cache.blocks.get(&number) {
+            return Ok(Some(block));
+        }
+
         Ok(self
-            .cache
+            .db
             .blocks
             .get(&number)
-            .or_else(|| self.db.blocks.get(&number)))
+            .filter(|block| self.is_committed_block(block)))
     }
 
     fn get_fatblock(&self, number: u64) -> Result<Option<FatBlock>> {
@@ -198,11 +887,33 @@ impl BlockStorage for FunStorage {
     }
 
     fn get_block_header(&self, number: u64) -> Result<Option<Header>> {
-        Ok(self
-            .cache
-            .headers
-            .get(&number)
-            .or_else(|| self.db.headers.get(&number)))
+        if let Some(h) = self.cache.headers.get(&number) {
+            return Ok(Some(h));
+        }
+
+        // fill the cache on a miss, so verifying a range of blocks one by one
+        // finds the parent of the next block in the cache
+        let h = self.db.headers.get(&number);
+        if let Some(h) = h.as_ref() {
+            self.cache.headers.insert(number, h.clone());
+        }
+
+        Ok(h)
+    }
+
+    fn get_block_hash(&self, number: u64) -> Result<Option<Hash>> {
+        if let Some(bh) = self.cache.block_hashes.get(&number) {
+            return Ok(Some(bh));
+        }
+
+        // the hash can always be derived from the header,
+        // so it is only memoized in the cache instead of an extra on-disk index
+        let bh = self.get_block_header(number).c(d!())?.map(|h| h.hash());
+        if let Some(bh) = bh {
+            self.cache.block_hashes.insert(number, bh);
+        }
+
+        Ok(bh)
     }
 
     fn get_latest_block(&self) -> Result<Block> {
@@ -210,7 +921,12 @@ impl BlockStorage for FunStorage {
             .latest_block
             .read()
             .clone()
-            .or_else(|| self.db.blocks.last().map(|(_, b)| b))
+            .or_else(|| {
+                self.db
+                    .headers
+                    .last()
+                    .and_then(|(number, _)| self.get_block(number).ok().flatten())
+            })
             .ok_or_else(|| eg!("no blocks found"))
     }
 
@@ -222,6 +938,17 @@ impl BlockStorage for FunStorage {
     fn get_latest_block_header(&self) -> Result<Header> {
         self.get_latest_block().c(d!()).map(|b| b.header)
     }
+
+    fn get_latest_block_number(&self) -> Result<BlockNumber> {
+        if let Some(b) = self.cache.latest_block.read().as_ref() {
+            return Ok(b.header.number);
+        }
+        self.db
+            .headers
+            .last()
+            .map(|(number, _)| number)
+            .ok_or_else(|| eg!("no blocks found"))
+    }
 }
 
 impl TxStorage for FunStorage {
@@ -234,8 +961,20 @@ impl TxStorage for FunStorage {
 
         signed_txs
             .into_iter()
-            .map(|tx| (block_number, tx))
-            .for_each(|h_tx| {
+            .enumerate()
+            .map(|(idx, tx)| (idx as u32, (block_number, tx)))
+            .for_each(|(idx, h_tx)| {
+                // a tx persisted again, e.g. by a reorg, has been indexed
+                if !self.db.tx_positions.contains_key(&h_tx.1.transaction.hash) {
+                    db.index_tx_addresses(&h_tx.1);
+                }
+
+                let position = (block_number, idx);
+                db.tx_positions.insert(&h_tx.1.transaction.hash, &position);
+                self.cache
+                    .tx_positions
+                    .insert(h_tx.1.transaction.hash, position);
+
                 db.transactions.insert(&h_tx.1.transaction.hash, &h_tx);
                 self.cache
                     .transactions
@@ -252,12 +991,11 @@ impl TxStorage for FunStorage {
             .get(block_hash)
             .or_else(|| self.db.block_numbers.get(block_hash))
         {
-            self.cache
-                .blocks
-                .get(&number)
-                .or_else(|| self.db.blocks.get(&number))
-                .map(Some)
-                .c(d!("BUG!"))
+            // the index is written before the commit marker
+            if self.get_block_hash(number).c(d!())? != Some(*block_hash) {
+                return Ok(None);
+            }
+            self.get_block(number).c(d!())
         } else {
             Ok(None)
         }
@@ -268,10 +1006,14 @@ impl TxStorage for FunStorage {
         block_number: u64,
         hashes: &[Hash],
     ) -> Result<Vec<Option<SignedTransaction>>> {
-        if hashes.len() > BATCH_LIMIT {
+        if hashes.len() > self.batch_limit() {
             return Err(eg!("request too large"));
         }
 
+        if !self.is_committed(block_number) {
+            return Ok(vec![None; hashes.len()]);
+        }
+
         Ok(self
             .get_txs_unlimited(hashes)
             .into_iter()
@@ -287,12 +1029,44 @@ impl TxStorage for FunStorage {
     }
 
     fn get_tx_by_hash(&self, hash: &Hash) -> Result<Option<SignedTransaction>> {
+        self.get_tx_with_block(hash).map(|tx| tx.map(|(_, tx)| tx))
+    }
+
+    fn get_tx_with_block(
+        &self,
+        hash: &Hash,
+    ) -> Result<Option<(BlockNumber, SignedTransaction)>> {
         Ok(self
             .cache
             .transactions
             .get(hash)
             .or_else(|| self.db.transactions.get(hash))
-            .map(|(_, tx)| tx))
+            .filter(|(number, _)| self.is_committed(*number)))
+    }
+
+    fn get_tx_position(&self, hash: &Hash) -> Result<Option<(BlockNumber, u32)>> {
+        if let Some(position) = self
+            .cache
+            .tx_positions
+            .get(hash)
+            .or_else(|| self.db.tx_positions.get(hash))
+            .filter(|(number, _)| self.is_committed(*number))
+        {
+            return Ok(Some(position));
+        }
+
+        // derive it from the block for the data without the index
+        if let Some((number, _)) = self.get_tx_with_block(hash).c(d!())? {
+            let block = self.get_block(number).c(d!())?.c(d!("BUG!"))?;
+            let idx = block
+                .tx_hashes
+                .iter()
+                .position(|h| h == hash)
+                .c(d!("BUG!"))?;
+            Ok(Some((number, idx as u32)))
+        } else {
+            Ok(None)
+        }
     }
 
     fn insert_receipts(&self, _block_number: u64, receipts: Vec<Receipt>) -> Result<()> {
@@ -311,7 +1085,8 @@ impl TxStorage for FunStorage {
             .cache
             .receipts
             .get(hash)
-            .or_else(|| self.db.receipts.get(hash)))
+            .or_else(|| self.db.receipts.get(hash))
+            .filter(|r| self.is_committed(r.block_number)))
     }
 
     fn get_receipts(
@@ -319,10 +1094,14 @@ impl TxStorage for FunStorage {
         block_number: u64,
         hashes: &[Hash],
     ) -> Result<Vec<Option<Receipt>>> {
-        if hashes.len() > BATCH_LIMIT {
+        if hashes.len() > self.batch_limit() {
             return Err(eg!("request too large"));
         }
 
+        if !self.is_committed(block_number) {
+            return Ok(vec![None; hashes.len()]);
+        }
+
         Ok(hashes
             .iter()
             .map(|txh| {
@@ -335,6 +1114,23 @@ impl TxStorage for FunStorage {
             .collect())
     }
 
+    fn get_block_receipts(&self, block_number: u64) -> Result<Option<Vec<Receipt>>> {
+        if let Some(block) = self.get_block(block_number).c(d!())? {
+            block
+                .tx_hashes
+                .iter()
+                .map(|txh| {
+                    self.get_receipt_by_hash(txh)
+                        .c(d!())?
+                        .c(d!("receipt not found: {:?}", txh))
+                })
+                .collect::<Result<Vec<_>>>()
+                .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
     fn insert_code(
         &self,
         code_address: H256,
@@ -346,7 +1142,9 @@ impl TxStorage for FunStorage {
         db.codes_addr_to_hash
             .insert(code_address.as_bytes(), code_hash.as_bytes());
         db.codes.insert(code_hash.as_bytes(), &code);
+        db.code_sizes.insert(&code_hash, &(code.len() as u64));
 
+        self.cache.code_sizes.insert(code_hash, code.len() as u64);
         self.cache
             .codes_addr_to_hash
             .insert(code_address, code_hash);
@@ -375,6 +1173,35 @@ impl TxStorage for FunStorage {
             Ok(None)
         }
     }
+
+    fn get_code_size_by_hash(&self, hash: &Hash) -> Result<Option<u64>> {
+        if let Some(size) = self
+            .cache
+            .code_sizes
+            .get(hash)
+            .or_else(|| self.db.code_sizes.get(hash))
+        {
+            return Ok(Some(size));
+        }
+
+        // the codes inserted before the size metadata was introduced
+        self.get_code_by_hash(hash)
+            .c(d!())
+            .map(|code| code.map(|c| c.len() as u64))
+    }
+
+    fn get_code_size_by_address(&self, address: &H256) -> Result<Option<u64>> {
+        if let Some(h) = self.cache.codes_addr_to_hash.get(address).or_else(|| {
+            self.db
+                .codes_addr_to_hash
+                .get(address)
+                .map(|hash| H256::from_slice(&hash))
+        }) {
+            self.get_code_size_by_hash(&h).c(d!())
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 pub fn get_account_by_backend(
@@ -389,9 +1216,8 @@ pub fn get_account_by_backend(
         storage.get_latest_block_header().c(d!())?
     };
 
-    let state = trie_db
-        .trie_restore(&WORLD_STATE_META_KEY, header.state_root.into())
-        .c(d!())?;
+    let state =
+        trie_restore(trie_db, &WORLD_STATE_META_KEY, header.state_root).c(d!())?;
 
     get_account_by_state(&state, address).c(d!())
 }
@@ -419,6 +1245,7 @@ pub fn save_account_by_backend(
         .trie_restore(&WORLD_STATE_META_KEY, header.state_root.into())
         .c(d!())?;
 
+    storage.index_account(address).c(d!())?;
     save_account_by_state(&mut state, address, account).c(d!())
 }
 
@@ -432,3 +1259,560 @@ pub fn save_account_by_state(
         .c(d!())
         .and_then(|acc| state.insert(address.as_bytes(), &acc).c(d!()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rt_evm_model::types::{
+        LegacyTransaction, TransactionAction, UnsignedTransaction, UnverifiedTransaction,
+    };
+
+    #[test]
+    fn test_retry_transient_failures() {
+        let flaky = |failures: u32| {
+            let mut calls = 0;
+            move || {
+                calls += 1;
+                alt!(calls > failures, Ok(calls), Err(eg!("transient")))
+            }
+        };
+
+        let backoff = Duration::from_millis(1);
+        assert_eq!(1, with_retry(0, backoff, flaky(0)).unwrap());
+        assert!(with_retry(0, backoff, flaky(1)).is_err());
+
+        // succeeds on the last retry
+        assert_eq!(3, with_retry(2, backoff, flaky(2)).unwrap());
+        assert!(with_retry(2, backoff, flaky(3)).is_err());
+
+        // no more attempts after a success
+        let mut calls = 0;
+        let op = || {
+            calls += 1;
+            Ok(())
+        };
+        with_retry(5, backoff, op).unwrap();
+        assert_eq!(1, calls);
+    }
+
+    #[test]
+    fn test_address_history_page() {
+        let (alice, bob) = (H160::repeat_byte(1), H160::repeat_byte(2));
+        assert_ne!(address_tx_key(&alice, 0), address_tx_key(&alice, 1));
+        assert_ne!(address_tx_key(&alice, 0), address_tx_key(&bob, 0));
+
+        // 5 txs in pages of 2
+        assert_eq!(0..2, page_range(5, 0, 2));
+        assert_eq!(2..4, page_range(5, 2, 2));
+        assert_eq!(4..5, page_range(5, 4, 2));
+        assert!(page_range(5, 5, 2).is_empty());
+        assert!(page_range(5, 9, 2).is_empty());
+        assert!(page_range(0, 0, 2).is_empty());
+        assert_eq!(3..5, page_range(5, 3, u64::MAX));
+    }
+
+    #[test]
+    fn test_code_index_entry() {
+        let address = H160::repeat_byte(7);
+        let mut account = Account {
+            nonce: U256::zero(),
+            balance: U256::one(),
+            storage_root: NIL_HASH,
+            code_hash: NIL_HASH,
+        };
+        assert!(code_index_entry(address, &account).is_none());
+
+        // an empty code is still a code
+        account.code_hash = Hasher::digest(b"");
+        let (code_address, code_hash) = code_index_entry(address, &account).unwrap();
+        assert_eq!(H256::from(address), code_address);
+        assert_eq!(&code_address[12..], address.as_bytes());
+        assert_eq!(account.code_hash, code_hash);
+    }
+
+    #[test]
+    fn test_block_event_hub_reorg() {
+        let hub = BlockEventHub::default();
+        let mut rx = hub.subscribe();
+        let dropped = hub.subscribe();
+        drop(dropped);
+
+        let old = Header {
+            number: 9,
+            ..Default::default()
+        };
+        hub.publish(BlockEvent::new(None, old.clone()));
+        assert_eq!(1, hub.sender.receiver_count());
+
+        // the same block again
+        hub.publish(BlockEvent::new(Some(old.hash()), old.clone()));
+
+        // a different block at the same height
+        let new = Header {
+            number: 9,
+            timestamp: 1,
+            ..Default::default()
+        };
+        hub.publish(BlockEvent::new(Some(old.hash()), new.clone()));
+
+        assert_eq!(BlockEvent::NewBlock(old.clone()), rx.try_recv().unwrap());
+        assert_eq!(BlockEvent::NewBlock(old.clone()), rx.try_recv().unwrap());
+        assert_eq!(
+            BlockEvent::Reorg {
+                removed: old.hash(),
+                header: new
+            },
+            rx.try_recv().unwrap()
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_block_event_hub_lagged() {
+        use tokio::sync::broadcast::error::TryRecvError;
+
+        let hub = BlockEventHub::new(2);
+        let mut rx = hub.subscribe();
+
+        let headers = (0..3)
+            .map(|number| Header {
+                number,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        headers
+            .iter()
+            .for_each(|h| hub.publish(BlockEvent::NewBlock(h.clone())));
+
+        // the oldest one is dropped
+        assert_eq!(Err(TryRecvError::Lagged(1)), rx.try_recv());
+        assert_eq!(
+            BlockEvent::NewBlock(headers[1].clone()),
+            rx.try_recv().unwrap()
+        );
+        assert_eq!(
+            BlockEvent::NewBlock(headers[2].clone()),
+            rx.try_recv().unwrap()
+        );
+        assert_eq!(Err(TryRecvError::Empty), rx.try_recv());
+    }
+
+    #[test]
+    fn test_decode_older_layouts() {
+        let header = Header {
+            number: 7,
+            ..Default::default()
+        };
+
+        let mut v0 = DBV0 {
+            blocks: MapxOrd::new(),
+            block_numbers: MapxOrd::new(),
+            headers: MapxOrd::new(),
+            transactions: MapxOrd::new(),
+            codes: MapxRaw::new(),
+            codes_addr_to_hash: MapxRaw::new(),
+            receipts: MapxOrd::new(),
+        };
+        v0.headers.insert(&header.number, &header);
+        let bytes = bcs::to_bytes(&v0).unwrap();
+        assert!(FunStorage::from_bytes(&bytes, 1).is_err());
+        let storage = FunStorage::from_bytes(&bytes, 0).unwrap();
+        assert_eq!(Some(header.clone()), storage.get_block_header(7).unwrap());
+
+        let mut v1 = DBV1 {
+            blocks: MapxOrd::new(),
+            block_numbers: MapxOrd::new(),
+            headers: MapxOrd::new(),
+            transactions: MapxOrd::new(),
+            tx_positions: MapxOrd::new(),
+            codes: MapxRaw::new(),
+            codes_addr_to_hash: MapxRaw::new(),
+            code_sizes: MapxOrd::new(),
+            receipts: MapxOrd::new(),
+            account_keys: MapxOrd::new(),
+        };
+        v1.headers.insert(&header.number, &header);
+        let bytes = bcs::to_bytes(&v1).unwrap();
+
+        // the current layout can not decode it
+        assert!(FunStorage::from_bytes(&bytes, STORAGE_LAYOUT_VERSION).is_err());
+
+        let storage = FunStorage::from_bytes(&bytes, 1).unwrap();
+        assert_eq!(Some(header.clone()), storage.get_block_header(7).unwrap());
+        assert_eq!(
+            (0, vec![]),
+            storage.get_txs_by_address(H160::zero(), 0, 10).unwrap()
+        );
+
+        let mut v2 = DBV2 {
+            blocks: MapxOrd::new(),
+            block_numbers: MapxOrd::new(),
+            headers: MapxOrd::new(),
+            transactions: MapxOrd::new(),
+            tx_positions: MapxOrd::new(),
+            codes: MapxRaw::new(),
+            codes_addr_to_hash: MapxRaw::new(),
+            code_sizes: MapxOrd::new(),
+            receipts: MapxOrd::new(),
+            account_keys: MapxOrd::new(),
+            tx_counts: MapxOrd::new(),
+            address_txs: MapxOrd::new(),
+        };
+        v2.headers.insert(&header.number, &header);
+        let bytes = bcs::to_bytes(&v2).unwrap();
+        assert!(FunStorage::from_bytes(&bytes, STORAGE_LAYOUT_VERSION).is_err());
+
+        let storage = FunStorage::from_bytes(&bytes, 2).unwrap();
+        assert_eq!(Some(header.clone()), storage.get_block_header(7).unwrap());
+
+        // written in the current layout afterwards
+        let bytes = bcs::to_bytes(&storage).unwrap();
+        let storage = FunStorage::from_bytes(&bytes, STORAGE_LAYOUT_VERSION).unwrap();
+        assert_eq!(Some(header), storage.get_block_header(7).unwrap());
+
+        assert!(FunStorage::from_bytes(&bytes, STORAGE_LAYOUT_VERSION + 1).is_err());
+    }
+
+    fn mock_tx(nonce: u64) -> SignedTransaction {
+        SignedTransaction {
+            transaction: UnverifiedTransaction {
+                unsigned: UnsignedTransaction::Legacy(LegacyTransaction {
+                    nonce: nonce.into(),
+                    gas_price: U256::one(),
+                    gas_limit: 21000.into(),
+                    action: TransactionAction::Create,
+                    value: U256::zero(),
+                    data: vec![],
+                }),
+                signature: None,
+                chain_id: 0,
+                hash: H256::random(),
+            },
+            sender: H160::random(),
+            public: None,
+        }
+    }
+
+    // a block including the `txs`, along with the receipts of them
+    fn mock_block(
+        number: u64,
+        timestamp: u64,
+        txs: &[SignedTransaction],
+    ) -> (Block, Vec<Receipt>) {
+        let mut block = Block::mock(0, number, Default::default(), timestamp);
+        block.tx_hashes = txs.iter().map(|tx| tx.transaction.hash).collect();
+
+        let receipts = txs
+            .iter()
+            .enumerate()
+            .map(|(idx, tx)| Receipt {
+                tx_hash: tx.transaction.hash,
+                block_number: number,
+                block_hash: block.hash(),
+                tx_index: idx as u32,
+                ..Default::default()
+            })
+            .collect();
+
+        (block, receipts)
+    }
+
+    // drop all the in-memory states, like a restart after a crash
+    fn restart(storage: &FunStorage) -> FunStorage {
+        let bytes = bcs::to_bytes(storage).unwrap();
+        FunStorage::from_bytes(&bytes, STORAGE_LAYOUT_VERSION).unwrap()
+    }
+
+    #[test]
+    fn test_persist_block_interrupted() {
+        let storage = FunStorage::default();
+
+        let (b0, _) = mock_block(0, 0, &[]);
+        storage.persist_block(b0, vec![], vec![]).unwrap();
+        let txs1 = vec![mock_tx(0), mock_tx(1)];
+        let (b1, r1) = mock_block(1, 1, &txs1);
+        storage.persist_block(b1.clone(), txs1, r1).unwrap();
+
+        // crashed before the header of block 2 is written
+        let txs2 = vec![mock_tx(2)];
+        let h2 = txs2[0].transaction.hash;
+        let (b2, r2) = mock_block(2, 2, &txs2);
+        storage.insert_txs(2, txs2.clone()).unwrap();
+        storage.insert_receipts(2, r2.clone()).unwrap();
+        let mut db = storage.db.shadow();
+        db.block_numbers.insert(&b2.hash(), &2);
+        db.blocks.insert(&2, &b2);
+
+        let storage = restart(&storage);
+        assert!(storage.get_block(2).unwrap().is_none());
+        assert!(storage.get_block_by_hash(&b2.hash()).unwrap().is_none());
+        assert!(storage.get_fatblock(2).unwrap().is_none());
+        assert!(storage.get_block_receipts(2).unwrap().is_none());
+        assert!(storage.get_tx_by_hash(&h2).unwrap().is_none());
+        assert!(storage.get_tx_position(&h2).unwrap().is_none());
+        assert!(storage.get_receipt_by_hash(&h2).unwrap().is_none());
+        assert_eq!(vec![None], storage.get_txs(2, &[h2]).unwrap());
+        assert_eq!(vec![None], storage.get_receipts(2, &[h2]).unwrap());
+        assert_eq!(b1, storage.get_latest_block().unwrap());
+
+        // produced again
+        storage.persist_block(b2.clone(), txs2, r2).unwrap();
+        assert_eq!(
+            Some(b2.clone()),
+            storage.get_block_by_hash(&b2.hash()).unwrap()
+        );
+        assert_eq!(Some((2, 0)), storage.get_tx_position(&h2).unwrap());
+
+        // crashed in the middle of replacing block 2 by a reorg
+        let txs2b = vec![mock_tx(3)];
+        let (b2b, r2b) = mock_block(2, 3, &txs2b);
+        storage.uncommit(2);
+        storage.insert_txs(2, txs2b.clone()).unwrap();
+        storage.insert_receipts(2, r2b).unwrap();
+        storage.db.shadow().blocks.insert(&2, &b2b);
+
+        let storage = restart(&storage);
+        assert!(storage.get_block(2).unwrap().is_none());
+        assert!(storage.get_block_by_hash(&b2.hash()).unwrap().is_none());
+        assert!(storage.get_block_by_hash(&b2b.hash()).unwrap().is_none());
+        assert!(storage.get_tx_by_hash(&h2).unwrap().is_none());
+        assert!(storage
+            .get_tx_by_hash(&txs2b[0].transaction.hash)
+            .unwrap()
+            .is_none());
+        assert_eq!(b1, storage.get_latest_block().unwrap());
+
+        // a block whose header has not been written over the old one
+        let (b1b, _) = mock_block(1, 4, &[]);
+        storage.db.shadow().blocks.insert(&1, &b1b);
+        let storage = restart(&storage);
+        assert!(storage.get_block(1).unwrap().is_none());
+        assert_eq!(Some(b1.hash()), storage.get_block_hash(1).unwrap());
+    }
+
+    #[test]
+    fn test_reorg_replaced_txs() {
+        let storage = FunStorage::default();
+        let hash = |tx: &SignedTransaction| tx.transaction.hash;
+
+        let (b0, _) = mock_block(0, 0, &[]);
+        storage.persist_block(b0, vec![], vec![]).unwrap();
+
+        let (kept, dropped, moved) = (mock_tx(0), mock_tx(1), mock_tx(2));
+        let txs = vec![kept.clone(), dropped.clone(), moved.clone()];
+        let (old, receipts) = mock_block(1, 1, &txs);
+        storage.persist_block(old.clone(), txs, receipts).unwrap();
+        let mut events = storage.subscribe_block_events();
+
+        // `moved` is at another index of the new block, `dropped` is not included
+        let txs = vec![moved.clone(), kept.clone()];
+        let (new, receipts) = mock_block(1, 2, &txs);
+        storage.insert_txs(1, txs).unwrap();
+        storage.insert_receipts(1, receipts).unwrap();
+        storage.set_block(new.clone()).unwrap();
+        assert_eq!(
+            BlockEvent::Reorg {
+                removed: old.hash(),
+                header: new.header.clone()
+            },
+            events.try_recv().unwrap()
+        );
+
+        let check = |storage: &FunStorage| {
+            assert!(storage.get_block_by_hash(&old.hash()).unwrap().is_none());
+            assert_eq!(Some(new.clone()), storage.get_block(1).unwrap());
+
+            assert_eq!(
+                Some((1, 0)),
+                storage.get_tx_position(&hash(&moved)).unwrap()
+            );
+            assert_eq!(Some((1, 1)), storage.get_tx_position(&hash(&kept)).unwrap());
+            let receipt = storage.get_receipt_by_hash(&hash(&kept)).unwrap().unwrap();
+            assert_eq!(new.hash(), receipt.block_hash);
+            assert_eq!(2, storage.get_block_receipts(1).unwrap().unwrap().len());
+
+            assert!(storage.get_tx_by_hash(&hash(&dropped)).unwrap().is_none());
+            assert!(storage.get_tx_position(&hash(&dropped)).unwrap().is_none());
+            assert!(storage
+                .get_receipt_by_hash(&hash(&dropped))
+                .unwrap()
+                .is_none());
+        };
+        check(&storage);
+        let storage = restart(&storage);
+        check(&storage);
+
+        // replaced again by `persist_block`, `dropped` is included this time
+        let txs = vec![dropped.clone()];
+        let (newer, receipts) = mock_block(1, 3, &txs);
+        storage.persist_block(newer.clone(), txs, receipts).unwrap();
+        assert_eq!(Some(newer), storage.get_block(1).unwrap());
+        assert_eq!(
+            Some((1, 0)),
+            storage.get_tx_position(&hash(&dropped)).unwrap()
+        );
+        assert!(storage.get_tx_by_hash(&hash(&kept)).unwrap().is_none());
+        assert!(storage.get_tx_by_hash(&hash(&moved)).unwrap().is_none());
+        assert!(storage
+            .get_receipt_by_hash(&hash(&moved))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_batch_limit() {
+        let storage = FunStorage::default();
+        assert_eq!(DEFAULT_BATCH_LIMIT, storage.batch_limit());
+
+        let txs = (0..4).map(mock_tx).collect::<Vec<_>>();
+        let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+        let (b0, _) = mock_block(0, 0, &[]);
+        storage.persist_block(b0, vec![], vec![]).unwrap();
+        let (b1, r1) = mock_block(1, 1, &txs);
+        storage.persist_block(b1, txs, r1).unwrap();
+
+        storage.set_batch_limit(3);
+        assert_eq!(3, storage.batch_limit());
+
+        // just below and at the limit
+        for n in [2, 3] {
+            let got = storage.get_txs(1, &hashes[..n]).unwrap();
+            assert_eq!(n, got.iter().flatten().count());
+            let got = storage.get_receipts(1, &hashes[..n]).unwrap();
+            assert_eq!(n, got.iter().flatten().count());
+        }
+
+        // just above the limit
+        assert!(storage.get_txs(1, &hashes).is_err());
+        assert!(storage.get_receipts(1, &hashes).is_err());
+
+        // back to a larger one
+        storage.set_batch_limit(4);
+        assert_eq!(4, storage.get_txs(1, &hashes).unwrap().len());
+
+        // not persisted
+        assert_eq!(DEFAULT_BATCH_LIMIT, restart(&storage).batch_limit());
+    }
+
+    #[test]
+    fn test_header_cache_agrees_with_db() {
+        let storage = FunStorage::default();
+        let blocks = (0..1000)
+            .map(|n| mock_block(n, n, &[]).0)
+            .collect::<Vec<_>>();
+        for b in blocks.iter() {
+            storage.persist_block(b.clone(), vec![], vec![]).unwrap();
+        }
+
+        // the uncached path
+        let uncached = |storage: &FunStorage, n: u64| storage.db.headers.get(&n);
+
+        // cold caches, the parents are looked up one by one like a verification
+        let storage = restart(&storage);
+        for n in 1..1000u64 {
+            assert!(storage.cache.headers.get(&(n - 1)).is_none());
+            let parent = storage.get_block_header(n - 1).unwrap();
+            assert_eq!(uncached(&storage, n - 1), parent);
+            assert_eq!(Some(blocks[n as usize - 1].header.clone()), parent);
+
+            // filled on the miss
+            assert_eq!(parent, storage.cache.headers.get(&(n - 1)));
+            assert_eq!(parent, storage.get_block_header(n - 1).unwrap());
+        }
+
+        // a replaced block is not served from the stale cache
+        let (b999, _) = mock_block(999, 1000, &[]);
+        assert_eq!(
+            Some(blocks[999].header.clone()),
+            storage.get_block_header(999).unwrap()
+        );
+        storage.persist_block(b999.clone(), vec![], vec![]).unwrap();
+        assert_eq!(Some(b999.header), storage.get_block_header(999).unwrap());
+        assert_eq!(
+            uncached(&storage, 999),
+            storage.get_block_header(999).unwrap()
+        );
+
+        assert!(storage.get_block_header(1000).unwrap().is_none());
+        assert!(storage.cache.headers.get(&1000).is_none());
+    }
+
+    #[test]
+    fn test_block_receipts() {
+        let storage = FunStorage::default();
+
+        let (b0, _) = mock_block(0, 0, &[]);
+        storage.persist_block(b0, vec![], vec![]).unwrap();
+        let txs = (0..3).map(mock_tx).collect::<Vec<_>>();
+        let (b1, r1) = mock_block(1, 1, &txs);
+        storage.persist_block(b1, txs.clone(), r1.clone()).unwrap();
+
+        // in the order of the txs, from the db too
+        for storage in [&storage, &restart(&storage)] {
+            let receipts = storage.get_block_receipts(1).unwrap().unwrap();
+            assert_eq!(r1, receipts);
+            assert_eq!(
+                txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>(),
+                receipts.iter().map(|r| r.tx_hash).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                vec![0, 1, 2],
+                receipts.iter().map(|r| r.tx_index).collect::<Vec<_>>()
+            );
+
+            // a block without txs
+            assert_eq!(Some(vec![]), storage.get_block_receipts(0).unwrap());
+
+            // a nonexistent block
+            assert!(storage.get_block_receipts(2).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_release_storage_trie() {
+        let storage = FunStorage::default();
+        let trie_db = MptStore::new();
+        let (contract, other) = (H160::repeat_byte(0xcc), H160::repeat_byte(0xdd));
+
+        let mut trie = trie_db.trie_create(contract.as_bytes(), false).unwrap();
+        for i in 1..=3u64 {
+            let slot = H256::from_low_u64_be(i);
+            trie.insert(slot.as_bytes(), H256::repeat_byte(7).as_bytes())
+                .unwrap();
+
+            // indexed only once
+            storage.index_storage_slot(contract, slot).unwrap();
+            storage.index_storage_slot(contract, slot).unwrap();
+        }
+        let root: MerkleRoot = trie.commit().into();
+
+        // written after the `root`
+        let slot = H256::from_low_u64_be(4);
+        storage.index_storage_slot(contract, slot).unwrap();
+        storage.index_storage_slot(other, slot).unwrap();
+
+        assert_eq!(0, storage.released_storage_trie_cnt());
+        assert_eq!(
+            3,
+            storage
+                .release_storage_trie(&trie_db, contract, root)
+                .unwrap()
+        );
+        assert_eq!(1, storage.released_storage_trie_cnt());
+
+        // the states referring to the `root` can still read it
+        let trie = trie_restore(&trie_db, contract.as_bytes(), root).unwrap();
+        for i in 1..=3u64 {
+            let slot = H256::from_low_u64_be(i);
+            assert_eq!(
+                Some(H256::repeat_byte(7).as_bytes().to_vec()),
+                trie.get(slot.as_bytes()).unwrap()
+            );
+        }
+
+        // the slots are recorded per address
+        let cnt = |address| storage.db.slot_counts.get(&Hasher::digest(address));
+        assert_eq!(Some(4), cnt(contract));
+        assert_eq!(Some(1), cnt(other));
+    }
+}
@@ -2,6 +2,11 @@ pub use ophelia::{
     BlsSignatureVerify, Crypto, Error, HashValue, PrivateKey, PublicKey, Signature,
     ToBlsPublicKey, ToPublicKey, UncompressedPublicKey,
 };
+// NOTE:
+// `secp256k1_recover` works on a process-wide secp256k1 context
+// which is initialized lazily within `ophelia_secp256k1`,
+// so no context will be allocated per recovery,
+// wrapping another cached context here gains nothing.
 pub use ophelia_secp256k1::{
     recover as secp256k1_recover, Secp256k1, Secp256k1PrivateKey, Secp256k1PublicKey,
     Secp256k1Recoverable, Secp256k1RecoverablePrivateKey, Secp256k1RecoverablePublicKey,
@@ -1,5 +1,5 @@
 use rt_evm::{
-    api::{set_node_sync_status, SyncStatus},
+    api::{set_node_sync_status, stop_jsonrpc_server, SyncStatus, TlsConfig},
     Address, EvmRuntime, TokenDistributon,
 };
 use ruc::*;
@@ -17,6 +17,9 @@ pub struct Config {
     // websocket rpc server
     ws_listening_address: Option<String>,
 
+    // serve `https://` and `wss://` if set
+    tls: Option<TlsConfig>,
+
     // storage path for the vsdb crate
     vsdb_base_dir: Option<String>,
 
@@ -44,17 +47,26 @@ impl Config {
         )
         .c(d!())?;
 
-        // will NOT block current thread
-        rt.spawn_jsonrpc_server(
-            self.client_version.as_str(),
-            self.http_listening_address.as_deref(),
-            self.ws_listening_address.as_deref(),
-        )
-        .await
-        .c(d!())?;
+        // will NOT block current thread,
+        // keep the handlers alive, or the servers will be stopped
+        let handlers = rt
+            .spawn_jsonrpc_server(
+                self.client_version.as_str(),
+                self.http_listening_address.as_deref(),
+                self.ws_listening_address.as_deref(),
+                self.tls.as_ref(),
+                &Default::default(),
+            )
+            .await
+            .c(d!())?;
 
         // the inner loop will block current thread !
-        self.start_consensus_engine(&rt).await.c(d!())
+        let ret = self.start_consensus_engine(&rt).await.c(d!());
+
+        // drain the in-flight requests before exiting
+        let stopped = stop_jsonrpc_server(handlers).await.c(d!());
+
+        ret.and(stopped)
     }
 
     // a fake consensus demo
@@ -78,7 +90,7 @@ impl Config {
             // take at most 1000 transactions to propose a new block
             let txs = evm_rt.mempool_handler().tx_take_propose(1000);
 
-            let header = producer.produce_block(txs.clone()).c(d!())?;
+            let header = producer.produce_block(txs.clone(), None).c(d!())?;
             dbg!(&header);
         }
     }
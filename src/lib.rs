@@ -15,48 +15,64 @@ pub use model::types::{
     transaction::SignedTransaction as SignedTx, Account, H160 as Address,
 };
 
-use api::{run_jsonrpc_server, DefaultAPIAdapter as API};
+use api::{
+    run_jsonrpc_server, DefaultAPIAdapter as API, RpcConfig, ServerHandlers, TlsConfig,
+};
 use blockmgmt::BlockMgmt;
-use executor::RTEvmExecutorAdapter;
+use executor::{PrecompileCache, RTEvmExecutorAdapter};
+use futures::stream::{self, BoxStream, StreamExt};
 use mempool::Mempool;
 use model::{
     traits::BlockStorage as _,
-    types::{Basic, Block, H160, U256},
+    types::{
+        Basic, Block, BlockEvent, Bytes, CheckPointConfig, Hardfork, Hash, H160, U256,
+    },
 };
-use once_cell::sync::Lazy;
 use rt_evm_model::lazy::set_chain_id;
 use ruc::*;
-use std::{fs, io::ErrorKind, mem::size_of, path::PathBuf, sync::Arc};
+use std::{
+    fs,
+    io::ErrorKind,
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use storage::{get_account_by_backend, save_account_by_backend, MptStore, Storage};
+use tokio::sync::broadcast::error::RecvError;
 
-static META_PATH: Lazy<MetaPath> = Lazy::new(|| {
-    let mut trie_db = vsdb::vsdb_get_custom_dir().to_path_buf();
-    let mut storage = trie_db.clone();
-    let mut chain_id = trie_db.clone();
-
-    chain_id.push("EVM_RUNTIME_chain_id.meta");
-    trie_db.push("EVM_RUNTIME_trie.meta");
-    storage.push("EVM_RUNTIME_storage.meta");
-
-    MetaPath {
-        chain_id,
-        trie_db,
-        storage,
-    }
-});
+/// The timestamp of the genesis block if not specified.
+pub const DEFAULT_GENESIS_TIMESTAMP: u64 = 0;
 
 pub struct EvmRuntime {
     chain_id: u64,
 
+    // where the meta data of this instance are stored
+    meta_path: MetaPath,
+
     // create a new instance every time
     mempool: Arc<Mempool>,
 
     trie_db: Arc<MptStore>,
     storage: Arc<Storage>,
+
+    // the `extraData` of the blocks produced by this instance
+    extra_data: Bytes,
+
+    // the rules followed by the blocks produced by this instance
+    hardfork: Hardfork,
+
+    // the activation heights followed by the blocks produced by this instance
+    checkpoint: CheckPointConfig,
+
+    // see `set_strict_block_hash`
+    strict_block_hash: bool,
+
+    // see `set_precompile_cache`
+    precompile_cache: Option<Arc<PrecompileCache>>,
 }
 
 impl EvmRuntime {
-    fn new(chain_id: u64, t: MptStore, s: Storage) -> Self {
+    fn new(chain_id: u64, meta_path: MetaPath, t: MptStore, s: Storage) -> Self {
         #[cfg(not(feature = "benchmark"))]
         const MEM_POOL_CAP: u64 = 20_0000;
 
@@ -70,6 +86,7 @@ impl EvmRuntime {
 
         Self {
             chain_id,
+            meta_path,
             mempool: Mempool::new(
                 MEM_POOL_CAP,
                 600,
@@ -79,6 +96,11 @@ impl EvmRuntime {
             ),
             trie_db,
             storage,
+            extra_data: Bytes::new(),
+            hardfork: Hardfork::default(),
+            checkpoint: CheckPointConfig::default(),
+            strict_block_hash: false,
+            precompile_cache: None,
         }
     }
 
@@ -86,7 +108,63 @@ impl EvmRuntime {
         chain_id: u64,
         token_distributions: &[TokenDistributon],
     ) -> Result<Self> {
-        let r = Self::new(chain_id, MptStore::new(), Storage::default());
+        Self::create_in(&default_meta_dir(), chain_id, token_distributions).c(d!())
+    }
+
+    /// Same as `create`, but store the meta data in the `meta_dir`,
+    /// so multiple instances can live in one process without interfering.
+    pub fn create_in(
+        meta_dir: &Path,
+        chain_id: u64,
+        token_distributions: &[TokenDistributon],
+    ) -> Result<Self> {
+        Self::create_in_with_genesis_timestamp(
+            meta_dir,
+            chain_id,
+            DEFAULT_GENESIS_TIMESTAMP,
+            token_distributions,
+        )
+        .c(d!())
+    }
+
+    /// Same as `create_in`, but with an explicit timestamp of the genesis block,
+    /// all the nodes of a network must use the same one,
+    /// or their genesis blocks will be different.
+    pub fn create_in_with_genesis_timestamp(
+        meta_dir: &Path,
+        chain_id: u64,
+        genesis_timestamp: u64,
+        token_distributions: &[TokenDistributon],
+    ) -> Result<Self> {
+        Self::create_in_with_checkpoint(
+            meta_dir,
+            chain_id,
+            genesis_timestamp,
+            CheckPointConfig::default(),
+            token_distributions,
+        )
+        .c(d!())
+    }
+
+    /// Same as `create_in_with_genesis_timestamp`, but with the activation heights
+    /// followed by the genesis block and the blocks produced afterwards,
+    /// see `set_checkpoint_config`.
+    pub fn create_in_with_checkpoint(
+        meta_dir: &Path,
+        chain_id: u64,
+        genesis_timestamp: u64,
+        checkpoint: CheckPointConfig,
+        token_distributions: &[TokenDistributon],
+    ) -> Result<Self> {
+        fs::create_dir_all(meta_dir).c(d!())?;
+
+        let mut r = Self::new(
+            chain_id,
+            MetaPath::new(meta_dir),
+            MptStore::new(),
+            Storage::default(),
+        );
+        r.checkpoint = checkpoint;
 
         {
             let mut exector_adapter =
@@ -110,43 +188,75 @@ impl EvmRuntime {
             // Set up the genesis block.
             //
             // NOTE:
-            // we should set the timestamp to a static value,
+            // the timestamp is a static value,
             // do NOT use the local time of the native machine,
             // or various consensus issues will occur.
             r.storage
-                .set_block(Block::mock(chain_id, 0, exector_adapter.commit(), 0))
+                .set_block(Block::genesis(
+                    chain_id,
+                    exector_adapter.commit(),
+                    genesis_timestamp,
+                    &r.checkpoint,
+                ))
                 .c(d!())?;
         }
 
         // Only need to write once time !
-        fs::write(META_PATH.chain_id.as_path(), u64::to_be_bytes(r.chain_id)).c(d!())?;
+        r.write_meta().c(d!())?;
 
-        // Only need to write once time !
-        bcs::to_bytes(&*r.trie_db)
-            .c(d!())
-            .and_then(|bytes| fs::write(META_PATH.trie_db.as_path(), bytes).c(d!()))?;
+        Ok(r)
+    }
 
-        // Only need to write once time !
-        bcs::to_bytes(&*r.storage)
-            .c(d!())
-            .and_then(|bytes| fs::write(META_PATH.storage.as_path(), bytes).c(d!()))?;
+    fn write_meta(&self) -> Result<()> {
+        fs::write(
+            self.meta_path.chain_id.as_path(),
+            encode_meta(&u64::to_be_bytes(self.chain_id)),
+        )
+        .c(d!())?;
 
-        Ok(r)
+        // Both of them are serialized as the handles of their vsdb instances,
+        // no data, and no in-memory cache or `HashMap`, so the bytes are
+        // deterministic, identical for the same instances.
+        bcs::to_bytes(&*self.trie_db).c(d!()).and_then(|bytes| {
+            fs::write(self.meta_path.trie_db.as_path(), encode_meta(&bytes)).c(d!())
+        })?;
+
+        bcs::to_bytes(&*self.storage).c(d!()).and_then(|bytes| {
+            fs::write(self.meta_path.storage.as_path(), encode_meta(&bytes)).c(d!())
+        })
     }
 
     pub fn restore() -> Result<Option<Self>> {
-        let chain_id = fs::read(META_PATH.chain_id.as_path());
-        let trie_db = fs::read(META_PATH.trie_db.as_path());
-        let storage = fs::read(META_PATH.storage.as_path());
+        Self::restore_from(&default_meta_dir()).c(d!())
+    }
+
+    /// Same as `restore`, but read the meta data from the `meta_dir`.
+    pub fn restore_from(meta_dir: &Path) -> Result<Option<Self>> {
+        let meta_path = MetaPath::new(meta_dir);
+
+        let chain_id = fs::read(meta_path.chain_id.as_path());
+        let trie_db = fs::read(meta_path.trie_db.as_path());
+        let storage = fs::read(meta_path.storage.as_path());
 
         match (chain_id, trie_db, storage) {
             (Ok(chain_id), Ok(trie_db), Ok(storage)) => {
-                let chain_id = <[u8; size_of::<u64>()]>::try_from(chain_id)
-                    .map_err(|_| eg!("invalid length"))
-                    .map(u64::from_be_bytes)?;
+                let (chain_id, _) = decode_meta(chain_id).c(d!())?;
+                let (trie_db, _) = decode_meta(trie_db).c(d!())?;
+                let (storage, version) = decode_meta(storage).c(d!())?;
+
+                // only the layout of the storage changes between the versions
+                let chain_id = decode_chain_id(chain_id).c(d!())?;
                 let trie_db = bcs::from_bytes::<MptStore>(&trie_db).c(d!())?;
-                let storage = bcs::from_bytes::<Storage>(&storage).c(d!())?;
-                Ok(Some(Self::new(chain_id, trie_db, storage)))
+                let storage = Storage::from_bytes(&storage, version).c(d!())?;
+
+                let r = Self::new(chain_id, meta_path, trie_db, storage);
+
+                // migrate the older files to the current version
+                if version < META_VERSION {
+                    r.write_meta().c(d!())?;
+                }
+
+                Ok(Some(r))
             }
             (Err(a), Err(b), Err(c)) => match (a.kind(), b.kind(), c.kind()) {
                 (ErrorKind::NotFound, ErrorKind::NotFound, ErrorKind::NotFound) => {
@@ -167,10 +277,19 @@ impl EvmRuntime {
         chain_id: u64,
         token_distributions: &[TokenDistributon],
     ) -> Result<Self> {
-        if let Some(rt) = Self::restore().c(d!())? {
+        Self::restore_or_create_in(&default_meta_dir(), chain_id, token_distributions)
+            .c(d!())
+    }
+
+    pub fn restore_or_create_in(
+        meta_dir: &Path,
+        chain_id: u64,
+        token_distributions: &[TokenDistributon],
+    ) -> Result<Self> {
+        if let Some(rt) = Self::restore_from(meta_dir).c(d!())? {
             Ok(rt)
         } else {
-            Self::create(chain_id, token_distributions).c(d!())
+            Self::create_in(meta_dir, chain_id, token_distributions).c(d!())
         }
     }
 
@@ -178,6 +297,10 @@ impl EvmRuntime {
         self.chain_id
     }
 
+    pub fn meta_dir(&self) -> &Path {
+        &self.meta_path.base_dir
+    }
+
     pub fn mempool_handler(&self) -> &Mempool {
         &self.mempool
     }
@@ -190,6 +313,18 @@ impl EvmRuntime {
         &self.storage
     }
 
+    /// Make all the written data of both the trie and the blocks durable,
+    /// e.g. call it after a block has been committed and before acknowledging it,
+    /// without this, the latest writes may be lost if the process crashes.
+    ///
+    /// NOTE:
+    /// - this is a blocking operation, it returns after the data are on disk
+    /// - vsdb is a global instance, so all the instances of the process are covered
+    /// - the meta data have been written synchronously when the instance was created
+    pub fn flush(&self) {
+        vsdb::vsdb_flush();
+    }
+
     pub fn copy_mempool_handler(&self) -> Arc<Mempool> {
         Arc::clone(&self.mempool)
     }
@@ -207,14 +342,73 @@ impl EvmRuntime {
         proposer: H160,
         timestamp: u64,
     ) -> Result<BlockMgmt> {
-        BlockMgmt::new(
+        let mut producer = BlockMgmt::new(
             proposer,
             timestamp,
             self.copy_mempool_handler(),
             self.copy_trie_handler(),
             self.copy_storage_handler(),
         )
-        .c(d!())
+        .c(d!())?;
+        producer.extra_data = self.extra_data.clone();
+        producer.hardfork = self.hardfork;
+        producer.checkpoint = self.checkpoint;
+        producer.strict_block_hash = self.strict_block_hash;
+        producer.precompile_cache = self.precompile_cache.clone();
+        Ok(producer)
+    }
+
+    /// Set the rules followed by the blocks produced afterwards,
+    /// and by the JSON-RPC servers spawned afterwards, `London` by default.
+    ///
+    /// NOTE: it is not persisted, set it again after `restore`.
+    pub fn set_hardfork(&mut self, hardfork: Hardfork) {
+        self.hardfork = hardfork;
+    }
+
+    /// Set the activation heights followed by the blocks produced afterwards,
+    /// and by the JSON-RPC servers spawned afterwards, none of them by default.
+    ///
+    /// NOTE: it is not persisted, set it again after `restore`,
+    /// and the genesis block follows the one passed to `create_in_with_checkpoint`.
+    pub fn set_checkpoint_config(&mut self, checkpoint: CheckPointConfig) {
+        self.checkpoint = checkpoint;
+    }
+
+    /// Fail the blocks produced afterwards instead of returning zero to `BLOCKHASH`
+    /// when a block within the recent 256 ones can not be found, disabled by default.
+    ///
+    /// NOTE:
+    /// a node missing the recent blocks, e.g. a pruned one, gets a zero hash,
+    /// and its state may diverge from the other nodes,
+    /// each miss is logged and counted by `executor::missing_block_hash_cnt`.
+    pub fn set_strict_block_hash(&mut self, enabled: bool) {
+        self.strict_block_hash = enabled;
+    }
+
+    /// Memoize the results of the ecRecover and sha256 precompiles by their inputs
+    /// in the blocks produced afterwards, disabled by default,
+    /// the results and the gas are exactly the same as without the cache.
+    ///
+    /// The cache belongs to this runtime, it is shared by its block producers only.
+    pub fn set_precompile_cache(&mut self, enabled: bool) {
+        self.precompile_cache = enabled.then(|| Arc::new(PrecompileCache::default()));
+    }
+
+    /// Set the max number of the txs or receipts queried in one batch,
+    /// e.g. the receipts of a block queried through the JSON-RPC, `1000` by default.
+    ///
+    /// NOTE: it is not persisted, set it again after `restore`.
+    pub fn set_batch_limit(&self, limit: usize) {
+        self.storage.set_batch_limit(limit);
+    }
+
+    /// Set the `extraData` of the blocks produced afterwards,
+    /// at most 32 bytes, like the client identifier of geth.
+    pub fn set_extra_data(&mut self, extra_data: Bytes) -> Result<()> {
+        blockmgmt::check_extra_data(&extra_data).c(d!())?;
+        self.extra_data = extra_data;
+        Ok(())
     }
 
     // Check transactions received from other nodes?
@@ -227,36 +421,40 @@ impl EvmRuntime {
         self.mempool.tx_insert(tx, false)
     }
 
+    /// The servers run in background tasks,
+    /// the returned handlers must be kept alive as long as the servers are needed,
+    /// pass them to `api::stop_jsonrpc_server` to shut the servers down gracefully.
+    ///
+    /// Serve `https://` and `wss://` if `tls` is set, plaintext otherwise.
+    ///
+    /// The limits and the switches, e.g. the admin APIs, are set by `config`,
+    /// per server, `RpcConfig::default()` fits most of the public nodes.
     pub async fn spawn_jsonrpc_server(
         &self,
         client_version: &str,
         http_listening_address: Option<&str>,
         ws_listening_address: Option<&str>,
-    ) -> Result<()> {
+        tls: Option<&TlsConfig>,
+        config: &RpcConfig,
+    ) -> Result<ServerHandlers> {
         let api = Arc::new(API::new(
             self.copy_mempool_handler(),
             self.copy_trie_handler(),
             self.copy_storage_handler(),
+            self.hardfork,
+            self.checkpoint,
         ));
 
-        let (http_hdr, ws_hdr) = run_jsonrpc_server(
+        run_jsonrpc_server(
             api,
             client_version,
             http_listening_address,
             ws_listening_address,
+            tls,
+            config,
         )
         .await
-        .c(d!())?;
-
-        if let Some(hdr) = http_hdr {
-            tokio::spawn(hdr);
-        }
-
-        if let Some(hdr) = ws_hdr {
-            tokio::spawn(hdr);
-        }
-
-        Ok(())
+        .c(d!())
     }
 
     /// Useful when other modules need to check the account balance
@@ -268,14 +466,136 @@ impl EvmRuntime {
     pub fn save_account(&self, address: Address, account: &Account) -> Result<()> {
         save_account_by_backend(&self.trie_db, &self.storage, address, account).c(d!())
     }
+
+    /// Follow the chain from the `start_height`,
+    /// useful for the indexers embedded in the same process.
+    ///
+    /// The blocks are yielded one by one in the order of height,
+    /// the stream waits on the block events of the storage for the new ones,
+    /// see `Storage::subscribe_block_events`.
+    pub fn block_stream(&self, start_height: u64) -> BlockStream {
+        // subscribe before reading, so no block is missed in between
+        let events = self.storage.subscribe_block_events();
+        let state = (self.copy_storage_handler(), events, start_height);
+
+        stream::unfold(state, |(storage, mut events, height)| async move {
+            loop {
+                match storage.get_block(height).c(d!()) {
+                    Ok(Some(b)) => {
+                        let item = BlockStreamItem::Block(b);
+                        return Some((Ok(item), (storage, events, height + 1)));
+                    }
+                    Ok(None) => {}
+                    Err(e) => return Some((Err(e), (storage, events, height))),
+                }
+
+                match events.recv().await {
+                    // a yielded block has been replaced, yield it again
+                    Ok(BlockEvent::Reorg { removed, header })
+                        if header.number < height =>
+                    {
+                        let number = header.number;
+                        let item = BlockStreamItem::Reorg { removed, number };
+                        return Some((Ok(item), (storage, events, number)));
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(n)) => {
+                        let item = BlockStreamItem::Lagged(n);
+                        return Some((Ok(item), (storage, events, height)));
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+/// See `EvmRuntime::block_stream`.
+pub type BlockStream = BoxStream<'static, Result<BlockStreamItem>>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockStreamItem {
+    /// The next block in the order of height.
+    Block(Block),
+
+    /// The block `removed` at the height `number` has been replaced by a reorg,
+    /// the blocks are yielded again from the `number`.
+    Reorg { removed: Hash, number: u64 },
+
+    /// The consumer has fallen too far behind, `n` block events were dropped,
+    /// the blocks are still yielded from the storage without a gap,
+    /// but the reorgs in the dropped events are not reported,
+    /// check the hashes of the yielded blocks again if that matters.
+    Lagged(u64),
 }
 
 struct MetaPath {
+    base_dir: PathBuf,
     chain_id: PathBuf,
     trie_db: PathBuf,
     storage: PathBuf,
 }
 
+impl MetaPath {
+    fn new(base_dir: &Path) -> Self {
+        let base_dir = base_dir.to_path_buf();
+        let mut trie_db = base_dir.clone();
+        let mut storage = base_dir.clone();
+        let mut chain_id = base_dir.clone();
+
+        chain_id.push("EVM_RUNTIME_chain_id.meta");
+        trie_db.push("EVM_RUNTIME_trie.meta");
+        storage.push("EVM_RUNTIME_storage.meta");
+
+        MetaPath {
+            base_dir,
+            chain_id,
+            trie_db,
+            storage,
+        }
+    }
+}
+
+// The meta files are prefixed with `<magic><version>`,
+// the legacy ones written before the versioning have no prefix,
+// their version is regarded as `0`.
+//
+// The version follows the layout of the storage, the only one that changes.
+const META_MAGIC: &[u8; 6] = b"RT-EVM";
+const META_VERSION: u8 = storage::STORAGE_LAYOUT_VERSION;
+
+fn encode_meta(payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(META_MAGIC.len() + 1 + payload.len());
+    bytes.extend_from_slice(META_MAGIC);
+    bytes.push(META_VERSION);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+// return the payload and its version
+fn decode_meta(bytes: Vec<u8>) -> Result<(Vec<u8>, u8)> {
+    match bytes.strip_prefix(META_MAGIC) {
+        Some([v, payload @ ..]) if (1..=META_VERSION).contains(v) => {
+            Ok((payload.to_vec(), *v))
+        }
+        Some([v, ..]) => Err(eg!("unsupported meta version: {}", v)),
+        Some([]) => Err(eg!("truncated meta data")),
+        None => Ok((bytes, 0)),
+    }
+}
+
+fn decode_chain_id(bytes: Vec<u8>) -> Result<u64> {
+    <[u8; size_of::<u64>()]>::try_from(bytes)
+        .map_err(|_| eg!("invalid length"))
+        .map(u64::from_be_bytes)
+}
+
+// the directory of vsdb, used when no meta directory is specified
+fn default_meta_dir() -> PathBuf {
+    vsdb::vsdb_get_custom_dir().to_path_buf()
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct TokenDistributon {
     address: H160,
@@ -294,3 +614,1480 @@ impl TokenDistributon {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::types::{
+        Hash, Hasher, Header, LegacyTransaction, MerkleRoot, Receipt, TransactionAction,
+        UnsignedTransaction, UnverifiedTransaction, BASE_FEE_PER_GAS, H256,
+        WORLD_STATE_META_KEY,
+    };
+    use storage::{save_account_by_state, trie_restore};
+    use vsdb::{MapxOrd, MapxRaw};
+
+    // the layout of the storage before the meta files were versioned
+    #[derive(serde::Serialize)]
+    struct LegacyDB {
+        blocks: MapxOrd<u64, Block>,
+        block_numbers: MapxOrd<Hash, u64>,
+        headers: MapxOrd<u64, Header>,
+        transactions: MapxOrd<Hash, (u64, SignedTx)>,
+        codes: MapxRaw,
+        codes_addr_to_hash: MapxRaw,
+        receipts: MapxOrd<Hash, Receipt>,
+    }
+
+    fn temp_meta_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rt_evm_{}_{}", name, ts!()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_meta_format() {
+        let (payload, version) = decode_meta(encode_meta(&[1, 2, 3])).unwrap();
+        assert_eq!(payload, vec![1, 2, 3]);
+        assert_eq!(META_VERSION, version);
+
+        let (payload, version) = decode_meta(encode_meta(&[])).unwrap();
+        assert!(payload.is_empty());
+        assert_eq!(META_VERSION, version);
+
+        // the older versions are still readable
+        let mut bytes = encode_meta(&[1, 2, 3]);
+        bytes[META_MAGIC.len()] = 1;
+        assert_eq!((vec![1, 2, 3], 1), decode_meta(bytes.clone()).unwrap());
+
+        for v in [0, META_VERSION + 1] {
+            bytes[META_MAGIC.len()] = v;
+            assert!(decode_meta(bytes.clone()).is_err());
+        }
+        assert!(decode_meta(META_MAGIC.to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_chain_id_meta() {
+        let dir = std::env::temp_dir().join(format!("rt_evm_meta_{}", ts!()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = MetaPath::new(&dir).chain_id;
+
+        // the legacy raw format
+        fs::write(&path, u64::to_be_bytes(9527)).unwrap();
+        let (payload, version) = decode_meta(fs::read(&path).unwrap()).unwrap();
+        assert_eq!(0, version);
+        assert_eq!(decode_chain_id(payload).unwrap(), 9527);
+
+        // the versioned format
+        fs::write(&path, encode_meta(&u64::to_be_bytes(9527))).unwrap();
+        let (payload, version) = decode_meta(fs::read(&path).unwrap()).unwrap();
+        assert_eq!(META_VERSION, version);
+        assert_eq!(decode_chain_id(payload).unwrap(), 9527);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore() {
+        let dir = temp_meta_dir("restore");
+        assert!(EvmRuntime::restore_from(&dir).unwrap().is_none());
+
+        let alice = H160::repeat_byte(1);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, 100.into())],
+        )
+        .unwrap();
+        let genesis = rt.storage.get_latest_block().unwrap();
+        drop(rt);
+
+        let rt = EvmRuntime::restore_from(&dir).unwrap().unwrap();
+        assert_eq!(9527, rt.chain_id());
+        assert_eq!(
+            genesis.hash(),
+            rt.storage.get_latest_block().unwrap().hash()
+        );
+        assert_eq!(U256::from(100), rt.get_account(alice).unwrap().balance);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_legacy() {
+        let dir = temp_meta_dir("restore_legacy");
+        let meta_path = MetaPath::new(&dir);
+
+        // the genesis state
+        let alice = H160::repeat_byte(1);
+        let trie_db = MptStore::new();
+        let mut state = trie_db.trie_create(&WORLD_STATE_META_KEY, false).unwrap();
+        let account = Account {
+            nonce: U256::zero(),
+            balance: 100.into(),
+            storage_root: Default::default(),
+            code_hash: Default::default(),
+        };
+        save_account_by_state(&mut state, alice, &account).unwrap();
+        let state_root: MerkleRoot = state.commit().into();
+
+        let genesis = Block::genesis(9527, state_root, 0, &Default::default());
+        let mut db = LegacyDB {
+            blocks: MapxOrd::new(),
+            block_numbers: MapxOrd::new(),
+            headers: MapxOrd::new(),
+            transactions: MapxOrd::new(),
+            codes: MapxRaw::new(),
+            codes_addr_to_hash: MapxRaw::new(),
+            receipts: MapxOrd::new(),
+        };
+        db.blocks.insert(&0, &genesis);
+        db.block_numbers.insert(&genesis.hash(), &0);
+        db.headers.insert(&0, &genesis.header);
+
+        // the raw files without the versioned prefix
+        fs::write(&meta_path.chain_id, u64::to_be_bytes(9527)).unwrap();
+        fs::write(&meta_path.trie_db, bcs::to_bytes(&trie_db).unwrap()).unwrap();
+        fs::write(&meta_path.storage, bcs::to_bytes(&db).unwrap()).unwrap();
+
+        let rt = EvmRuntime::restore_from(&dir).unwrap().unwrap();
+        assert_eq!(9527, rt.chain_id());
+        assert_eq!(
+            genesis.hash(),
+            rt.storage.get_latest_block().unwrap().hash()
+        );
+        assert_eq!(account.balance, rt.get_account(alice).unwrap().balance);
+        drop(rt);
+
+        // migrated to the current version
+        for path in [&meta_path.chain_id, &meta_path.trie_db, &meta_path.storage] {
+            let (_, version) = decode_meta(fs::read(path).unwrap()).unwrap();
+            assert_eq!(META_VERSION, version);
+        }
+
+        let rt = EvmRuntime::restore_from(&dir).unwrap().unwrap();
+        assert_eq!(
+            genesis.hash(),
+            rt.storage.get_latest_block().unwrap().hash()
+        );
+        assert_eq!(account.balance, rt.get_account(alice).unwrap().balance);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_account_range() {
+        let dir = temp_meta_dir("account_range");
+
+        let accounts = (1..=250u64).map(H160::from_low_u64_be).collect::<Vec<_>>();
+        let distributions = accounts
+            .iter()
+            .map(|a| TokenDistributon::new(*a, 1.into()))
+            .collect::<Vec<_>>();
+        let rt = EvmRuntime::create_in(&dir, 9527, &distributions).unwrap();
+
+        let root = rt.storage.get_latest_block_header().unwrap().state_root;
+        let state = trie_restore(&rt.trie_db, &WORLD_STATE_META_KEY, root).unwrap();
+
+        // in pages of 100
+        let mut visited = vec![];
+        let mut start = Some(H256::zero());
+        while let Some(s) = start {
+            let (page, next) = rt.storage.get_account_range(&state, s, 100).unwrap();
+            assert!(page.len() <= 100);
+            assert!(page.windows(2).all(|p| p[0].0 < p[1].0));
+            visited.extend(page.into_iter().map(|(_, address, _)| address));
+            start = next;
+        }
+
+        // each of them exactly once
+        visited.sort();
+        assert_eq!(accounts, visited);
+
+        // the accounts written by the simulations are not indexed
+        let ghost = H160::repeat_byte(0xff);
+        let mut backend = RTEvmExecutorAdapter::from_root(
+            root,
+            &rt.trie_db,
+            &rt.storage,
+            Default::default(),
+        )
+        .unwrap()
+        .simulated();
+        let basic = Basic {
+            balance: 1.into(),
+            nonce: U256::zero(),
+        };
+        backend.apply(ghost, basic, None, vec![], false);
+        let root = backend.commit();
+
+        let state = trie_restore(&rt.trie_db, &WORLD_STATE_META_KEY, root).unwrap();
+        assert!(state.get(ghost.as_bytes()).unwrap().is_some());
+        let (page, next) = rt
+            .storage
+            .get_account_range(&state, H256::zero(), 1000)
+            .unwrap();
+        assert!(next.is_none());
+        assert_eq!(accounts.len(), page.len());
+        assert!(page.iter().all(|(_, address, _)| address != &ghost));
+
+        // the walk is bounded even if none of the recorded accounts exists in the state
+        let empty = rt
+            .trie_db
+            .trie_create(&WORLD_STATE_META_KEY, false)
+            .unwrap();
+        let mut pages = 0;
+        let mut start = Some(H256::zero());
+        while let Some(s) = start {
+            let (page, next) = rt.storage.get_account_range(&empty, s, 10).unwrap();
+            assert!(page.is_empty());
+            start = next;
+            pages += 1;
+        }
+        assert_eq!(4, pages);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // the signature is regarded as checked, so it is not needed
+    fn mock_tx(
+        sender: H160,
+        nonce: u64,
+        action: TransactionAction,
+        data: Bytes,
+    ) -> SignedTx {
+        let mut utx = UnverifiedTransaction {
+            unsigned: UnsignedTransaction::Legacy(LegacyTransaction {
+                nonce: nonce.into(),
+                gas_price: BASE_FEE_PER_GAS.into(),
+                gas_limit: 100_000.into(),
+                action,
+                value: U256::zero(),
+                data,
+            }),
+            signature: None,
+            chain_id: 9527,
+            hash: Default::default(),
+        };
+        utx.hash = utx.get_hash();
+        SignedTx {
+            transaction: utx,
+            sender,
+            public: None,
+        }
+    }
+
+    #[test]
+    fn test_queued_txs_promotion() {
+        let dir = temp_meta_dir("queued_txs_promotion");
+
+        let alice = H160::repeat_byte(1);
+        let bob = H160::repeat_byte(2);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, U256::exp10(24))],
+        )
+        .unwrap();
+        let genesis = rt.storage.get_latest_block_header().unwrap();
+
+        let txs = (0..3)
+            .map(|n| mock_tx(alice, n, TransactionAction::Call(bob), vec![]))
+            .collect::<Vec<_>>();
+        let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
+        // n + 2 and n + 1 wait for the gap
+        rt.mempool.tx_insert(txs[2].clone(), true).unwrap();
+        assert_eq!((0, 1), rt.mempool.tx_pool_status());
+        rt.mempool.tx_insert(txs[1].clone(), true).unwrap();
+        assert_eq!((0, 2), rt.mempool.tx_pool_status());
+        assert!(rt.mempool.tx_take_propose(10).is_empty());
+        assert!(hashes.iter().all(|h| rt.mempool.tx_is_pooled(h)));
+
+        // n fills it, all of them are pending
+        rt.mempool.tx_insert(txs[0].clone(), true).unwrap();
+        assert_eq!((3, 0), rt.mempool.tx_pool_status());
+        assert_eq!(3, rt.mempool.tx_pending_cnt(Some(alice)));
+        assert_eq!(0, rt.mempool.tx_queued_cnt(Some(alice)));
+
+        // proposed in the order of the nonces
+        let proposed = rt.mempool.tx_take_propose(10);
+        assert_eq!(
+            hashes,
+            proposed
+                .iter()
+                .map(|tx| tx.transaction.hash)
+                .collect::<Vec<_>>()
+        );
+
+        let header = rt
+            .generate_blockproducer(alice, genesis.timestamp + 1)
+            .unwrap()
+            .produce_block(proposed, None)
+            .unwrap();
+        let block = rt.storage.get_block(header.number).unwrap().unwrap();
+        assert_eq!(hashes, block.tx_hashes);
+        assert_eq!(U256::from(3), rt.get_account(alice).unwrap().nonce);
+        assert_eq!((0, 0), rt.mempool.tx_pool_status());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_tx_block_context() {
+        use model::traits::APIAdapter;
+
+        let dir = temp_meta_dir("confirmed_tx_block_context");
+
+        let alice = H160::repeat_byte(1);
+        let bob = H160::repeat_byte(2);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, U256::exp10(24))],
+        )
+        .unwrap();
+        let api = API::new(
+            rt.copy_mempool_handler(),
+            rt.copy_trie_handler(),
+            rt.copy_storage_handler(),
+            rt.hardfork,
+            rt.checkpoint,
+        );
+
+        // two blocks, with 1 and 2 txs
+        let mut txs = vec![];
+        for (n, nonces) in [(1u64, 0..1u64), (2, 1..3)] {
+            let block_txs = nonces
+                .map(|nonce| mock_tx(alice, nonce, TransactionAction::Call(bob), vec![]))
+                .collect::<Vec<_>>();
+            let header = rt
+                .generate_blockproducer(alice, n)
+                .unwrap()
+                .produce_block(block_txs.clone(), None)
+                .unwrap();
+            assert_eq!(n, header.number);
+            txs.extend(
+                block_txs
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, tx)| (header.number, header.hash(), idx as u32, tx)),
+            );
+        }
+
+        for (number, block_hash, idx, tx) in txs {
+            let hash = tx.transaction.hash;
+            let (n, found) = api.get_tx_with_block(hash).await.unwrap().unwrap();
+            assert_eq!(number, n);
+            assert_eq!(tx, found);
+            assert_eq!(
+                Some((number, idx)),
+                api.get_tx_position(hash).await.unwrap()
+            );
+
+            let receipt = api.get_receipt_by_tx_hash(hash).await.unwrap().unwrap();
+            assert_eq!(number, receipt.block_number);
+            assert_eq!(block_hash, receipt.block_hash);
+            assert_eq!(idx, receipt.tx_index);
+        }
+
+        // not confirmed
+        let pending = mock_tx(alice, 3, TransactionAction::Call(bob), vec![]);
+        let hash = pending.transaction.hash;
+        rt.mempool.tx_insert(pending, true).unwrap();
+        assert!(api.get_tx_with_block(hash).await.unwrap().is_none());
+        assert!(api.get_tx_position(hash).await.unwrap().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tx_expiry() {
+        use mempool::EvictionReason;
+        use std::{
+            sync::atomic::{AtomicU64, Ordering},
+            thread,
+            time::{Duration, Instant},
+        };
+
+        // read by the background cleaner too, so it can not be thread local
+        static NOW: AtomicU64 = AtomicU64::new(1_000);
+        fn clock() -> u64 {
+            NOW.load(Ordering::Relaxed)
+        }
+
+        let dir = temp_meta_dir("tx_expiry");
+
+        let alice = H160::repeat_byte(1);
+        let bob = H160::repeat_byte(2);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, U256::exp10(24))],
+        )
+        .unwrap();
+        let mempool = Mempool::new_with_clock(
+            100,
+            10,
+            None,
+            rt.copy_trie_handler(),
+            rt.copy_storage_handler(),
+            clock,
+        );
+
+        // one pending, one queued, both expire at 1010
+        let pending = mock_tx(alice, 0, TransactionAction::Call(bob), vec![]);
+        let queued = mock_tx(alice, 5, TransactionAction::Call(bob), vec![]);
+        let hashes = [pending.transaction.hash, queued.transaction.hash];
+        mempool.tx_insert(pending, true).unwrap();
+        mempool.tx_insert(queued, true).unwrap();
+        assert_eq!((1, 1), mempool.tx_pool_status());
+
+        // not before the end of the lifetime, whatever the cleaner runs
+        NOW.store(1_009, Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(2_500));
+        assert_eq!((1, 1), mempool.tx_pool_status());
+        assert!(hashes.iter().all(|h| mempool.tx_is_pooled(h)));
+        assert!(hashes
+            .iter()
+            .all(|h| mempool.was_recently_evicted(h).is_none()));
+
+        // within one interval of the cleaner after it
+        NOW.store(1_010, Ordering::Relaxed);
+        let start = Instant::now();
+        while mempool.tx_pool_status() != (0, 0) {
+            assert!(start.elapsed() < Duration::from_secs(3));
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(hashes.iter().all(|h| !mempool.tx_is_pooled(h)));
+        assert!(hashes
+            .iter()
+            .all(|h| Some(EvictionReason::Expired) == mempool.was_recently_evicted(h)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_speculative_block() {
+        let dir = temp_meta_dir("speculative_block");
+
+        let alice = H160::repeat_byte(1);
+        let bob = H160::repeat_byte(2);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, U256::exp10(24))],
+        )
+        .unwrap();
+        let genesis = rt.storage.get_latest_block_header().unwrap();
+
+        // deploy the code `[0x00]`:
+        // PUSH1 0, PUSH1 0, MSTORE8, PUSH1 1, PUSH1 0, RETURN
+        let init_code = vec![0x60, 0x00, 0x60, 0x00, 0x53, 0x60, 0x01, 0x60, 0x00, 0xf3];
+        let code_hash = Hasher::digest([0x00u8]);
+
+        rt.mempool
+            .tx_insert(
+                mock_tx(alice, 0, TransactionAction::Call(bob), vec![]),
+                true,
+            )
+            .unwrap();
+        rt.mempool
+            .tx_insert(
+                mock_tx(alice, 1, TransactionAction::Create, init_code),
+                true,
+            )
+            .unwrap();
+        let txs = rt.mempool.tx_take_propose(10);
+        assert_eq!(2, txs.len());
+
+        let producer = rt
+            .generate_blockproducer(alice, genesis.timestamp + 1)
+            .unwrap();
+        let (block, _) = producer.generate_block_speculative(&txs, None).unwrap();
+        assert_eq!(2, block.tx_hashes.len());
+
+        // nothing is changed by the speculative one
+        assert_eq!(2, rt.mempool.tx_pending_cnt(Some(alice)));
+        assert_eq!(txs, rt.mempool.tx_take_propose(10));
+        assert_eq!(genesis, rt.storage.get_latest_block_header().unwrap());
+        assert!(rt.storage.get_code_by_hash(&code_hash).unwrap().is_none());
+        assert_eq!(U256::zero(), rt.get_account(alice).unwrap().nonce);
+
+        // the same block is produced for real
+        let header = producer.produce_block(txs, None).unwrap();
+        assert_eq!(block.header.state_root, header.state_root);
+        assert_eq!(0, rt.mempool.tx_pending_cnt(Some(alice)));
+        assert_eq!(header, rt.storage.get_latest_block_header().unwrap());
+        assert!(rt.storage.get_code_by_hash(&code_hash).unwrap().is_some());
+        assert_eq!(U256::from(2), rt.get_account(alice).unwrap().nonce);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_flush_and_reopen() {
+        let dir = temp_meta_dir("flush_and_reopen");
+
+        let alice = H160::repeat_byte(1);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, 100.into())],
+        )
+        .unwrap();
+
+        // written after the meta data
+        let genesis = rt.storage.get_latest_block_header().unwrap();
+        let header = rt
+            .generate_blockproducer(alice, genesis.timestamp + 1)
+            .unwrap()
+            .produce_block(vec![], None)
+            .unwrap();
+        rt.flush();
+        drop(rt);
+
+        let rt = EvmRuntime::restore_from(&dir).unwrap().unwrap();
+        assert_eq!(header, rt.storage.get_latest_block_header().unwrap());
+        assert_eq!(U256::from(100), rt.get_account(alice).unwrap().balance);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_runtimes_in_different_dirs() {
+        let dir_a = temp_meta_dir("runtimes_in_different_dirs_a");
+        let dir_b = temp_meta_dir("runtimes_in_different_dirs_b");
+
+        let alice = H160::repeat_byte(1);
+        let bob = H160::repeat_byte(2);
+        let rt_a = EvmRuntime::create_in(
+            &dir_a,
+            9527,
+            &[TokenDistributon::new(alice, 100.into())],
+        )
+        .unwrap();
+        let rt_b = EvmRuntime::create_in(
+            &dir_b,
+            9527,
+            &[TokenDistributon::new(bob, 200.into())],
+        )
+        .unwrap();
+        assert_eq!(dir_a.as_path(), rt_a.meta_dir());
+        assert_eq!(dir_b.as_path(), rt_b.meta_dir());
+
+        // the states are not shared
+        assert_eq!(U256::from(100), rt_a.get_account(alice).unwrap().balance);
+        assert_eq!(U256::zero(), rt_a.get_account(bob).unwrap().balance);
+        assert_eq!(U256::from(200), rt_b.get_account(bob).unwrap().balance);
+        assert_eq!(U256::zero(), rt_b.get_account(alice).unwrap().balance);
+
+        // neither are the chains
+        let genesis_a = rt_a.storage.get_latest_block_header().unwrap();
+        let genesis_b = rt_b.storage.get_latest_block_header().unwrap();
+        assert_ne!(genesis_a.hash(), genesis_b.hash());
+        let header = rt_a
+            .generate_blockproducer(alice, genesis_a.timestamp + 1)
+            .unwrap()
+            .produce_block(vec![], None)
+            .unwrap();
+        assert_eq!(1, header.number);
+        assert_eq!(genesis_b, rt_b.storage.get_latest_block_header().unwrap());
+
+        // each one is restored from its own meta data
+        rt_a.flush();
+        drop(rt_a);
+        drop(rt_b);
+
+        let rt_a = EvmRuntime::restore_from(&dir_a).unwrap().unwrap();
+        let rt_b = EvmRuntime::restore_from(&dir_b).unwrap().unwrap();
+        assert_eq!(header, rt_a.storage.get_latest_block_header().unwrap());
+        assert_eq!(genesis_b, rt_b.storage.get_latest_block_header().unwrap());
+        assert_eq!(U256::from(100), rt_a.get_account(alice).unwrap().balance);
+        assert_eq!(U256::from(200), rt_b.get_account(bob).unwrap().balance);
+        assert_eq!(U256::zero(), rt_b.get_account(alice).unwrap().balance);
+
+        fs::remove_dir_all(&dir_a).unwrap();
+        fs::remove_dir_all(&dir_b).unwrap();
+    }
+
+    // cargo test --release -- --ignored --nocapture bench_verify_sequential_blocks
+    #[test]
+    #[ignore]
+    fn bench_verify_sequential_blocks() {
+        use std::time::Instant;
+
+        const N: u64 = 1000;
+
+        let dir = temp_meta_dir("bench_verify_sequential_blocks");
+
+        let alice = H160::repeat_byte(1);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, 100.into())],
+        )
+        .unwrap();
+        for _ in 0..N {
+            let latest = rt.storage.get_latest_block_header().unwrap();
+            rt.generate_blockproducer(alice, latest.timestamp + 1)
+                .unwrap()
+                .produce_block(vec![], None)
+                .unwrap();
+        }
+        let fbs = (1..=N)
+            .map(|n| rt.storage.get_fatblock(n).unwrap().unwrap())
+            .collect::<Vec<_>>();
+        rt.flush();
+        drop(rt);
+
+        // the caches are empty after a restart,
+        // so every parent header is looked up in the db once
+        let rt = EvmRuntime::restore_from(&dir).unwrap().unwrap();
+        let verifier = rt.generate_blockproducer(alice, N + 1).unwrap();
+
+        let verify_all = || {
+            let now = Instant::now();
+            fbs.iter().for_each(|fb| verifier.verify_block(fb).unwrap());
+            now.elapsed()
+        };
+        let cold = verify_all();
+        let warm = verify_all();
+        println!(
+            "verify {} sequential blocks: {:?} with cold caches, {:?} with warm caches",
+            N, cold, warm
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // cargo test --release -- --ignored --nocapture bench_touch_many_accounts
+    #[test]
+    #[ignore]
+    fn bench_touch_many_accounts() {
+        use std::time::Instant;
+
+        // the txs of a block, each one calls the same `N` accounts
+        const TXS: u64 = 5;
+        const N: u16 = 1000;
+
+        // for i in (1..=N).rev() { CALL(gas(), 0x10000 + i, 0, 0, 0, 0, 0) },
+        // every CALL checks whether the target exists
+        let [n_hi, n_lo] = N.to_be_bytes();
+        let init_code = vec![
+            0x61, n_hi, n_lo, // PUSH2 N
+            0x5b, // JUMPDEST
+            // PUSH1 0 (x5), the value and the memory ranges
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00,
+            0x85, // DUP6
+            0x62, 0x01, 0x00, 0x00, // PUSH3 0x10000
+            0x01, // ADD
+            0x5a, // GAS
+            0xf1, // CALL
+            0x50, // POP
+            0x60, 0x01, 0x90, 0x03, // i - 1
+            0x80, 0x60, 0x03, 0x57, // JUMPI(3, i)
+            0x00, // STOP
+        ];
+
+        let dir = temp_meta_dir("bench_touch_many_accounts");
+
+        let alice = H160::repeat_byte(1);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, U256::exp10(24))],
+        )
+        .unwrap();
+
+        let txs = (0..TXS)
+            .map(|nonce| {
+                let mut tx =
+                    mock_tx(alice, nonce, TransactionAction::Create, init_code.clone());
+                if let UnsignedTransaction::Legacy(ref mut utx) = tx.transaction.unsigned
+                {
+                    utx.gas_limit = 5_000_000.into();
+                }
+                tx.transaction.hash = tx.transaction.get_hash();
+                tx
+            })
+            .collect::<Vec<_>>();
+
+        let now = Instant::now();
+        let header = rt
+            .generate_blockproducer(alice, 1)
+            .unwrap()
+            .produce_block(txs, None)
+            .unwrap();
+        let elapsed = now.elapsed();
+
+        let receipts = rt.storage.get_block_receipts(1).unwrap().unwrap();
+        assert_eq!(TXS as usize, receipts.len());
+        assert!(receipts.iter().all(|r| r.status() == U256::one()));
+        println!(
+            "{} txs touching the same {} accounts: {:?}, {} gas",
+            TXS, N, elapsed, header.gas_used
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unauthorized_proposer() {
+        let dir = temp_meta_dir("unauthorized_proposer");
+
+        let alice = H160::repeat_byte(1);
+        let bob = H160::repeat_byte(2);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, 100.into())],
+        )
+        .unwrap();
+        let genesis = rt.storage.get_latest_block_header().unwrap();
+        let only_alice = Some([alice].into_iter().collect());
+
+        let mut producer = rt
+            .generate_blockproducer(bob, genesis.timestamp + 1)
+            .unwrap();
+        producer.authorized_proposers = only_alice.clone();
+        assert!(producer.produce_block(vec![], None).is_err());
+
+        // produced by a node without the validation
+        producer.authorized_proposers = None;
+        let header = producer.produce_block(vec![], None).unwrap();
+        assert_eq!(bob, header.proposer);
+        let fb = rt.storage.get_fatblock(header.number).unwrap().unwrap();
+
+        let mut verifier = rt
+            .generate_blockproducer(alice, header.timestamp + 1)
+            .unwrap();
+        assert!(verifier.verify_block(&fb).is_ok());
+
+        verifier.authorized_proposers = only_alice;
+        assert!(verifier.verify_block(&fb).is_err());
+
+        verifier.authorized_proposers = Some([alice, bob].into_iter().collect());
+        assert!(verifier.verify_block(&fb).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_config_per_runtime() {
+        use model::types::{NIL_HASH, RLP_NULL};
+
+        let alice = H160::repeat_byte(1);
+        let create = |name, checkpoint| {
+            let dir = temp_meta_dir(name);
+            let rt = EvmRuntime::create_in_with_checkpoint(
+                &dir,
+                9527,
+                DEFAULT_GENESIS_TIMESTAMP,
+                checkpoint,
+                &[TokenDistributon::new(alice, 100.into())],
+            )
+            .unwrap();
+            (dir, rt)
+        };
+
+        // two runtimes in one process, no global state is shared
+        let (dir_a, rt_a) = create(
+            "checkpoint_config_a",
+            CheckPointConfig {
+                empty_root_height: Some(0),
+                ..Default::default()
+            },
+        );
+        let (dir_b, rt_b) = create("checkpoint_config_b", CheckPointConfig::default());
+
+        for (rt, root) in [(&rt_a, RLP_NULL), (&rt_b, NIL_HASH)] {
+            let genesis = rt.storage.get_latest_block_header().unwrap();
+            assert_eq!(root, genesis.transactions_root);
+            assert_eq!(root, genesis.receipts_root);
+
+            let producer = rt
+                .generate_blockproducer(alice, genesis.timestamp + 1)
+                .unwrap();
+            assert_eq!(rt.checkpoint, producer.checkpoint);
+            let header = producer.produce_block(vec![], None).unwrap();
+            assert_eq!(root, header.transactions_root);
+            assert_eq!(root, header.receipts_root);
+
+            let fb = rt.storage.get_fatblock(header.number).unwrap().unwrap();
+            assert!(producer.verify_block(&fb).is_ok());
+        }
+
+        // a block of one chain is rejected by the other one
+        let fb = rt_a.storage.get_fatblock(1).unwrap().unwrap();
+        let verifier = rt_b.generate_blockproducer(alice, u64::MAX).unwrap();
+        assert!(verifier.verify_block(&fb).is_err());
+
+        fs::remove_dir_all(&dir_a).unwrap();
+        fs::remove_dir_all(&dir_b).unwrap();
+    }
+
+    #[test]
+    fn test_batch_limit() {
+        let dir = temp_meta_dir("batch_limit");
+
+        let alice = H160::repeat_byte(1);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, 100.into())],
+        )
+        .unwrap();
+
+        rt.set_batch_limit(2);
+        assert_eq!(2, rt.storage_handler().batch_limit());
+
+        let hashes = [
+            Hash::repeat_byte(1),
+            Hash::repeat_byte(2),
+            Hash::repeat_byte(3),
+        ];
+        assert_eq!(2, rt.storage.get_receipts(0, &hashes[..2]).unwrap().len());
+        assert!(rt.storage.get_receipts(0, &hashes).is_err());
+        assert!(rt.storage.get_txs(0, &hashes).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_block_difficulty_and_mixed_hash() {
+        use api::jsonrpc::web3_types::Web3Block;
+
+        let dir = temp_meta_dir("block_difficulty_and_mixed_hash");
+
+        let alice = H160::repeat_byte(1);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, 100.into())],
+        )
+        .unwrap();
+        let genesis = rt.storage.get_latest_block_header().unwrap();
+
+        // the default ones
+        let producer = rt
+            .generate_blockproducer(alice, genesis.timestamp + 1)
+            .unwrap();
+        assert_eq!(U256::one(), producer.difficulty);
+        assert!(!producer.fill_mixed_hash);
+
+        let mut producer = rt
+            .generate_blockproducer(alice, genesis.timestamp + 1)
+            .unwrap();
+        producer.difficulty = 7.into();
+        producer.fill_mixed_hash = true;
+        let header1 = producer.produce_block(vec![], None).unwrap();
+        assert_eq!(U256::from(7), header1.difficulty);
+        assert_eq!(Some(genesis.hash()), header1.mixed_hash);
+
+        // inherited from the previous block
+        let mut producer = rt
+            .generate_blockproducer(alice, header1.timestamp + 1)
+            .unwrap();
+        assert_eq!(U256::from(7), producer.difficulty);
+        producer.fill_mixed_hash = true;
+
+        // the difficulty is fixed along the chain
+        producer.difficulty = 8.into();
+        assert!(producer.produce_block(vec![], None).is_err());
+        producer.difficulty = 7.into();
+        let header2 = producer.produce_block(vec![], None).unwrap();
+        assert_eq!(U256::from(7), header2.difficulty);
+        assert_eq!(Some(header1.hash()), header2.mixed_hash);
+
+        let block2 = rt.storage.get_block(2).unwrap().unwrap();
+        assert_eq!(header2, block2.header);
+        let web3_block = Web3Block::from(block2);
+        assert_eq!(Some(U256::from(14)), web3_block.total_difficulty);
+
+        // a block with another difficulty is rejected by the verifiers
+        let verifier = rt
+            .generate_blockproducer(alice, header2.timestamp + 1)
+            .unwrap();
+        let mut fb = rt.storage.get_fatblock(2).unwrap().unwrap();
+        assert!(verifier.verify_block(&fb).is_ok());
+        fb.block.header.difficulty = 8.into();
+        assert!(verifier.verify_block(&fb).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_block_stream() {
+        let dir = temp_meta_dir("block_stream");
+
+        let alice = H160::repeat_byte(1);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, 100.into())],
+        )
+        .unwrap();
+        let genesis = rt.storage.get_latest_block().unwrap();
+
+        let mut blocks = rt.block_stream(0);
+        assert_eq!(
+            BlockStreamItem::Block(genesis.clone()),
+            blocks.next().await.unwrap().unwrap()
+        );
+
+        // produced while the stream is waiting
+        let (mempool, trie, storage) = (
+            rt.copy_mempool_handler(),
+            rt.copy_trie_handler(),
+            rt.copy_storage_handler(),
+        );
+        let producer = std::thread::spawn(move || {
+            (1..=5)
+                .map(|i| {
+                    BlockMgmt::new(
+                        alice,
+                        genesis.header.timestamp + i,
+                        Arc::clone(&mempool),
+                        Arc::clone(&trie),
+                        Arc::clone(&storage),
+                    )
+                    .unwrap()
+                    .produce_block(vec![], None)
+                    .unwrap()
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let mut yielded = vec![];
+        while yielded.len() < 5 {
+            match blocks.next().await.unwrap().unwrap() {
+                BlockStreamItem::Block(b) => yielded.push(b.header),
+                item => panic!("unexpected: {:?}", item),
+            }
+        }
+        assert_eq!(producer.join().unwrap(), yielded);
+
+        // a new stream catches up from the storage
+        let mut blocks = rt.block_stream(3);
+        for header in &yielded[2..] {
+            match blocks.next().await.unwrap().unwrap() {
+                BlockStreamItem::Block(b) => assert_eq!(header, &b.header),
+                item => panic!("unexpected: {:?}", item),
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stop_jsonrpc_server() {
+        use tokio::net::TcpStream;
+
+        let dir = temp_meta_dir("stop_jsonrpc_server");
+        let rt = EvmRuntime::create_in(&dir, 9527, &[]).unwrap();
+
+        let config = Default::default();
+
+        // the ports are picked by the servers themselves
+        let handlers = rt
+            .spawn_jsonrpc_server(
+                "test",
+                Some("127.0.0.1:0"),
+                Some("127.0.0.1:0"),
+                None,
+                &config,
+            )
+            .await
+            .unwrap();
+        assert!(handlers.http.is_some() && handlers.ws.is_some());
+        let addrs = [handlers.http_addr.unwrap(), handlers.ws_addr.unwrap()]
+            .map(|addr| addr.to_string());
+        for addr in addrs.iter() {
+            assert!(TcpStream::connect(addr.as_str()).await.is_ok());
+        }
+
+        api::stop_jsonrpc_server(handlers).await.unwrap();
+        for addr in addrs.iter() {
+            assert!(TcpStream::connect(addr.as_str()).await.is_err());
+        }
+
+        // the runtime stays usable, and the addresses have been released
+        assert_eq!(0, rt.storage.get_latest_block_header().unwrap().number);
+        let handlers = rt
+            .spawn_jsonrpc_server(
+                "test",
+                Some(&addrs[0]),
+                Some(&addrs[1]),
+                None,
+                &config,
+            )
+            .await
+            .unwrap();
+        for addr in addrs.iter() {
+            assert!(TcpStream::connect(addr.as_str()).await.is_ok());
+        }
+        api::stop_jsonrpc_server(handlers).await.unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_selfdestruct_releases_storage() {
+        use model::{traits::Backend as _, types::NIL_HASH};
+
+        // SSTORE(0, 42), then deploy `CALLER SELFDESTRUCT`
+        const INIT_CODE: [u8; 16] = [
+            0x60, 0x2a, 0x60, 0x00, 0x55, 0x61, 0x33, 0xff, 0x60, 0x00, 0x52, 0x60,
+            0x02, 0x60, 0x1e, 0xf3,
+        ];
+
+        let dir = temp_meta_dir("selfdestruct_releases_storage");
+
+        let alice = H160::repeat_byte(1);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, U256::exp10(24))],
+        )
+        .unwrap();
+        let contract = H160::from(executor::code_address(alice, &U256::zero()));
+
+        let produce = |n: u64, tx: SignedTx| {
+            rt.generate_blockproducer(alice, n)
+                .unwrap()
+                .produce_block(vec![tx], None)
+                .unwrap()
+        };
+        let slot_at = |header: &Header| {
+            RTEvmExecutorAdapter::from_root(
+                header.state_root,
+                &rt.trie_db,
+                &rt.storage,
+                Default::default(),
+            )
+            .unwrap()
+            .storage(contract, H256::zero())
+        };
+
+        let deployed = produce(
+            1,
+            mock_tx(alice, 0, TransactionAction::Create, INIT_CODE.to_vec()),
+        );
+        let account_at = |n| {
+            get_account_by_backend(&rt.trie_db, &rt.storage, contract, Some(n)).unwrap()
+        };
+
+        let storage_root = account_at(1).storage_root;
+        assert_ne!(NIL_HASH, storage_root);
+        assert_eq!(H256::from_low_u64_be(42), slot_at(&deployed));
+        assert_eq!(0, rt.storage.released_storage_trie_cnt());
+
+        let destructed = produce(
+            2,
+            mock_tx(alice, 1, TransactionAction::Call(contract), vec![]),
+        );
+        assert_eq!(H256::zero(), slot_at(&destructed));
+        assert_eq!(NIL_HASH, account_at(2).storage_root);
+
+        // released only once, after the block is persisted
+        assert_eq!(1, rt.storage.released_storage_trie_cnt());
+
+        // the block before the destruction can still read the storage
+        assert_eq!(H256::from_low_u64_be(42), slot_at(&deployed));
+        let trie = trie_restore(&rt.trie_db, contract.as_bytes(), storage_root).unwrap();
+        assert!(trie.contains(H256::zero().as_bytes()).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_strict_block_hash_per_runtime() {
+        // `BLOCKHASH(9)` in the init code
+        const INIT_CODE: [u8; 5] = [0x60, 0x09, 0x40, 0x50, 0x00];
+
+        let alice = H160::repeat_byte(1);
+        let create = |name| {
+            let dir = temp_meta_dir(name);
+            let rt = EvmRuntime::create_in(
+                &dir,
+                9527,
+                &[TokenDistributon::new(alice, U256::exp10(24))],
+            )
+            .unwrap();
+            (dir, rt)
+        };
+
+        let (dir_a, mut rt_a) = create("strict_block_hash_a");
+        let (dir_b, rt_b) = create("strict_block_hash_b");
+        rt_a.set_strict_block_hash(true);
+
+        // two runtimes in one process, no global state is shared
+        for (rt, strict) in [(&rt_a, true), (&rt_b, false)] {
+            let mut producer = rt.generate_blockproducer(alice, 1).unwrap();
+            assert_eq!(strict, producer.strict_block_hash);
+
+            // as if the blocks before the 10th one had been pruned
+            producer.block_number = 10;
+            let tx = mock_tx(alice, 0, TransactionAction::Create, INIT_CODE.to_vec());
+            let e = producer.produce_block(vec![tx], None).unwrap_err();
+
+            // no panic in either mode, only the strict one rejects the miss itself
+            assert_eq!(strict, e.to_string().contains("unavailable to BLOCKHASH"));
+            assert_eq!(0, rt.storage.get_latest_block_number().unwrap());
+        }
+
+        fs::remove_dir_all(&dir_a).unwrap();
+        fs::remove_dir_all(&dir_b).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tls_eth_chain_id() {
+        use std::net::SocketAddr;
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpStream,
+        };
+        use tokio_rustls::{
+            rustls::{Certificate, ClientConfig, RootCertStore, ServerName},
+            TlsConnector,
+        };
+
+        async fn eth_chain_id(connector: &TlsConnector, addr: SocketAddr) -> String {
+            let tcp = TcpStream::connect(addr).await.unwrap();
+            let mut stream = connector
+                .connect(ServerName::try_from("localhost").unwrap(), tcp)
+                .await
+                .unwrap();
+
+            let body = r#"{"jsonrpc":"2.0","id":1,"method":"eth_chainId","params":[]}"#;
+            let req = format!(
+                "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(req.as_bytes()).await.unwrap();
+
+            // the peer may close without a `close_notify`, the response is what matters
+            let mut resp = vec![];
+            let _ = stream.read_to_end(&mut resp).await;
+            String::from_utf8_lossy(&resp).into_owned()
+        }
+
+        let dir = temp_meta_dir("tls_eth_chain_id");
+
+        let cert =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+        let tls =
+            TlsConfig::new(cert_path.to_string_lossy(), key_path.to_string_lossy());
+
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(&Certificate(cert.serialize_der().unwrap()))
+            .unwrap();
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(config));
+
+        let rt = EvmRuntime::create_in(&dir, 9527, &[]).unwrap();
+        let spawn = |addr: String| {
+            let tls = tls.clone();
+            let rt = &rt;
+            async move {
+                rt.spawn_jsonrpc_server(
+                    "test",
+                    Some(&addr),
+                    None,
+                    Some(&tls),
+                    &Default::default(),
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        let handlers = spawn("127.0.0.1:0".to_owned()).await;
+        let addr = handlers.http_addr.unwrap();
+
+        // a probe without a handshake only affects its own connection
+        drop(TcpStream::connect(addr).await.unwrap());
+
+        for _ in 0..2 {
+            let resp = eth_chain_id(&connector, addr).await;
+            assert!(resp.starts_with("HTTP/1.1 200"));
+            assert!(resp.contains(r#""result":"0x2537""#));
+        }
+
+        // the TLS listener is closed, and its address can be served again
+        api::stop_jsonrpc_server(handlers).await.unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
+
+        let handlers = spawn(addr.to_string()).await;
+        assert_eq!(Some(addr), handlers.http_addr);
+        assert!(eth_chain_id(&connector, addr)
+            .await
+            .contains(r#""result":"0x2537""#));
+        api::stop_jsonrpc_server(handlers).await.unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_call_at_tx_index() {
+        use model::traits::APIAdapter;
+
+        // deploy `PUSH1 1 PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN`
+        const INIT_CODE: [u8; 19] = [
+            0x69, 0x60, 0x01, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3, 0x60,
+            0x00, 0x52, 0x60, 0x0a, 0x60, 0x16, 0xf3,
+        ];
+
+        // whether each contract has code in the state right before the tx
+        async fn deployed(
+            api: &API,
+            contracts: &[H160],
+            tx_index: usize,
+        ) -> Result<Vec<bool>> {
+            let mut ret = vec![];
+            for c in contracts {
+                let resp = api
+                    .evm_call_at_tx_index(
+                        None,
+                        Some(*c),
+                        None,
+                        None,
+                        0.into(),
+                        vec![],
+                        1,
+                        tx_index,
+                    )
+                    .await
+                    .c(d!())?;
+                ret.push(!resp.ret.is_empty());
+            }
+            Ok(ret)
+        }
+
+        let dir = temp_meta_dir("call_at_tx_index");
+
+        let alice = H160::repeat_byte(1);
+        let mut rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, U256::exp10(24))],
+        )
+        .unwrap();
+        rt.set_hardfork(Hardfork::Cancun);
+
+        // two contracts deployed by the tx 0 and the tx 1 of the block 1
+        let contracts = [0u64, 1]
+            .map(|nonce| H160::from(executor::code_address(alice, &U256::from(nonce))));
+        let txs = (0..2)
+            .map(|nonce| {
+                mock_tx(alice, nonce, TransactionAction::Create, INIT_CODE.to_vec())
+            })
+            .collect();
+        let producer = rt.generate_blockproducer(alice, 1).unwrap();
+        assert_eq!(Hardfork::Cancun, producer.hardfork);
+        producer.produce_block(txs, None).unwrap();
+
+        let api = API::new(
+            rt.copy_mempool_handler(),
+            rt.copy_trie_handler(),
+            rt.copy_storage_handler(),
+            rt.hardfork,
+            rt.checkpoint,
+        );
+
+        assert_eq!(
+            vec![false, false],
+            deployed(&api, &contracts, 0).await.unwrap()
+        );
+        // the tx 0 has been applied, the tx 1 has not
+        assert_eq!(
+            vec![true, false],
+            deployed(&api, &contracts, 1).await.unwrap()
+        );
+        assert_eq!(
+            vec![true, true],
+            deployed(&api, &contracts, 2).await.unwrap()
+        );
+        assert!(deployed(&api, &contracts, 3).await.is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_call_many_with_state_overrides() {
+        use model::{
+            traits::APIAdapter,
+            types::{CallRequest, StateOverride},
+        };
+        use std::collections::BTreeMap;
+
+        // SSTORE(0, SLOAD(0) + 1), then return the new value
+        const COUNTER: [u8; 18] = [
+            0x60, 0x00, 0x54, 0x60, 0x01, 0x01, 0x80, 0x60, 0x00, 0x55, 0x60, 0x00,
+            0x52, 0x60, 0x20, 0x60, 0x00, 0xf3,
+        ];
+
+        let dir = temp_meta_dir("call_many_with_state_overrides");
+
+        let alice = H160::repeat_byte(1);
+        let rt = EvmRuntime::create_in(
+            &dir,
+            9527,
+            &[TokenDistributon::new(alice, U256::exp10(24))],
+        )
+        .unwrap();
+        let api = API::new(
+            rt.copy_mempool_handler(),
+            rt.copy_trie_handler(),
+            rt.copy_storage_handler(),
+            rt.hardfork,
+            rt.checkpoint,
+        );
+
+        // `counter` only exists in the overrides, so does the balance of `bob`
+        let (bob, counter) = (H160::repeat_byte(2), H160::repeat_byte(0xcc));
+        let overrides = || {
+            let mut o = BTreeMap::new();
+            o.insert(
+                counter,
+                StateOverride {
+                    code: Some(COUNTER.to_vec()),
+                    state_diff: [(H256::zero(), H256::from_low_u64_be(41))].into(),
+                    ..Default::default()
+                },
+            );
+            o.insert(
+                bob,
+                StateOverride {
+                    balance: Some(U256::exp10(24)),
+                    ..Default::default()
+                },
+            );
+            o
+        };
+        let calls = |value: U256| {
+            (0..3)
+                .map(|_| CallRequest {
+                    from: Some(bob),
+                    to: Some(counter),
+                    gas_price: None,
+                    gas_limit: None,
+                    value,
+                    data: vec![],
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // each call sees the writes of the previous ones
+        let resps = api
+            .evm_call_many(calls(U256::exp10(18)), overrides(), None)
+            .await
+            .unwrap();
+        for (resp, n) in resps.iter().zip(42u64..) {
+            assert!(resp.exit_reason.is_succeed());
+            assert_eq!(H256::from_low_u64_be(n).as_bytes(), &resp.ret[..]);
+        }
+
+        // nothing is persisted, the next batch starts over
+        let resps = api
+            .evm_call_many(calls(U256::exp10(18)), overrides(), None)
+            .await
+            .unwrap();
+        assert_eq!(H256::from_low_u64_be(42).as_bytes(), &resps[0].ret[..]);
+
+        let resps = api
+            .evm_call_many(calls(U256::zero()), BTreeMap::new(), None)
+            .await
+            .unwrap();
+        assert!(resps.iter().all(|r| r.ret.is_empty()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tx_state_diff() {
+        use model::traits::APIAdapter;
+
+        // SSTORE(0, 42), then deploy `CALLER SELFDESTRUCT`
+        const INIT_CODE: [u8; 16] = [
+            0x60, 0x2a, 0x60, 0x00, 0x55, 0x61, 0x33, 0xff, 0x60, 0x00, 0x52, 0x60,
+            0x02, 0x60, 0x1e, 0xf3,
+        ];
+
+        let alice = H160::repeat_byte(1);
+        let contract = H160::from(executor::code_address(alice, &U256::zero()));
+
+        // the contract is deleted by the call only before EIP-6780
+        for (name, hardfork) in [
+            ("tx_state_diff_london", Hardfork::London),
+            ("tx_state_diff_cancun", Hardfork::Cancun),
+        ] {
+            let dir = temp_meta_dir(name);
+            let mut rt = EvmRuntime::create_in(
+                &dir,
+                9527,
+                &[TokenDistributon::new(alice, U256::exp10(24))],
+            )
+            .unwrap();
+            rt.set_hardfork(hardfork);
+
+            // deployed by the tx 0 and called by the tx 1 of the same block
+            let txs = vec![
+                mock_tx(alice, 0, TransactionAction::Create, INIT_CODE.to_vec()),
+                mock_tx(alice, 1, TransactionAction::Call(contract), vec![]),
+            ];
+            let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+            rt.generate_blockproducer(alice, 1)
+                .unwrap()
+                .produce_block(txs, None)
+                .unwrap();
+
+            let api = API::new(
+                rt.copy_mempool_handler(),
+                rt.copy_trie_handler(),
+                rt.copy_storage_handler(),
+                rt.hardfork,
+                rt.checkpoint,
+            );
+
+            let deploy = api.get_tx_state_diff(hashes[0]).await.unwrap().unwrap();
+            assert_eq!(Some(U256::zero()), deploy.pre[&alice].nonce);
+            assert_eq!(Some(U256::one()), deploy.post[&alice].nonce);
+            assert_eq!(
+                Some(&H256::from_low_u64_be(42)),
+                deploy.post[&contract].storage.get(&H256::zero())
+            );
+            assert_eq!(Some(vec![0x33, 0xff]), deploy.post[&contract].code);
+
+            // replayed on top of the tx 0
+            let call = api.get_tx_state_diff(hashes[1]).await.unwrap().unwrap();
+            assert_eq!(Some(U256::one()), call.pre[&alice].nonce);
+            assert_eq!(Some(U256::from(2)), call.post[&alice].nonce);
+            if hardfork.is_eip6780_enabled() {
+                assert!(!call.pre.contains_key(&contract));
+                assert!(!call.post.contains_key(&contract));
+            } else {
+                assert_eq!(Some(vec![0x33, 0xff]), call.pre[&contract].code);
+                assert_eq!(Some(vec![]), call.post[&contract].code);
+            }
+
+            // not confirmed
+            assert!(api
+                .get_tx_state_diff(Hash::repeat_byte(9))
+                .await
+                .unwrap()
+                .is_none());
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}